@@ -1,18 +1,36 @@
+use crate::archive::{self, ArchiveEntry, ArchiveIndex};
 use crate::config::{Config, SortBy};
-use crate::file_ops::{self, OpKind, PendingOp, SearchResult};
-use crate::git_status::{self, GitFileStatus};
-use crate::preview::{self, PreviewLine};
+use crate::file_ops::{self, ArchiveKind, Codec, OpKind, PendingOp, SearchOptions, SearchResult};
+use crate::git_status::{self, CommitInfo, GitFileStatus, GitStatusCache, IgnoreRules, RepoInfo};
+use crate::jobs::{self, JobKind, JobManager, JobOutcome, Progress};
+use crate::keymap::{Action, KeyLookup, KeyPress, Keymap};
+use crate::preview::{self, PreviewEngine, PreviewLine, PreviewSlot, PreviewStyle};
 use crate::theme::{Theme, ThemeName};
 use crate::undo::{self, UndoStack};
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
 use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Most commits the git log view lists
+const GIT_LOG_LIMIT: usize = 500;
+
+/// Extracted archives and revisions kept in the cache between sessions
+const SCRATCH_KEEP: usize = 64;
+
+/// Older scratch copies are removed at startup
+const SCRATCH_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How often to check repositories for commits, checkouts and the like
+const GIT_REVALIDATE_INTERVAL: Duration = Duration::from_secs(1);
 
 #[derive(Debug, Clone)]
 pub struct FileEntry {
@@ -38,6 +56,125 @@ pub enum InputMode {
     Chmod,
     Search,
     SearchResults,
+    PasteConflict,
+    Jobs,
+    /// Naming an archive and picking its format
+    Compress,
+    /// Picking where to extract an archive
+    Extract,
+    /// Asking before throwing away worktree changes
+    ConfirmDiscard,
+    /// Browsing the commits that touched a path
+    GitLog,
+}
+
+/// What the preview shows for files git tracks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GitPreview {
+    #[default]
+    Contents,
+    /// Staged and unstaged changes, for changed files
+    Diff,
+    /// Each line annotated with its last commit and author
+    Blame,
+}
+
+/// How to resolve a paste whose destination already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictChoice {
+    Overwrite,
+    Skip,
+    /// Paste under a free name such as `name (1).ext`
+    Rename,
+}
+
+/// Size and mtime of one side of a paste collision
+#[derive(Debug, Clone, Copy)]
+pub struct FileStat {
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub is_dir: bool,
+}
+
+impl FileStat {
+    fn of(path: &Path) -> Option<Self> {
+        let meta = fs::symlink_metadata(path).ok()?;
+        Some(Self {
+            size: meta.len(),
+            modified: meta.modified().ok(),
+            is_dir: meta.is_dir(),
+        })
+    }
+}
+
+/// A collision the user is being asked about
+#[derive(Debug, Clone)]
+pub struct PasteConflict {
+    pub src: PathBuf,
+    pub dest: PathBuf,
+    pub src_stat: Option<FileStat>,
+    pub dest_stat: Option<FileStat>,
+}
+
+/// One planned copy/move, executed after every collision is resolved
+#[derive(Debug, Clone)]
+pub struct PasteItem {
+    pub src: PathBuf,
+    pub dest: PathBuf,
+    /// `dest` exists and will be stashed for undo before being replaced
+    pub overwrite: bool,
+}
+
+/// A paste in progress, paused while the user resolves name collisions
+#[derive(Debug, Clone)]
+pub struct PasteState {
+    pub op: PendingOp,
+    pub dest_dir: PathBuf,
+    /// Sources not yet checked for collisions
+    pub queue: VecDeque<PathBuf>,
+    pub plan: Vec<PasteItem>,
+    pub conflict: Option<PasteConflict>,
+    /// Remember the next choice for all remaining collisions
+    pub apply_all: bool,
+    pub remembered: Option<ConflictChoice>,
+    /// Show the size/mtime comparison for the current collision
+    pub show_compare: bool,
+    pub skipped: usize,
+}
+
+impl PasteState {
+    /// First free path for `name`, passing over destinations already
+    /// planned for other items of this paste
+    fn free_dest(&self, name: &str) -> PathBuf {
+        file_ops::unique_dest_except(&self.dest_dir, name, |p| self.is_planned(p))
+    }
+
+    fn is_planned(&self, dest: &Path) -> bool {
+        self.plan.iter().any(|item| item.dest == dest)
+    }
+
+    fn resolve(&mut self, src: PathBuf, dest: PathBuf, choice: ConflictChoice) {
+        match choice {
+            ConflictChoice::Overwrite => self.plan.push(PasteItem {
+                src,
+                dest,
+                overwrite: true,
+            }),
+            ConflictChoice::Skip => self.skipped += 1,
+            ConflictChoice::Rename => {
+                let name = dest
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let dest = self.free_dest(&name);
+                self.plan.push(PasteItem {
+                    src,
+                    dest,
+                    overwrite: false,
+                });
+            }
+        }
+    }
 }
 
 /// A node in the tree view
@@ -49,8 +186,34 @@ pub struct TreeNode {
     pub has_children: bool,
 }
 
+/// An archive index being built in the background
+#[derive(Debug)]
+struct PendingArchive {
+    path: PathBuf,
+    rx: Receiver<Result<ArchiveIndex, String>>,
+}
+
+/// A file's git history being read in the background
+#[derive(Debug)]
+struct PendingLog {
+    path: PathBuf,
+    rx: Receiver<Result<Vec<CommitInfo>, String>>,
+}
+
+/// Whether `dir` is the directory `src` or lies below it, so a copy of
+/// `src` into it would never end
+fn is_inside(dir: &Path, src: &Path) -> bool {
+    if !src.symlink_metadata().is_ok_and(|m| m.is_dir()) {
+        return false;
+    }
+    match (fs::canonicalize(dir), fs::canonicalize(src)) {
+        (Ok(dir), Ok(src)) => dir.starts_with(src),
+        _ => false,
+    }
+}
+
 /// Per-tab state
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Tab {
     pub current_dir: PathBuf,
     pub entries: Vec<FileEntry>,
@@ -63,11 +226,40 @@ pub struct Tab {
     pub sort_by: SortBy,
     pub selected: HashSet<PathBuf>,
     pub git_statuses: HashMap<String, GitFileStatus>,
+    /// Branch and state of the repository holding `current_dir`
+    pub repo_info: Option<RepoInfo>,
     pub filter_text: String,
     pub tree_mode: bool,
     pub tree_nodes: Vec<TreeNode>,
     pub tree_cursor: usize,
     pub tree_expanded: HashSet<PathBuf>,
+    /// Syntect theme used to highlight the preview
+    pub syntax_theme: String,
+    /// Preview renderer and cache, shared with the other tabs
+    pub preview_engine: Arc<PreviewEngine>,
+    /// Where background renders for this tab's cursor arrive
+    pub preview_slot: Arc<PreviewSlot>,
+    /// The cursor moved to an entry whose preview hasn't arrived yet
+    pub preview_loading: bool,
+    /// Set while browsing inside an archive; `current_dir` is then a
+    /// virtual path such as `/src/lib.zip/util`
+    pub archive: Option<Arc<ArchiveIndex>>,
+    /// The archive being indexed on a worker thread before `archive` is set
+    archive_loading: Option<PendingArchive>,
+    /// Preview tracked files as their diff or blame rather than contents
+    pub git_preview: GitPreview,
+    /// Git status of every repository, shared with the other tabs
+    pub git_cache: Arc<GitStatusCache>,
+    /// Cache generation `git_statuses` was last taken from
+    git_generation: u64,
+    /// The repository's status is still being scanned
+    pub git_pending: bool,
+    /// Leave out entries ignored by `.gitignore` or `.ignore`
+    pub hide_ignored: bool,
+    /// Ignore files that apply to `current_dir`
+    ignore_rules: IgnoreRules,
+    /// Where entries of archives being browsed are extracted
+    cache_dir: PathBuf,
 }
 
 impl Tab {
@@ -75,6 +267,10 @@ impl Tab {
         dir: PathBuf,
         show_hidden: bool,
         sort_by: SortBy,
+        syntax_theme: String,
+        preview_engine: Arc<PreviewEngine>,
+        git_cache: Arc<GitStatusCache>,
+        cache_dir: PathBuf,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let mut tab = Self {
             current_dir: dir,
@@ -88,27 +284,44 @@ impl Tab {
             sort_by,
             selected: HashSet::new(),
             git_statuses: HashMap::new(),
+            repo_info: None,
             filter_text: String::new(),
             tree_mode: false,
             tree_nodes: Vec::new(),
             tree_cursor: 0,
             tree_expanded: HashSet::new(),
+            syntax_theme,
+            preview_engine,
+            preview_slot: Arc::new(PreviewSlot::default()),
+            preview_loading: false,
+            archive: None,
+            archive_loading: None,
+            git_preview: GitPreview::default(),
+            git_cache,
+            git_generation: 0,
+            git_pending: false,
+            hide_ignored: false,
+            ignore_rules: IgnoreRules::default(),
+            cache_dir,
         };
         tab.refresh()?;
         Ok(tab)
     }
 
     pub fn refresh(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        self.entries = read_dir(&self.current_dir, self.show_hidden)?;
+        self.sync_archive();
+        self.entries = self.list_dir(&self.current_dir)?;
         self.sort_entries();
-        self.git_statuses = git_status::get_git_statuses(&self.current_dir);
-        for entry in &mut self.entries {
-            entry.git_status = self.git_statuses.get(&entry.name).copied();
-        }
+        self.ignore_rules = if self.in_archive() {
+            IgnoreRules::default()
+        } else {
+            IgnoreRules::for_dir(&self.current_dir)
+        };
+        self.load_git_status(true);
         self.apply_filter();
 
         if let Some(parent) = self.current_dir.parent() {
-            self.parent_entries = read_dir(parent, self.show_hidden).unwrap_or_default();
+            self.parent_entries = self.list_dir(parent).unwrap_or_default();
             self.parent_entries.sort_by(|a, b| {
                 b.is_dir
                     .cmp(&a.is_dir)
@@ -128,6 +341,103 @@ impl Tab {
         Ok(())
     }
 
+    /// Keep `archive` in step with `current_dir`: index the archive in the
+    /// background when the directory lies inside one, and drop the index
+    /// once it doesn't or the archive changed on disk
+    fn sync_archive(&mut self) {
+        if let Some(index) = &self.archive {
+            if index.inner_path(&self.current_dir).is_some() && !index.is_stale() {
+                return;
+            }
+            self.archive = None;
+        }
+        let archive_path = if archive::is_browsable(&self.current_dir) {
+            Some(self.current_dir.clone())
+        } else if !self.current_dir.exists() {
+            archive::split_virtual(&self.current_dir).map(|(archive, _)| archive)
+        } else {
+            None
+        };
+        let Some(path) = archive_path else {
+            self.archive_loading = None;
+            return;
+        };
+        if self
+            .archive_loading
+            .as_ref()
+            .is_some_and(|p| p.path == path)
+        {
+            return;
+        }
+        let (tx, rx) = mpsc::channel();
+        let worker_path = path.clone();
+        thread::spawn(move || {
+            let _ = tx.send(ArchiveIndex::open(&worker_path));
+        });
+        self.archive_loading = Some(PendingArchive { path, rx });
+    }
+
+    /// Browsing inside an archive, or waiting for one to be indexed
+    pub fn in_archive(&self) -> bool {
+        self.archive.is_some() || self.archive_loading.is_some()
+    }
+
+    /// Pick up an archive indexed in the background and list it. When it
+    /// can't be read, go back to the directory holding it and return why.
+    pub fn poll_archive(&mut self) -> Option<String> {
+        let pending = self.archive_loading.as_ref()?;
+        let result = match pending.rx.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => Err("Indexing stopped".to_string()),
+        };
+        let path = self.archive_loading.take()?.path;
+        match result {
+            Ok(index) => {
+                self.archive = Some(Arc::new(index));
+                if let Err(e) = self.refresh() {
+                    return Some(e.to_string());
+                }
+                None
+            }
+            Err(e) => {
+                if let Some(parent) = path.parent() {
+                    self.current_dir = parent.to_path_buf();
+                    self.cursor = 0;
+                    let _ = self.refresh();
+                    if let Some(pos) = self.visible_entries().iter().position(|e| e.path == path) {
+                        self.cursor = pos;
+                        self.update_preview();
+                    }
+                }
+                Some(e)
+            }
+        }
+    }
+
+    /// Entries of `dir`, from the archive index when `dir` is inside the
+    /// archive being browsed
+    fn list_dir(&self, dir: &Path) -> Result<Vec<FileEntry>, Box<dyn std::error::Error>> {
+        let Some((index, inner)) = self
+            .archive
+            .as_ref()
+            .and_then(|index| Some((index, index.inner_path(dir)?)))
+        else {
+            if let Some(pending) = &self.archive_loading {
+                if dir.starts_with(&pending.path) {
+                    // Listed once the index arrives
+                    return Ok(Vec::new());
+                }
+            }
+            return read_dir(dir, self.show_hidden);
+        };
+        Ok(index
+            .children(inner)
+            .map(|e| archive_file_entry(&index.path, e))
+            .filter(|e| self.show_hidden || !e.name.starts_with('.'))
+            .collect())
+    }
+
     fn sort_entries(&mut self) {
         let sort_by = self.sort_by;
         self.entries.sort_by(|a, b| {
@@ -157,6 +467,8 @@ impl Tab {
     }
 
     pub fn apply_filter(&mut self) {
+        let hide_ignored = self.hide_ignored;
+        let shown = |e: &FileEntry| !hide_ignored || e.git_status != Some(GitFileStatus::Ignored);
         if !self.filter_text.is_empty() {
             let matcher = SkimMatcherV2::default();
             let query = &self.filter_text;
@@ -164,11 +476,17 @@ impl Tab {
                 .entries
                 .iter()
                 .enumerate()
-                .filter(|(_, e)| matcher.fuzzy_match(&e.name, query).is_some())
+                .filter(|(_, e)| shown(e) && matcher.fuzzy_match(&e.name, query).is_some())
                 .map(|(i, _)| i)
                 .collect();
         } else {
-            self.filtered_entries = (0..self.entries.len()).collect();
+            self.filtered_entries = self
+                .entries
+                .iter()
+                .enumerate()
+                .filter(|(_, e)| shown(e))
+                .map(|(i, _)| i)
+                .collect();
         }
         if self.cursor >= self.filtered_entries.len() {
             self.cursor = self.filtered_entries.len().saturating_sub(1);
@@ -188,11 +506,249 @@ impl Tab {
             .and_then(|&i| self.entries.get(i))
     }
 
+    /// Take git statuses from the shared cache, never waiting for a scan.
+    /// With `check_files`, a listed entry modified since the last scan
+    /// starts a new one.
+    fn load_git_status(&mut self, check_files: bool) {
+        self.git_generation = self.git_cache.generation();
+        let workdir = if self.in_archive() {
+            None
+        } else {
+            git_status::workdir_of(&self.current_dir)
+        };
+        let snapshot = workdir.as_ref().and_then(|workdir| {
+            let newest = if check_files {
+                self.newest_modified()
+            } else {
+                None
+            };
+            self.git_cache.get(workdir, newest)
+        });
+        self.git_pending = workdir
+            .as_ref()
+            .is_some_and(|w| self.git_cache.is_scanning(w));
+        self.git_statuses = match (&snapshot, &workdir) {
+            (Some(snapshot), Some(workdir)) => self
+                .current_dir
+                .strip_prefix(workdir)
+                .map(|rel| snapshot.children(rel))
+                .unwrap_or_default(),
+            _ => HashMap::new(),
+        };
+        self.repo_info = snapshot.and_then(|s| s.info.clone());
+        for entry in &mut self.entries {
+            // Ignore files cover what git hasn't reported yet, and `.ignore`
+            // which git doesn't read; tracked changes still show as such
+            entry.git_status = match self.git_statuses.get(&entry.name).copied() {
+                None | Some(GitFileStatus::Untracked)
+                    if self.ignore_rules.is_ignored(&entry.path, entry.is_dir) =>
+                {
+                    Some(GitFileStatus::Ignored)
+                }
+                status => status,
+            };
+        }
+    }
+
+    /// Latest modification of the directory or anything listed in it.
+    /// Times in the future are left out: clamped to now they would still
+    /// be newer than every scan. The worktree watcher covers those files.
+    fn newest_modified(&self) -> Option<SystemTime> {
+        let now = SystemTime::now();
+        let own = fs::metadata(&self.current_dir)
+            .and_then(|m| m.modified())
+            .ok();
+        self.entries
+            .iter()
+            .filter_map(|e| e.modified)
+            .chain(own)
+            .filter(|&t| t <= now)
+            .max()
+    }
+
+    /// Pick up git statuses from a scan finished since the last look;
+    /// true if there were any
+    pub fn poll_git_status(&mut self) -> bool {
+        if self.git_cache.generation() == self.git_generation {
+            return false;
+        }
+        self.load_git_status(false);
+        if self.hide_ignored && !self.tree_mode {
+            let current = self.selected_entry().map(|e| e.path.clone());
+            self.apply_filter();
+            if let Some(pos) =
+                current.and_then(|p| self.visible_entries().iter().position(|e| e.path == p))
+            {
+                self.cursor = pos;
+            }
+        }
+        // Diff and blame previews depend on the status
+        if self.git_preview != GitPreview::Contents {
+            if self.tree_mode {
+                self.update_preview_for_tree();
+            } else {
+                self.update_preview();
+            }
+        }
+        true
+    }
+
     pub fn update_preview(&mut self) {
-        if let Some(entry) = self.selected_entry() {
-            self.preview_lines = preview::preview_path(&entry.path);
+        let path = self.selected_entry().map(|e| e.path.clone());
+        self.show_preview(path);
+    }
+
+    /// Show the preview for `path`: at once when cached, otherwise render
+    /// it in the background and show a placeholder until it arrives
+    fn show_preview(&mut self, path: Option<PathBuf>) {
+        let Some(path) = path else {
+            self.preview_slot.next_generation();
+            self.preview_lines.clear();
+            self.preview_loading = false;
+            return;
+        };
+        if let Some(index) = self.archive.clone() {
+            if let Some(inner) = index.inner_path(&path) {
+                let inner = inner.to_path_buf();
+                self.show_archive_preview(index, inner);
+                return;
+            }
+        }
+        if self.git_preview == GitPreview::Diff && self.has_diff(&path) {
+            self.preview_engine.request_diff(&self.preview_slot, path);
+            self.preview_lines.clear();
+            self.preview_loading = true;
+        } else if self.git_preview == GitPreview::Blame && self.is_tracked(&path) {
+            self.preview_engine
+                .request_blame(&self.preview_slot, path, None);
+            self.preview_lines.clear();
+            self.preview_loading = true;
+        } else if let Some(lines) = self.preview_engine.cached(&path, &self.syntax_theme) {
+            // Supersede any render still in flight for an earlier entry
+            self.preview_slot.next_generation();
+            self.preview_lines = lines;
+            self.preview_loading = false;
         } else {
+            self.preview_engine
+                .request(&self.preview_slot, path, self.syntax_theme.clone());
             self.preview_lines.clear();
+            self.preview_loading = true;
+        }
+    }
+
+    /// Whether `path` is a listed file with changes git can diff
+    fn has_diff(&self, path: &Path) -> bool {
+        self.entries.iter().any(|e| {
+            e.path == path
+                && !e.is_dir
+                && matches!(
+                    e.git_status,
+                    Some(
+                        GitFileStatus::Modified
+                            | GitFileStatus::Staged
+                            | GitFileStatus::StagedModified
+                            | GitFileStatus::Renamed
+                    )
+                )
+        })
+    }
+
+    /// Whether `path` is a listed file in a repository that git tracks
+    fn is_tracked(&self, path: &Path) -> bool {
+        self.repo_info.is_some()
+            && self.entries.iter().any(|e| {
+                e.path == path
+                    && !e.is_dir
+                    && !matches!(
+                        e.git_status,
+                        Some(GitFileStatus::Untracked | GitFileStatus::Ignored)
+                    )
+            })
+    }
+
+    /// Preview an entry of the browsed archive: directories are listed from
+    /// the index, files are extracted to a scratch copy in the background
+    fn show_archive_preview(&mut self, index: Arc<ArchiveIndex>, inner: PathBuf) {
+        let entry = index.entry(&inner).cloned();
+        let is_dir = inner.as_os_str().is_empty() || entry.as_ref().is_some_and(|e| e.is_dir);
+        if is_dir {
+            self.preview_slot.next_generation();
+            let names = index
+                .children(&inner)
+                .map(|c| (archive_file_entry(&index.path, c).name, c.is_dir))
+                .collect();
+            self.preview_lines = preview::preview_listing(
+                format!("📁 Directory: {}", index.path.join(&inner).display()),
+                names,
+                self.preview_engine.max_lines(),
+            );
+            self.preview_loading = false;
+        } else if let Some(big) = entry.filter(|e| e.size > archive::PREVIEW_EXTRACT_LIMIT) {
+            self.preview_slot.next_generation();
+            self.preview_lines = vec![
+                PreviewLine::new(
+                    format!("📦 {}", index.path.join(&inner).display()),
+                    PreviewStyle::Header,
+                ),
+                PreviewLine::new(
+                    format!(
+                        "{} bytes — too large to preview inside the archive",
+                        big.size
+                    ),
+                    PreviewStyle::Normal,
+                ),
+            ];
+            self.preview_loading = false;
+        } else {
+            let scratch = index.scratch_path(&self.cache_dir, &inner);
+            if scratch.exists() {
+                self.show_preview(Some(scratch));
+            } else {
+                let cache_dir = self.cache_dir.clone();
+                self.preview_engine.request_prepared(
+                    &self.preview_slot,
+                    self.syntax_theme.clone(),
+                    move || index.materialize(&cache_dir, &inner),
+                );
+                self.preview_lines.clear();
+                self.preview_loading = true;
+            }
+        }
+    }
+
+    /// Pick up a preview rendered in the background; true if one arrived
+    pub fn poll_preview(&mut self) -> bool {
+        match self.preview_slot.take() {
+            Some(lines) => {
+                self.preview_lines = lines;
+                self.preview_loading = false;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Switch the highlight theme and re-render the current preview
+    pub fn set_syntax_theme(&mut self, syntax_theme: String) {
+        self.syntax_theme = syntax_theme;
+        if self.tree_mode {
+            self.update_preview_for_tree();
+        } else {
+            self.update_preview();
+        }
+    }
+
+    /// Switch the preview to `mode`, or back to contents if already there
+    pub fn toggle_git_preview(&mut self, mode: GitPreview) {
+        self.git_preview = if self.git_preview == mode {
+            GitPreview::Contents
+        } else {
+            mode
+        };
+        if self.tree_mode {
+            self.update_preview_for_tree();
+        } else {
+            self.update_preview();
         }
     }
 
@@ -226,6 +782,10 @@ impl Tab {
 
     fn build_tree_recursive(&mut self, dir: &Path, depth: usize) {
         let mut entries = read_dir(dir, self.show_hidden).unwrap_or_default();
+        if self.hide_ignored {
+            let rules = IgnoreRules::for_dir(dir);
+            entries.retain(|e| !rules.is_ignored(&e.path, e.is_dir));
+        }
         entries.sort_by(|a, b| {
             b.is_dir
                 .cmp(&a.is_dir)
@@ -270,11 +830,8 @@ impl Tab {
     }
 
     fn update_preview_for_tree(&mut self) {
-        if let Some(entry) = self.selected_tree_entry() {
-            self.preview_lines = preview::preview_path(&entry.path);
-        } else {
-            self.preview_lines.clear();
-        }
+        let path = self.selected_tree_entry().map(|e| e.path.clone());
+        self.show_preview(path);
     }
 
     pub fn tab_title(&self) -> String {
@@ -291,20 +848,32 @@ pub struct App {
     #[allow(dead_code)]
     pub config: Config,
     pub pending_op: Option<PendingOp>,
+    /// Paste waiting on conflict resolution
+    pub paste_state: Option<PasteState>,
     pub input_mode: InputMode,
     pub input_buffer: String,
     pub status_message: Option<String>,
     pub bookmarks: HashMap<char, PathBuf>,
-    pub pending_g: bool,
-    pub pending_d: bool,
-    pub pending_y: bool,
-    pub pending_p: bool,
+    /// Key bindings resolved from defaults and `[keybinds]`
+    pub keymap: Keymap,
+    /// Keys typed so far of a multi-key sequence such as `gg`
+    pub pending_keys: Vec<KeyPress>,
     /// Layout areas for mouse hit-testing (set during draw)
     pub mouse_areas: MouseAreas,
     /// Recursive search results
     pub search_results: Vec<SearchResult>,
     /// Cursor position in search results
     pub search_cursor: usize,
+    /// Regex, case and whole-word choices of the search prompt
+    pub search_options: SearchOptions,
+    /// Globs limiting the files searched, space-separated
+    pub search_globs: String,
+    /// The search prompt is editing `search_globs` rather than the pattern
+    pub search_globs_focus: bool,
+    /// The running content search, cancelled when a new one starts
+    search_job: Option<u64>,
+    /// Renders previews for every tab; loads syntect assets once
+    pub preview_engine: Arc<PreviewEngine>,
     /// Current theme
     pub theme_name: ThemeName,
     pub theme: Theme,
@@ -316,16 +885,108 @@ pub struct App {
     pub dual_tab: Option<Tab>,
     /// Which pane is active in dual mode: false=left (main tab), true=right (dual_tab)
     pub dual_right_active: bool,
-    /// Archive compression format choice
-    #[allow(dead_code)]
+    /// Format picked in the compress prompt
     pub compress_mode: Option<CompressFormat>,
+    /// Archive the extract prompt is for
+    pub extract_prompt: Option<ExtractPrompt>,
+    /// Paths whose worktree changes wait for confirmation to be discarded
+    pub pending_discard: Vec<PathBuf>,
+    /// Path whose history the git log view shows
+    pub git_log_path: Option<PathBuf>,
+    /// Commits that touched `git_log_path`, newest first
+    pub git_log: Vec<CommitInfo>,
+    /// Cursor position in the git log view
+    pub git_log_cursor: usize,
+    /// Whether the git log view previews blame rather than contents,
+    /// kept apart from the tab's own git preview mode
+    git_log_blame: bool,
+    /// History being read for the git log view
+    git_log_loading: Option<PendingLog>,
+    /// When the repositories were last checked for outside changes
+    git_checked: Instant,
+    /// Copies, moves, deletes and archive work running in the background
+    pub jobs: JobManager,
+    /// Cursor position in the job list
+    pub job_cursor: usize,
+    /// Set after a quit was refused because jobs are running
+    quit_armed: bool,
+    /// Where scratch copies of archive entries and revisions go
+    cache_dir: PathBuf,
+}
+
+/// An archive waiting for its destination in the extract prompt
+#[derive(Debug, Clone)]
+pub struct ExtractPrompt {
+    pub archive: PathBuf,
+    /// Name of the folder made for the entries by default; `None` for a
+    /// single compressed file, which extracts to just one file
+    pub subfolder: Option<String>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[allow(dead_code)]
 pub enum CompressFormat {
     Zip,
     TarGz,
+    TarXz,
+    TarBz2,
+    TarZst,
+    Tar,
+    /// One file compressed on its own, as `notes.txt.xz`. Only picked by
+    /// typing the extension.
+    Single(Codec),
+}
+
+impl CompressFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Zip => "zip",
+            Self::TarGz => "tar.gz",
+            Self::TarXz => "tar.xz",
+            Self::TarBz2 => "tar.bz2",
+            Self::TarZst => "tar.zst",
+            Self::Tar => "tar",
+            Self::Single(codec) => codec.extensions().first().copied().unwrap_or_default(),
+        }
+    }
+
+    /// Next format in the prompt's Tab cycle
+    pub fn next(self) -> Self {
+        match self {
+            Self::Zip => Self::TarGz,
+            Self::TarGz => Self::TarXz,
+            Self::TarXz => Self::TarBz2,
+            Self::TarBz2 => Self::TarZst,
+            Self::TarZst => Self::Tar,
+            Self::Tar | Self::Single(_) => Self::Zip,
+        }
+    }
+
+    /// Compression around the tar stream, or `None` for zip and single files
+    pub fn tar_codec(self) -> Option<Codec> {
+        match self {
+            Self::Zip | Self::Single(_) => None,
+            Self::TarGz => Some(Codec::Gzip),
+            Self::TarXz => Some(Codec::Xz),
+            Self::TarBz2 => Some(Codec::Bzip2),
+            Self::TarZst => Some(Codec::Zstd),
+            Self::Tar => Some(Codec::None),
+        }
+    }
+
+    /// Format implied by an archive file name, if it has a known extension
+    pub fn from_name(name: &str) -> Option<Self> {
+        match file_ops::archive_kind_from_name(Path::new(name))? {
+            ArchiveKind::Zip => Some(Self::Zip),
+            ArchiveKind::Tar(codec) => Some(match codec {
+                Codec::None => Self::Tar,
+                Codec::Gzip => Self::TarGz,
+                Codec::Xz => Self::TarXz,
+                Codec::Bzip2 => Self::TarBz2,
+                Codec::Zstd => Self::TarZst,
+            }),
+            ArchiveKind::Single(codec) => Some(Self::Single(codec)),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -338,42 +999,101 @@ pub struct MouseAreas {
 impl App {
     pub fn new(config: Config) -> Result<Self, Box<dyn std::error::Error>> {
         let current_dir = std::env::current_dir()?;
-        Self::with_dir(config, current_dir)
+        let app = Self::with_dir(config, current_dir, Config::cache_dir())?;
+        app.undo_stack.prune_backups();
+        app.prune_scratch();
+        Ok(app)
     }
 
+    /// The app opened in `current_dir`, keeping scratch copies in `cache_dir`
     pub fn with_dir(
         config: Config,
         current_dir: PathBuf,
+        cache_dir: PathBuf,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let show_hidden = config.show_hidden;
         let sort_by = config.sort_by;
-        let tab = Tab::new(current_dir, show_hidden, sort_by)?;
+        let syntax_theme = config
+            .syntax_theme
+            .clone()
+            .unwrap_or_else(|| config.theme.syntax_theme().to_string());
+        let preview_engine = Arc::new(PreviewEngine::new(config.preview.clone()));
+        let tab = Tab::new(
+            current_dir,
+            show_hidden,
+            sort_by,
+            syntax_theme,
+            Arc::clone(&preview_engine),
+            Arc::new(GitStatusCache::default()),
+            cache_dir.clone(),
+        )?;
+        let (keymap, keymap_errors) = Keymap::with_overrides(&config.keybinds);
         Ok(Self {
             tabs: vec![tab],
             active_tab: 0,
             config: config.clone(),
             pending_op: None,
+            paste_state: None,
             input_mode: InputMode::Normal,
             input_buffer: String::new(),
-            status_message: None,
+            status_message: match keymap_errors.as_slice() {
+                [] => None,
+                [error] => Some(format!("Keybind error: {error}")),
+                errors => Some(format!(
+                    "{} keybind errors: {}",
+                    errors.len(),
+                    errors.join("; ")
+                )),
+            },
             bookmarks: HashMap::new(),
-            pending_g: false,
-            pending_d: false,
-            pending_y: false,
-            pending_p: false,
+            keymap,
+            pending_keys: Vec::new(),
             mouse_areas: MouseAreas::default(),
             search_results: Vec::new(),
             search_cursor: 0,
+            search_options: SearchOptions::default(),
+            search_globs: String::new(),
+            search_globs_focus: false,
+            search_job: None,
+            preview_engine,
             theme_name: config.theme,
             theme: Theme::from_name(config.theme),
-            undo_stack: UndoStack::new(),
+            undo_stack: UndoStack::new(cache_dir.join(undo::BACKUP_DIR)),
             dual_pane: false,
             dual_tab: None,
             dual_right_active: false,
             compress_mode: None,
+            extract_prompt: None,
+            pending_discard: Vec::new(),
+            git_log_path: None,
+            git_log: Vec::new(),
+            git_log_cursor: 0,
+            git_log_blame: false,
+            git_log_loading: None,
+            git_checked: Instant::now(),
+            jobs: JobManager::default(),
+            job_cursor: 0,
+            quit_armed: false,
+            cache_dir,
         })
     }
 
+    /// Trim extracted archive entries and old revisions left by earlier
+    /// sessions
+    fn prune_scratch(&self) {
+        for dir in [archive::SCRATCH_DIR, git_status::REVISIONS_DIR] {
+            file_ops::prune_cache(&self.cache_dir.join(dir), SCRATCH_KEEP, SCRATCH_MAX_AGE);
+        }
+    }
+
+    /// Syntect theme for previews: the configured one, else the palette's match
+    fn syntax_theme(&self) -> String {
+        self.config
+            .syntax_theme
+            .clone()
+            .unwrap_or_else(|| self.theme_name.syntax_theme().to_string())
+    }
+
     /// Access the active tab (respects dual-pane focus)
     pub fn tab(&self) -> &Tab {
         if self.dual_pane && self.dual_right_active {
@@ -415,7 +1135,15 @@ impl App {
             let dir = self.tabs[self.active_tab].current_dir.clone();
             let show_hidden = self.tabs[self.active_tab].show_hidden;
             let sort_by = self.tabs[self.active_tab].sort_by;
-            self.dual_tab = Some(Tab::new(dir, show_hidden, sort_by)?);
+            self.dual_tab = Some(Tab::new(
+                dir,
+                show_hidden,
+                sort_by,
+                self.syntax_theme(),
+                Arc::clone(&self.preview_engine),
+                Arc::clone(&self.tab().git_cache),
+                self.cache_dir.clone(),
+            )?);
         }
         if !self.dual_pane {
             self.dual_right_active = false;
@@ -465,6 +1193,10 @@ impl App {
         &self.tab().preview_lines
     }
 
+    pub fn preview_loading(&self) -> bool {
+        self.tab().preview_loading
+    }
+
     pub fn selected(&self) -> &HashSet<PathBuf> {
         &self.tab().selected
     }
@@ -474,6 +1206,10 @@ impl App {
         &self.tab().git_statuses
     }
 
+    pub fn repo_info(&self) -> Option<&RepoInfo> {
+        self.tab().repo_info.as_ref()
+    }
+
     pub fn file_count(&self) -> usize {
         self.tab().file_count()
     }
@@ -505,13 +1241,39 @@ impl App {
         let dir = self.tab().current_dir.clone();
         let show_hidden = self.tab().show_hidden;
         let sort_by = self.tab().sort_by;
-        let tab = Tab::new(dir, show_hidden, sort_by)?;
+        let mut tab = Tab::new(
+            dir,
+            show_hidden,
+            sort_by,
+            self.syntax_theme(),
+            Arc::clone(&self.preview_engine),
+            Arc::clone(&self.tab().git_cache),
+            self.cache_dir.clone(),
+        )?;
+        if self.tab().hide_ignored {
+            tab.hide_ignored = true;
+            tab.apply_filter();
+        }
         self.active_tab += 1;
         self.tabs.insert(self.active_tab, tab);
         Ok(())
     }
 
     /// Close the current tab. Returns true if the app should quit (last tab closed).
+    /// Whether to quit now. With jobs running, the first attempt only
+    /// warns, so a move isn't cut off between its copy and delete.
+    fn try_quit(&mut self) -> bool {
+        let running = self.jobs.running_count();
+        if running == 0 || self.quit_armed {
+            return true;
+        }
+        self.quit_armed = true;
+        self.status_message = Some(format!(
+            "{running} job(s) still running — quit again to abandon them"
+        ));
+        false
+    }
+
     pub fn close_tab(&mut self) -> bool {
         if self.tabs.len() <= 1 {
             return true; // quit
@@ -541,63 +1303,7 @@ impl App {
 
     pub fn handle_key(&mut self, key: KeyEvent) -> Result<bool, Box<dyn std::error::Error>> {
         if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
-            return Ok(true);
-        }
-
-        // Tab keybinds (work in normal mode)
-        if self.input_mode == InputMode::Normal && key.modifiers.contains(KeyModifiers::CONTROL) {
-            match key.code {
-                KeyCode::Char('y') => {
-                    let entry = if self.tab().tree_mode {
-                        self.tab().selected_tree_entry().cloned()
-                    } else {
-                        self.tab().selected_entry().cloned()
-                    };
-                    if let Some(entry) = entry {
-                        match file_ops::copy_content_to_clipboard(&entry.path) {
-                            Ok(_) => {
-                                self.status_message =
-                                    Some(format!("Content copied: {}", entry.name))
-                            }
-                            Err(e) => self.status_message = Some(format!("Error: {e}")),
-                        }
-                    }
-                    return Ok(false);
-                }
-                KeyCode::Char('t') => {
-                    self.new_tab()?;
-                    self.status_message = Some(format!("Tab {} opened", self.active_tab + 1));
-                    return Ok(false);
-                }
-                KeyCode::Char('w') => {
-                    if self.close_tab() {
-                        return Ok(true);
-                    }
-                    self.status_message =
-                        Some(format!("Tab closed ({} remaining)", self.tabs.len()));
-                    return Ok(false);
-                }
-                KeyCode::Right => {
-                    self.next_tab();
-                    return Ok(false);
-                }
-                KeyCode::Left => {
-                    self.prev_tab();
-                    return Ok(false);
-                }
-                _ => {}
-            }
-        }
-
-        // Alt+1..9 to switch tabs
-        if self.input_mode == InputMode::Normal && key.modifiers.contains(KeyModifiers::ALT) {
-            if let KeyCode::Char(c @ '1'..='9') = key.code {
-                let idx = (c as usize) - ('1' as usize);
-                if idx < self.tabs.len() {
-                    self.active_tab = idx;
-                }
-                return Ok(false);
-            }
+            return Ok(self.try_quit());
         }
 
         match self.input_mode {
@@ -611,6 +1317,29 @@ impl App {
             InputMode::Chmod => self.handle_chmod_key(key),
             InputMode::Search => self.handle_search_key(key),
             InputMode::SearchResults => self.handle_search_results_key(key),
+            InputMode::PasteConflict => self.handle_paste_conflict_key(key),
+            InputMode::Jobs => self.handle_jobs_key(key),
+            InputMode::GitLog => self.handle_git_log_key(key),
+            InputMode::Compress => self.handle_compress_key(key),
+            InputMode::Extract => self.handle_extract_key(key),
+            InputMode::ConfirmDiscard => self.handle_confirm_discard_key(key),
+        }
+    }
+
+    /// Feed a key into the pending sequence and return the action it
+    /// completes, if any. Unbound sequences are discarded.
+    fn resolve_key(&mut self, key: KeyEvent) -> Option<Action> {
+        self.pending_keys.push(KeyPress::from(key));
+        match self.keymap.lookup(&self.pending_keys) {
+            KeyLookup::Matched(action) => {
+                self.pending_keys.clear();
+                Some(action)
+            }
+            KeyLookup::Pending => None,
+            KeyLookup::Unbound => {
+                self.pending_keys.clear();
+                None
+            }
         }
     }
 
@@ -671,19 +1400,73 @@ impl App {
         Ok(false)
     }
 
-    fn handle_tree_common_key(
-        &mut self,
-        key: KeyEvent,
-    ) -> Result<bool, Box<dyn std::error::Error>> {
-        // Handle keys that work the same in tree mode as normal (t, Y, sort, etc.)
-        match key.code {
-            KeyCode::Char('t') => {
+    fn handle_normal_key(&mut self, key: KeyEvent) -> Result<bool, Box<dyn std::error::Error>> {
+        self.status_message = None;
+        match self.resolve_key(key) {
+            Some(action) => {
+                if !matches!(action, Action::Quit | Action::CloseTab) {
+                    self.quit_armed = false;
+                }
+                self.run_action(action)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// The entry under the cursor, in either list or tree view
+    fn cursor_entry(&self) -> Option<FileEntry> {
+        if self.tab().tree_mode {
+            self.tab().selected_tree_entry().cloned()
+        } else {
+            self.tab().selected_entry().cloned()
+        }
+    }
+
+    /// Actions that behave the same in list and tree view
+    fn run_common_action(&mut self, action: Action) -> Result<bool, Box<dyn std::error::Error>> {
+        match action {
+            Action::Quit => return Ok(self.try_quit()),
+            Action::ToggleGitDiff | Action::ToggleGitBlame => {
+                let (mode, name) = if action == Action::ToggleGitDiff {
+                    (GitPreview::Diff, "diff")
+                } else {
+                    (GitPreview::Blame, "blame")
+                };
+                self.tab_mut().toggle_git_preview(mode);
+                let state = if self.tab().git_preview == mode {
+                    "ON"
+                } else {
+                    "OFF"
+                };
+                self.status_message = Some(format!("Git {name} preview {state}"));
+            }
+            Action::ToggleIgnored => {
+                let tab = self.tab_mut();
+                tab.hide_ignored = !tab.hide_ignored;
+                if tab.tree_mode {
+                    tab.rebuild_tree();
+                } else {
+                    tab.refresh()?;
+                }
+                let state = if tab.hide_ignored { "hidden" } else { "shown" };
+                self.status_message = Some(format!("Ignored files {state}"));
+            }
+            Action::GitLog => {
+                if let Some(entry) = self.cursor_entry() {
+                    self.open_git_log(entry.path);
+                }
+            }
+            Action::ShowJobs => {
+                self.job_cursor = self.jobs.jobs.len().saturating_sub(1);
+                self.input_mode = InputMode::Jobs;
+            }
+            Action::ToggleTree => {
                 self.tab_mut().toggle_tree_mode();
                 let mode = if self.tab().tree_mode { "Tree" } else { "List" };
                 self.status_message = Some(format!("View: {mode}"));
             }
-            KeyCode::Char('Y') => {
-                if let Some(entry) = self.tab().selected_tree_entry().cloned() {
+            Action::CopyPath => {
+                if let Some(entry) = self.cursor_entry() {
                     match file_ops::copy_path_to_clipboard(&entry.path) {
                         Ok(_) => {
                             self.status_message =
@@ -693,156 +1476,156 @@ impl App {
                     }
                 }
             }
-            KeyCode::Char('.') => {
-                let new_hidden = !self.tab().show_hidden;
-                self.tab_mut().show_hidden = new_hidden;
-                self.tab_mut().rebuild_tree();
+            Action::CopyContent => {
+                if let Some(entry) = self.cursor_entry() {
+                    match file_ops::copy_content_to_clipboard(&entry.path) {
+                        Ok(_) => {
+                            self.status_message = Some(format!("Content copied: {}", entry.name))
+                        }
+                        Err(e) => self.status_message = Some(format!("Error: {e}")),
+                    }
+                }
             }
-            _ => {}
-        }
-        Ok(false)
-    }
-
-    fn handle_normal_key(&mut self, key: KeyEvent) -> Result<bool, Box<dyn std::error::Error>> {
-        self.status_message = None;
-
-        if self.pending_g {
-            self.pending_g = false;
-            if key.code == KeyCode::Char('g') {
-                if self.tab().tree_mode {
-                    self.tab_mut().tree_cursor = 0;
-                    self.tab_mut().update_preview_for_tree();
-                } else {
-                    self.tab_mut().cursor = 0;
-                    self.tab_mut().update_preview();
-                }
-            }
-            return Ok(false);
-        }
-        if self.pending_d {
-            self.pending_d = false;
-            if key.code == KeyCode::Char('d') {
-                self.delete_selected()?;
+            Action::NewTab => {
+                self.new_tab()?;
+                self.status_message = Some(format!("Tab {} opened", self.active_tab + 1));
             }
-            return Ok(false);
-        }
-        if self.pending_y {
-            self.pending_y = false;
-            if key.code == KeyCode::Char('y') {
-                self.yank_selected();
+            Action::CloseTab => {
+                if self.close_tab() {
+                    return Ok(self.try_quit());
+                }
+                self.status_message = Some(format!("Tab closed ({} remaining)", self.tabs.len()));
             }
-            return Ok(false);
-        }
-        if self.pending_p {
-            self.pending_p = false;
-            if key.code == KeyCode::Char('p') {
-                self.paste()?;
+            Action::NextTab => self.next_tab(),
+            Action::PrevTab => self.prev_tab(),
+            Action::GotoTab(n) => {
+                let idx = usize::from(n).saturating_sub(1);
+                if idx < self.tabs.len() {
+                    self.active_tab = idx;
+                }
             }
-            return Ok(false);
+            _ => {}
         }
+        Ok(false)
+    }
 
-        // Tree mode navigation
-        if self.tab().tree_mode {
-            match key.code {
-                KeyCode::Char('q') => return Ok(true),
-                KeyCode::Char('j') | KeyCode::Down => {
-                    let tab = self.tab_mut();
-                    if tab.tree_cursor < tab.tree_nodes.len().saturating_sub(1) {
-                        tab.tree_cursor += 1;
-                        tab.update_preview_for_tree();
-                    }
+    fn run_tree_action(&mut self, action: Action) -> Result<bool, Box<dyn std::error::Error>> {
+        match action {
+            Action::MoveDown => {
+                let tab = self.tab_mut();
+                if tab.tree_cursor < tab.tree_nodes.len().saturating_sub(1) {
+                    tab.tree_cursor += 1;
+                    tab.update_preview_for_tree();
                 }
-                KeyCode::Char('k') | KeyCode::Up => {
-                    let tab = self.tab_mut();
-                    if tab.tree_cursor > 0 {
-                        tab.tree_cursor -= 1;
-                        tab.update_preview_for_tree();
-                    }
+            }
+            Action::MoveUp => {
+                let tab = self.tab_mut();
+                if tab.tree_cursor > 0 {
+                    tab.tree_cursor -= 1;
+                    tab.update_preview_for_tree();
                 }
-                KeyCode::Char('l') | KeyCode::Right | KeyCode::Enter => {
-                    if let Some(entry) = self.tab().selected_tree_entry().cloned() {
-                        if entry.is_dir {
-                            self.tab_mut().tree_toggle_expand();
-                        } else {
-                            let _ = open::that(&entry.path);
-                        }
+            }
+            Action::Open => {
+                if let Some(entry) = self.tab().selected_tree_entry().cloned() {
+                    if entry.is_dir {
+                        self.tab_mut().tree_toggle_expand();
+                    } else {
+                        let _ = open::that(&entry.path);
                     }
                 }
-                KeyCode::Char('h') | KeyCode::Left => {
-                    // Collapse current dir or go to parent node
-                    if let Some(node) = self.tab().tree_nodes.get(self.tab().tree_cursor).cloned() {
-                        if node.entry.is_dir && node.expanded {
-                            self.tab_mut().tree_toggle_expand();
-                        } else if node.depth > 0 {
-                            // Find parent node
-                            let tab = self.tab_mut();
-                            for i in (0..tab.tree_cursor).rev() {
-                                if tab.tree_nodes[i].depth < node.depth {
-                                    tab.tree_cursor = i;
-                                    tab.update_preview_for_tree();
-                                    break;
-                                }
+            }
+            Action::Parent => {
+                // Collapse current dir or go to parent node
+                if let Some(node) = self.tab().tree_nodes.get(self.tab().tree_cursor).cloned() {
+                    if node.entry.is_dir && node.expanded {
+                        self.tab_mut().tree_toggle_expand();
+                    } else if node.depth > 0 {
+                        // Find parent node
+                        let tab = self.tab_mut();
+                        for i in (0..tab.tree_cursor).rev() {
+                            if tab.tree_nodes[i].depth < node.depth {
+                                tab.tree_cursor = i;
+                                tab.update_preview_for_tree();
+                                break;
                             }
                         }
                     }
                 }
-                KeyCode::Char('g') => self.pending_g = true,
-                KeyCode::Char('G') => {
-                    let len = self.tab().tree_nodes.len();
+            }
+            Action::JumpTop => {
+                self.tab_mut().tree_cursor = 0;
+                self.tab_mut().update_preview_for_tree();
+            }
+            Action::JumpBottom => {
+                let len = self.tab().tree_nodes.len();
+                let tab = self.tab_mut();
+                tab.tree_cursor = len.saturating_sub(1);
+                tab.update_preview_for_tree();
+            }
+            Action::ToggleSelect => {
+                if let Some(entry) = self.tab().selected_tree_entry().cloned() {
                     let tab = self.tab_mut();
-                    tab.tree_cursor = len.saturating_sub(1);
-                    tab.update_preview_for_tree();
-                }
-                KeyCode::Char(' ') => {
-                    if let Some(entry) = self.tab().selected_tree_entry().cloned() {
-                        let tab = self.tab_mut();
-                        if tab.selected.contains(&entry.path) {
-                            tab.selected.remove(&entry.path);
-                        } else {
-                            tab.selected.insert(entry.path);
-                        }
-                        if tab.tree_cursor < tab.tree_nodes.len().saturating_sub(1) {
-                            tab.tree_cursor += 1;
-                            tab.update_preview_for_tree();
-                        }
+                    if tab.selected.contains(&entry.path) {
+                        tab.selected.remove(&entry.path);
+                    } else {
+                        tab.selected.insert(entry.path);
+                    }
+                    if tab.tree_cursor < tab.tree_nodes.len().saturating_sub(1) {
+                        tab.tree_cursor += 1;
+                        tab.update_preview_for_tree();
                     }
                 }
-                _ => {
-                    return self.handle_tree_common_key(key);
-                }
             }
+            Action::ToggleHidden => {
+                let new_hidden = !self.tab().show_hidden;
+                self.tab_mut().show_hidden = new_hidden;
+                self.tab_mut().rebuild_tree();
+            }
+            _ => return self.run_common_action(action),
+        }
+        Ok(false)
+    }
+
+    fn run_action(&mut self, action: Action) -> Result<bool, Box<dyn std::error::Error>> {
+        if self.tab().tree_mode {
+            return self.run_tree_action(action);
+        }
+        if self.tab().in_archive() && changes_directory(action) {
+            self.status_message =
+                Some("Archive is read-only — yank entries and paste them elsewhere".to_string());
             return Ok(false);
         }
 
-        match key.code {
-            KeyCode::Char('q') => return Ok(true),
-            KeyCode::Char('j') | KeyCode::Down => {
+        match action {
+            Action::MoveDown => {
                 let tab = self.tab_mut();
                 if tab.cursor < tab.filtered_entries.len().saturating_sub(1) {
                     tab.cursor += 1;
                     tab.update_preview();
                 }
             }
-            KeyCode::Char('k') | KeyCode::Up => {
+            Action::MoveUp => {
                 let tab = self.tab_mut();
                 if tab.cursor > 0 {
                     tab.cursor -= 1;
                     tab.update_preview();
                 }
             }
-            KeyCode::Char('l') | KeyCode::Right | KeyCode::Enter => {
+            Action::Open => {
                 if let Some(entry) = self.tab().selected_entry().cloned() {
                     if entry.is_dir {
                         let tab = self.tab_mut();
                         tab.current_dir = entry.path;
                         tab.cursor = 0;
                         tab.refresh()?;
+                    } else if archive::is_browsable(&entry.path) {
+                        self.enter_archive(entry)?;
                     } else {
-                        let _ = open::that(&entry.path);
+                        self.open_file(&entry.path);
                     }
                 }
             }
-            KeyCode::Char('h') | KeyCode::Left => {
+            Action::Parent => {
                 let parent = self.tab().current_dir.parent().map(|p| p.to_path_buf());
                 if let Some(parent) = parent {
                     let old_name = self
@@ -863,21 +1646,26 @@ impl App {
                     }
                 }
             }
-            KeyCode::Char('g') => self.pending_g = true,
-            KeyCode::Char('G') => {
+            Action::JumpTop => {
+                self.tab_mut().cursor = 0;
+                self.tab_mut().update_preview();
+            }
+            Action::JumpBottom => {
                 let len = self.tab().filtered_entries.len();
                 let tab = self.tab_mut();
                 tab.cursor = len.saturating_sub(1);
                 tab.update_preview();
             }
-            KeyCode::Char('/') => {
+            Action::Filter => {
                 self.input_mode = InputMode::Filter;
                 self.input_buffer.clear();
             }
-            KeyCode::Char('d') => self.pending_d = true,
-            KeyCode::Char('y') => self.pending_y = true,
-            KeyCode::Char('p') => self.pending_p = true,
-            KeyCode::Char(' ') => {
+            Action::Delete => self.delete_selected()?,
+            Action::Yank => self.yank_selected(),
+            Action::Paste => self.paste()?,
+            Action::Cut => self.cut_selected(),
+            Action::PasteToOtherPane => self.paste_to_other_pane()?,
+            Action::ToggleSelect => {
                 if let Some(entry) = self.tab().selected_entry().cloned() {
                     let tab = self.tab_mut();
                     if tab.selected.contains(&entry.path) {
@@ -891,7 +1679,7 @@ impl App {
                     }
                 }
             }
-            KeyCode::Char('s') => {
+            Action::CycleSort => {
                 let new_sort = match self.tab().sort_by {
                     SortBy::Name => SortBy::Size,
                     SortBy::Size => SortBy::Date,
@@ -902,40 +1690,36 @@ impl App {
                 self.status_message = Some(format!("Sort: {new_sort:?}"));
                 self.tab_mut().refresh()?;
             }
-            KeyCode::Char('.') => {
+            Action::ToggleHidden => {
                 let new_hidden = !self.tab().show_hidden;
                 self.tab_mut().show_hidden = new_hidden;
                 self.tab_mut().refresh()?;
             }
-            KeyCode::Char('r') => {
-                if self.tab().selected_entry().is_some() {
+            Action::Rename => {
+                if let Some(name) = self.tab().selected_entry().map(|e| e.name.clone()) {
                     self.input_mode = InputMode::Rename;
-                    self.input_buffer = self
-                        .tab()
-                        .selected_entry()
-                        .map(|e| e.name.clone())
-                        .unwrap_or_default();
+                    self.input_buffer = name;
                 }
             }
-            KeyCode::Char('n') => {
+            Action::NewFile => {
                 self.input_mode = InputMode::CreateFile;
                 self.input_buffer.clear();
                 self.status_message = Some("New file: ".to_string());
             }
-            KeyCode::Char('N') => {
+            Action::NewDir => {
                 self.input_mode = InputMode::CreateDir;
                 self.input_buffer.clear();
                 self.status_message = Some("New directory: ".to_string());
             }
-            KeyCode::Char('m') => {
+            Action::SetBookmark => {
                 self.input_mode = InputMode::Bookmark;
                 self.status_message = Some("Bookmark key: ".to_string());
             }
-            KeyCode::Char('\'') => {
+            Action::JumpBookmark => {
                 self.input_mode = InputMode::JumpBookmark;
                 self.status_message = Some("Jump to bookmark: ".to_string());
             }
-            KeyCode::Char('c') => {
+            Action::Chmod => {
                 #[cfg(unix)]
                 if let Some(entry) = self.tab().selected_entry() {
                     if let Ok(meta) = std::fs::metadata(&entry.path) {
@@ -950,107 +1734,90 @@ impl App {
                     self.status_message = Some("chmod not supported on this platform".to_string());
                 }
             }
-            KeyCode::Char('F') => {
+            Action::Search => {
                 self.input_mode = InputMode::Search;
                 self.input_buffer.clear();
-                self.status_message = Some("Search: ".to_string());
-            }
-            KeyCode::Char('t') => {
-                self.tab_mut().toggle_tree_mode();
-                let mode = if self.tab().tree_mode { "Tree" } else { "List" };
-                self.status_message = Some(format!("View: {mode}"));
-            }
-            KeyCode::Char('Y') => {
-                let entry = if self.tab().tree_mode {
-                    self.tab().selected_tree_entry().cloned()
-                } else {
-                    self.tab().selected_entry().cloned()
-                };
-                if let Some(entry) = entry {
-                    match file_ops::copy_path_to_clipboard(&entry.path) {
-                        Ok(_) => {
-                            self.status_message =
-                                Some(format!("Path copied: {}", entry.path.display()))
-                        }
-                        Err(e) => self.status_message = Some(format!("Clipboard error: {e}")),
-                    }
-                }
+                self.status_message = Some(self.search_prompt());
             }
-            KeyCode::Char('T') => {
+            Action::CycleTheme => {
                 self.theme_name = self.theme_name.next();
                 self.theme = Theme::from_name(self.theme_name);
+                let syntax_theme = self.syntax_theme();
+                for tab in self.tabs.iter_mut().chain(self.dual_tab.as_mut()) {
+                    tab.set_syntax_theme(syntax_theme.clone());
+                }
                 self.status_message = Some(format!("Theme: {}", self.theme_name.label()));
             }
-            KeyCode::Char('u') => match self.undo_stack.undo() {
+            Action::Undo => match self.undo_stack.undo() {
                 Ok(msg) => {
                     self.status_message = Some(msg);
                     self.tab_mut().refresh()?;
                 }
                 Err(e) => self.status_message = Some(e),
             },
-            KeyCode::Char('U') => match self.undo_stack.redo() {
+            Action::Redo => match self.undo_stack.redo() {
                 Ok(msg) => {
                     self.status_message = Some(msg);
                     self.tab_mut().refresh()?;
                 }
                 Err(e) => self.status_message = Some(e),
             },
-            KeyCode::Char('D') => {
+            Action::ToggleDualPane => {
                 self.toggle_dual_pane()?;
                 let mode = if self.dual_pane { "ON" } else { "OFF" };
                 self.status_message = Some(format!("Dual pane: {mode} (Tab to switch)"));
             }
-            KeyCode::Tab => {
-                if self.dual_pane {
-                    self.dual_switch_pane();
-                    let side = if self.dual_right_active {
-                        "Right"
-                    } else {
-                        "Left"
-                    };
-                    self.status_message = Some(format!("Active pane: {side}"));
-                }
+            Action::SwitchPane if self.dual_pane => {
+                self.dual_switch_pane();
+                let side = if self.dual_right_active {
+                    "Right"
+                } else {
+                    "Left"
+                };
+                self.status_message = Some(format!("Active pane: {side}"));
             }
-            KeyCode::Char('X') => {
-                // Extract archive
+            Action::ExtractArchive => {
                 if let Some(entry) = self.tab().selected_entry().cloned() {
-                    if file_ops::is_archive(&entry.path) {
-                        let dest = self.tab().current_dir.clone();
-                        match file_ops::extract_archive(&entry.path, &dest) {
-                            Ok(files) => {
-                                self.status_message = Some(format!(
-                                    "Extracted {} entries from {}",
-                                    files.len(),
-                                    entry.name
-                                ));
-                                self.tab_mut().refresh()?;
-                                // Also refresh other pane if in dual mode
-                                if self.dual_pane {
-                                    if self.dual_right_active {
-                                        self.tabs[self.active_tab].refresh()?;
-                                    } else if let Some(dt) = self.dual_tab.as_mut() {
-                                        let _ = dt.refresh();
-                                    }
-                                }
-                            }
-                            Err(e) => self.status_message = Some(format!("Extract error: {e}")),
-                        }
+                    if let Some(kind) = file_ops::detect_archive(&entry.path) {
+                        let subfolder = match kind {
+                            ArchiveKind::Single(_) => None,
+                            _ => Some(file_ops::archive_stem(&entry.path)),
+                        };
+                        self.extract_prompt = Some(ExtractPrompt {
+                            archive: entry.path,
+                            subfolder,
+                        });
+                        self.input_buffer = self.extract_suggestion(false).unwrap_or_default();
+                        self.input_mode = InputMode::Extract;
+                        self.status_message = None;
                     } else {
                         self.status_message =
-                            Some("Not an archive (zip/tar/tar.gz/tgz)".to_string());
+                            Some("Not an archive (zip, tar, gz, xz, bz2, zst)".to_string());
                     }
                 }
             }
-            KeyCode::Char('Z') => {
-                // Compress selected files to zip
-                let sources: Vec<PathBuf> = if self.tab().selected.is_empty() {
-                    self.tab()
-                        .selected_entry()
-                        .map(|e| vec![e.path.clone()])
-                        .unwrap_or_default()
+            Action::GitStage | Action::GitUnstage => {
+                let paths = self.op_sources();
+                if paths.is_empty() {
+                    return Ok(false);
+                }
+                if action == Action::GitStage {
+                    let run = git_status::stage_paths;
+                    self.git_in_background(JobKind::Stage, paths, run, "Staged");
                 } else {
-                    self.tab().selected.iter().cloned().collect()
-                };
+                    let run = git_status::unstage_paths;
+                    self.git_in_background(JobKind::Unstage, paths, run, "Unstaged");
+                }
+            }
+            Action::GitDiscard => {
+                let paths = self.op_sources();
+                if !paths.is_empty() {
+                    self.pending_discard = paths;
+                    self.input_mode = InputMode::ConfirmDiscard;
+                }
+            }
+            Action::CompressArchive => {
+                let sources = self.op_sources();
                 if sources.is_empty() {
                     self.status_message = Some("Nothing to compress".to_string());
                 } else {
@@ -1063,21 +1830,14 @@ impl App {
                     } else {
                         "archive".to_string()
                     };
-                    let dest = self.tab().current_dir.join(format!("{base_name}.zip"));
-                    match file_ops::compress_zip(&sources, &dest) {
-                        Ok(count) => {
-                            self.status_message = Some(format!(
-                                "Compressed {count} item(s) → {}",
-                                dest.file_name().unwrap_or_default().to_string_lossy()
-                            ));
-                            self.tab_mut().selected.clear();
-                            self.tab_mut().refresh()?;
-                        }
-                        Err(e) => self.status_message = Some(format!("Compress error: {e}")),
-                    }
+                    let format = CompressFormat::Zip;
+                    self.compress_mode = Some(format);
+                    self.input_buffer = format!("{base_name}.{}", format.extension());
+                    self.input_mode = InputMode::Compress;
+                    self.status_message = None;
                 }
             }
-            _ => {}
+            _ => return self.run_common_action(action),
         }
         Ok(false)
     }
@@ -1230,11 +1990,9 @@ impl App {
                 self.input_buffer.pop();
                 self.status_message = Some(format!("chmod (octal): {}", self.input_buffer));
             }
-            KeyCode::Char(c) if c.is_ascii_digit() && c < '8' => {
-                if self.input_buffer.len() < 4 {
-                    self.input_buffer.push(c);
-                    self.status_message = Some(format!("chmod (octal): {}", self.input_buffer));
-                }
+            KeyCode::Char(c) if c.is_ascii_digit() && c < '8' && self.input_buffer.len() < 4 => {
+                self.input_buffer.push(c);
+                self.status_message = Some(format!("chmod (octal): {}", self.input_buffer));
             }
             _ => {}
         }
@@ -1246,60 +2004,139 @@ impl App {
             KeyCode::Esc => {
                 self.input_mode = InputMode::Normal;
                 self.input_buffer.clear();
+                self.search_globs_focus = false;
                 self.status_message = None;
             }
             KeyCode::Enter => {
                 let pattern = self.input_buffer.clone();
-                self.input_buffer.clear();
                 if pattern.is_empty() {
                     self.input_mode = InputMode::Normal;
                     self.status_message = None;
-                } else {
-                    let dir = self.tab().current_dir.clone();
-                    self.search_results = file_ops::search_recursive(&dir, &pattern, 200);
-                    self.search_cursor = 0;
-                    if self.search_results.is_empty() {
-                        self.input_mode = InputMode::Normal;
-                        self.status_message = Some(format!("No results for \"{pattern}\""));
-                    } else {
-                        let count = self.search_results.len();
-                        self.input_mode = InputMode::SearchResults;
-                        self.status_message = Some(format!("{count} results for \"{pattern}\" — j/k navigate, Enter open, Esc close"));
-                    }
+                    return Ok(false);
+                }
+                let dir = self.tab().current_dir.clone();
+                let globs: Vec<&str> = self.search_globs.split_whitespace().collect();
+                let search =
+                    match file_ops::ContentSearch::new(&dir, &pattern, self.search_options, &globs)
+                    {
+                        Ok(search) => search,
+                        Err(e) => {
+                            // Leave the prompt open to fix the pattern
+                            self.status_message = Some(format!("Invalid pattern: {e}"));
+                            return Ok(false);
+                        }
+                    };
+                self.input_buffer.clear();
+                self.search_globs_focus = false;
+                self.input_mode = InputMode::Normal;
+                if let Some(id) = self.search_job.take() {
+                    self.jobs.cancel(id);
+                }
+                self.status_message = Some(format!("Searching for \"{pattern}\"…"));
+                let id = self
+                    .jobs
+                    .spawn(JobKind::Search, pattern.clone(), move |progress| {
+                        let results = search.run(200, progress);
+                        if progress.is_cancelled() {
+                            return JobOutcome {
+                                error: Some("Cancelled".to_string()),
+                                search: Some(Vec::new()),
+                                ..Default::default()
+                            };
+                        }
+                        let message = if results.is_empty() {
+                            format!("No results for \"{pattern}\"")
+                        } else {
+                            format!(
+                            "{} results for \"{pattern}\" — j/k navigate, Enter open, Esc close",
+                            results.len()
+                        )
+                        };
+                        JobOutcome {
+                            message,
+                            search: Some(results),
+                            ..Default::default()
+                        }
+                    });
+                self.search_job = Some(id);
+            }
+            // Option toggles, as in most editors' find bars
+            KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::ALT) => {
+                let options = &mut self.search_options;
+                match c {
+                    'r' => options.regex = !options.regex,
+                    'c' => options.case = options.case.next(),
+                    'w' => options.whole_word = !options.whole_word,
+                    _ => return Ok(false),
                 }
+                self.status_message = Some(self.search_prompt());
+            }
+            KeyCode::Tab => {
+                self.search_globs_focus = !self.search_globs_focus;
+                self.status_message = Some(self.search_prompt());
             }
             KeyCode::Backspace => {
-                self.input_buffer.pop();
-                self.status_message = Some(format!("Search: {}", self.input_buffer));
+                self.search_field().pop();
+                self.status_message = Some(self.search_prompt());
             }
             KeyCode::Char(c) => {
-                self.input_buffer.push(c);
-                self.status_message = Some(format!("Search: {}", self.input_buffer));
+                self.search_field().push(c);
+                self.status_message = Some(self.search_prompt());
             }
             _ => {}
         }
         Ok(false)
     }
 
+    /// The search prompt text being edited: the pattern or the globs
+    fn search_field(&mut self) -> &mut String {
+        if self.search_globs_focus {
+            &mut self.search_globs
+        } else {
+            &mut self.input_buffer
+        }
+    }
+
+    /// The search prompt with the options in effect
+    pub fn search_prompt(&self) -> String {
+        if self.search_globs_focus {
+            return format!(
+                "Search files (*.rs includes, !dir/ excludes; Tab: pattern): {}",
+                self.search_globs
+            );
+        }
+        let globs = if self.search_globs.trim().is_empty() {
+            String::new()
+        } else {
+            format!(" │ files: {}", self.search_globs.trim())
+        };
+        format!(
+            "Search [{}] (Alt-r/c/w, Tab: files): {}{globs}",
+            self.search_options.labels(),
+            self.input_buffer
+        )
+    }
+
     fn handle_search_results_key(
         &mut self,
         key: KeyEvent,
     ) -> Result<bool, Box<dyn std::error::Error>> {
-        match key.code {
-            KeyCode::Esc | KeyCode::Char('q') => {
-                self.input_mode = InputMode::Normal;
-                self.search_results.clear();
-                self.status_message = None;
-            }
-            KeyCode::Char('j') | KeyCode::Down => {
-                if self.search_cursor < self.search_results.len().saturating_sub(1) {
-                    self.search_cursor += 1;
-                }
+        if key.code == KeyCode::Esc {
+            self.pending_keys.clear();
+            self.close_search_results();
+            return Ok(false);
+        }
+        match self.resolve_key(key) {
+            Some(Action::Quit) => self.close_search_results(),
+            Some(Action::MoveDown)
+                if self.search_cursor < self.search_results.len().saturating_sub(1) =>
+            {
+                self.search_cursor += 1;
             }
-            KeyCode::Char('k') | KeyCode::Up => {
+            Some(Action::MoveUp) => {
                 self.search_cursor = self.search_cursor.saturating_sub(1);
             }
-            KeyCode::Enter => {
+            Some(Action::Open) => {
                 if let Some(result) = self.search_results.get(self.search_cursor).cloned() {
                     // Navigate to the file's parent directory
                     if let Some(parent) = result.path.parent() {
@@ -1327,10 +2164,10 @@ impl App {
                     self.status_message = Some(format!("Opened: {}", result.path.display()));
                 }
             }
-            KeyCode::Char('G') => {
+            Some(Action::JumpBottom) => {
                 self.search_cursor = self.search_results.len().saturating_sub(1);
             }
-            KeyCode::Char('g') => {
+            Some(Action::JumpTop) => {
                 self.search_cursor = 0;
             }
             _ => {}
@@ -1338,231 +2175,2147 @@ impl App {
         Ok(false)
     }
 
-    fn delete_selected(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        if self.tab().selected.is_empty() {
-            if let Some(entry) = self.tab().selected_entry() {
-                match file_ops::delete_to_trash(&entry.path) {
-                    Ok(_) => self.status_message = Some("Deleted to trash".to_string()),
-                    Err(e) => self.status_message = Some(format!("Error: {e}")),
+    /// Switch to the full-screen list of commits that touched `path`
+    fn open_git_log(&mut self, path: PathBuf) {
+        if self.tab().in_archive() {
+            self.status_message = Some("No git history inside archives".to_string());
+            return;
+        }
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        self.status_message = Some(format!("Reading the history of {name}…"));
+        let (tx, rx) = mpsc::channel();
+        let worker_path = path.clone();
+        thread::spawn(move || {
+            let _ = tx.send(git_status::file_log(&worker_path, GIT_LOG_LIMIT));
+        });
+        self.git_log_loading = Some(PendingLog { path, rx });
+    }
+
+    /// Show the history read by [`Self::open_git_log`] once it arrives,
+    /// unless another view was opened meanwhile
+    fn poll_git_log(&mut self) {
+        let Some(pending) = &self.git_log_loading else {
+            return;
+        };
+        let result = match pending.rx.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Err("Reading history stopped".to_string()),
+        };
+        let Some(PendingLog { path, .. }) = self.git_log_loading.take() else {
+            return;
+        };
+        if self.input_mode != InputMode::Normal {
+            return;
+        }
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        match result {
+            Ok(log) if log.is_empty() => {
+                self.status_message = Some(format!("No commits touch {name}"));
+            }
+            Ok(log) => {
+                self.status_message = Some(format!("{} commit(s) touching {name}", log.len()));
+                self.git_log = log;
+                self.git_log_cursor = 0;
+                self.git_log_blame = self.tab().git_preview == GitPreview::Blame;
+                self.git_log_path = Some(path);
+                self.input_mode = InputMode::GitLog;
+                self.preview_log_revision();
+            }
+            Err(e) => self.status_message = Some(format!("Git error: {e}")),
+        }
+    }
+
+    fn handle_git_log_key(&mut self, key: KeyEvent) -> Result<bool, Box<dyn std::error::Error>> {
+        if key.code == KeyCode::Esc {
+            self.pending_keys.clear();
+            self.close_git_log();
+            return Ok(false);
+        }
+        let last = self.git_log.len().saturating_sub(1);
+        match self.resolve_key(key) {
+            Some(Action::Quit) => {
+                self.close_git_log();
+                return Ok(false);
+            }
+            Some(Action::MoveDown) => self.git_log_cursor = (self.git_log_cursor + 1).min(last),
+            Some(Action::MoveUp) => self.git_log_cursor = self.git_log_cursor.saturating_sub(1),
+            Some(Action::JumpBottom) => self.git_log_cursor = last,
+            Some(Action::JumpTop) => self.git_log_cursor = 0,
+            Some(Action::ToggleGitBlame) => self.git_log_blame = !self.git_log_blame,
+            _ => return Ok(false),
+        }
+        self.preview_log_revision();
+        Ok(false)
+    }
+
+    /// Preview the logged path as of the commit under the cursor: the
+    /// file itself, its blame in blame mode, or the commit for directories
+    fn preview_log_revision(&mut self) {
+        let (Some(path), Some(commit)) = (
+            self.git_log_path.clone(),
+            self.git_log.get(self.git_log_cursor).cloned(),
+        ) else {
+            return;
+        };
+        let cache_dir = self.cache_dir.clone();
+        let blame = self.git_log_blame;
+        let tab = self.tab_mut();
+        if path.is_dir() {
+            tab.preview_slot.next_generation();
+            tab.preview_lines = vec![
+                PreviewLine::new(format!("commit {}", commit.id), PreviewStyle::Header),
+                PreviewLine::new(format!("Author: {}", commit.author), PreviewStyle::Normal),
+                PreviewLine::new(String::new(), PreviewStyle::Normal),
+                PreviewLine::new(format!("    {}", commit.subject), PreviewStyle::Normal),
+            ];
+            tab.preview_loading = false;
+            return;
+        }
+        if blame {
+            tab.preview_engine
+                .request_blame(&tab.preview_slot, path, Some(commit.id));
+        } else {
+            tab.preview_engine.request_prepared(
+                &tab.preview_slot,
+                tab.syntax_theme.clone(),
+                move || git_status::materialize_revision(&path, &commit.id, &cache_dir),
+            );
+        }
+        tab.preview_lines.clear();
+        tab.preview_loading = true;
+    }
+
+    fn close_git_log(&mut self) {
+        self.input_mode = InputMode::Normal;
+        self.git_log.clear();
+        self.git_log_path = None;
+        self.status_message = None;
+        let tab = self.tab_mut();
+        if tab.tree_mode {
+            tab.update_preview_for_tree();
+        } else {
+            tab.update_preview();
+        }
+    }
+
+    fn close_search_results(&mut self) {
+        self.input_mode = InputMode::Normal;
+        self.search_results.clear();
+        self.status_message = None;
+    }
+
+    fn delete_selected(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let paths: Vec<PathBuf> = if self.tab().selected.is_empty() {
+            self.op_sources()
+        } else {
+            self.tab_mut().selected.drain().collect()
+        };
+        if paths.is_empty() {
+            return Ok(());
+        }
+        let description = describe_items(&paths);
+        self.jobs
+            .spawn(JobKind::Delete, description, move |progress| {
+                progress.set_totals(paths.len() as u64, 0);
+                let mut count = 0;
+                let mut error = None;
+                for p in &paths {
+                    if progress.is_cancelled() {
+                        error = Some("Cancelled".to_string());
+                        break;
+                    }
+                    match file_ops::delete_to_trash(p) {
+                        Ok(_) => count += 1,
+                        Err(e) => error = Some(e),
+                    }
+                    progress.add_files(1);
+                }
+                let message = match (&error, paths.len()) {
+                    (None, 1) => "Deleted to trash".to_string(),
+                    (Some(e), 1) => format!("Error: {e}"),
+                    _ => format!("Deleted {count} items to trash"),
+                };
+                JobOutcome {
+                    message,
+                    error,
+                    ..Default::default()
+                }
+            });
+        Ok(())
+    }
+
+    /// Browse an archive as a read-only directory, once it is indexed
+    fn enter_archive(&mut self, entry: FileEntry) -> Result<(), Box<dyn std::error::Error>> {
+        let tab = self.tab_mut();
+        tab.current_dir = entry.path;
+        tab.cursor = 0;
+        tab.refresh()?;
+        self.status_message = Some(format!("Browsing {} (read-only)", entry.name));
+        Ok(())
+    }
+
+    /// Open a file with the system opener, extracting it first when it
+    /// lives inside the archive being browsed
+    fn open_file(&mut self, path: &Path) {
+        let extracted = self.tab().archive.as_ref().and_then(|index| {
+            let inner = index.inner_path(path)?;
+            Some(index.materialize(&self.cache_dir, inner))
+        });
+        match extracted {
+            Some(Ok(real)) => {
+                let _ = open::that(real);
+            }
+            Some(Err(e)) => self.status_message = Some(format!("Error: {e}")),
+            None => {
+                let _ = open::that(path);
+            }
+        }
+    }
+
+    /// Paths an operation should act on: the selection, or the cursor entry
+    fn op_sources(&self) -> Vec<PathBuf> {
+        if self.tab().selected.is_empty() {
+            self.tab()
+                .selected_entry()
+                .map(|e| vec![e.path.clone()])
+                .unwrap_or_default()
+        } else {
+            self.tab().selected.iter().cloned().collect()
+        }
+    }
+
+    fn yank_selected(&mut self) {
+        let sources = self.op_sources();
+        let count = sources.len();
+        self.pending_op = Some(PendingOp {
+            kind: OpKind::Copy,
+            sources,
+        });
+        self.status_message = Some(format!("Yanked {count} item(s)"));
+    }
+
+    fn cut_selected(&mut self) {
+        let sources = self.op_sources();
+        let count = sources.len();
+        self.pending_op = Some(PendingOp {
+            kind: OpKind::Move,
+            sources,
+        });
+        self.tab_mut().selected.clear();
+        self.status_message = Some(format!("Cut {count} item(s) — pp to move here"));
+    }
+
+    /// Whether `path` is waiting in the cut register to be moved
+    pub fn is_cut(&self, path: &Path) -> bool {
+        self.pending_op
+            .as_ref()
+            .is_some_and(|op| op.kind == OpKind::Move && op.sources.iter().any(|p| p == path))
+    }
+
+    /// Directory of the inactive dual pane, if dual-pane mode is on
+    fn other_pane_dir(&self) -> Option<PathBuf> {
+        if !self.dual_pane {
+            return None;
+        }
+        if self.dual_right_active {
+            Some(self.tabs[self.active_tab].current_dir.clone())
+        } else {
+            self.dual_tab.as_ref().map(|t| t.current_dir.clone())
+        }
+    }
+
+    fn paste(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let dest_dir = self.tab().current_dir.clone();
+        self.paste_into(&dest_dir)
+    }
+
+    fn paste_to_other_pane(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        match self.other_pane_dir() {
+            Some(dest_dir) => self.paste_into(&dest_dir),
+            None => {
+                self.status_message = Some("Dual pane is off (D to enable)".to_string());
+                Ok(())
+            }
+        }
+    }
+
+    fn paste_into(&mut self, dest_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if archive::is_browsable(dest_dir) || archive::split_virtual(dest_dir).is_some() {
+            self.status_message = Some("Cannot paste into an archive".to_string());
+            return Ok(());
+        }
+        if let Some(op) = &self.pending_op {
+            if let Some(src) = op.sources.iter().find(|src| is_inside(dest_dir, src)) {
+                let name = src.file_name().unwrap_or_default().to_string_lossy();
+                self.status_message = Some(format!("Cannot paste {name} into itself"));
+                return Ok(());
+            }
+        }
+        if let Some(op) = self.pending_op.take() {
+            self.paste_state = Some(PasteState {
+                queue: op.sources.iter().cloned().collect(),
+                op,
+                dest_dir: dest_dir.to_path_buf(),
+                plan: Vec::new(),
+                conflict: None,
+                apply_all: false,
+                remembered: None,
+                show_compare: false,
+                skipped: 0,
+            });
+            self.continue_paste()?;
+        } else {
+            self.status_message = Some("Nothing to paste".to_string());
+        }
+        Ok(())
+    }
+
+    /// Resolve destinations for queued sources until a collision needs the
+    /// user's decision, then run the paste once every item is planned.
+    fn continue_paste(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(state) = self.paste_state.as_mut() else {
+            return Ok(());
+        };
+        while let Some(src) = state.queue.pop_front() {
+            let Some(name) = src.file_name().map(|n| n.to_string_lossy().to_string()) else {
+                continue;
+            };
+            if state.op.kind == OpKind::Move && src.parent() == Some(state.dest_dir.as_path()) {
+                // Moving onto itself is a no-op
+                continue;
+            }
+            let dest = state.dest_dir.join(&name);
+            if dest == src || state.is_planned(&dest) {
+                // Copying a file into its own directory makes a duplicate,
+                // and so does a name another item of this paste now takes
+                let dest = state.free_dest(&name);
+                state.plan.push(PasteItem {
+                    src,
+                    dest,
+                    overwrite: false,
+                });
+            } else if dest.symlink_metadata().is_err() {
+                state.plan.push(PasteItem {
+                    src,
+                    dest,
+                    overwrite: false,
+                });
+            } else if let Some(choice) = state.remembered {
+                state.resolve(src, dest, choice);
+            } else {
+                state.conflict = Some(PasteConflict {
+                    src_stat: FileStat::of(&src),
+                    dest_stat: FileStat::of(&dest),
+                    src,
+                    dest,
+                });
+                state.show_compare = false;
+                self.input_mode = InputMode::PasteConflict;
+                self.status_message = None;
+                return Ok(());
+            }
+        }
+        self.input_mode = InputMode::Normal;
+        if let Some(state) = self.paste_state.take() {
+            self.execute_paste(state)?;
+        }
+        Ok(())
+    }
+
+    fn execute_paste(&mut self, state: PasteState) -> Result<(), Box<dyn std::error::Error>> {
+        self.tab_mut().selected.clear();
+        let kind = state.op.kind;
+        let skipped = state.skipped;
+        let plan = state.plan;
+        if plan.is_empty() {
+            self.status_message = Some(paste_summary(kind, 0, skipped, 0));
+            return Ok(());
+        }
+        let job_kind = match kind {
+            OpKind::Copy => JobKind::Copy,
+            OpKind::Move => JobKind::Move,
+        };
+        let sources: Vec<PathBuf> = plan.iter().map(|item| item.src.clone()).collect();
+        let description = format!(
+            "{} → {}",
+            describe_items(&sources),
+            state.dest_dir.display()
+        );
+        let backup_dir = self.undo_stack.backup_dir().to_path_buf();
+        self.jobs.spawn(job_kind, description, move |progress| {
+            run_paste(kind, &plan, &backup_dir, skipped, progress)
+        });
+        Ok(())
+    }
+
+    /// Default destination for the extract prompt, in the current pane
+    /// (relative to it) or the other one (absolute): a fresh folder named
+    /// after the archive, or the directory itself for a single file
+    fn extract_suggestion(&self, other_pane: bool) -> Option<String> {
+        let prompt = self.extract_prompt.as_ref()?;
+        let current_dir = &self.tab().current_dir;
+        let base = if other_pane {
+            self.other_pane_dir()?
+        } else {
+            current_dir.clone()
+        };
+        let dest = match &prompt.subfolder {
+            Some(name) => file_ops::unique_dest(&base, name),
+            None => base,
+        };
+        Some(match dest.strip_prefix(current_dir) {
+            Ok(rel) if !other_pane && rel.as_os_str().is_empty() => ".".to_string(),
+            Ok(rel) if !other_pane => rel.display().to_string(),
+            _ => dest.display().to_string(),
+        })
+    }
+
+    fn handle_extract_key(&mut self, key: KeyEvent) -> Result<bool, Box<dyn std::error::Error>> {
+        match key.code {
+            KeyCode::Esc => {
+                self.input_mode = InputMode::Normal;
+                self.input_buffer.clear();
+                self.extract_prompt = None;
+            }
+            KeyCode::Tab => {
+                // Flip between the suggestions for this pane and the other
+                let here = self.extract_suggestion(false);
+                let there = self.extract_suggestion(true);
+                match there {
+                    Some(there) if here.as_deref() == Some(self.input_buffer.as_str()) => {
+                        self.input_buffer = there;
+                    }
+                    Some(_) => self.input_buffer = here.unwrap_or_default(),
+                    None => {
+                        self.status_message = Some("Dual pane is off (D to enable)".to_string());
+                    }
+                }
+            }
+            KeyCode::Enter => {
+                let Some(prompt) = self.extract_prompt.clone() else {
+                    self.input_mode = InputMode::Normal;
+                    return Ok(false);
+                };
+                let input = self.input_buffer.trim().to_string();
+                let current_dir = self.tab().current_dir.clone();
+                let dest = match input.as_str() {
+                    "" | "." => current_dir,
+                    path => current_dir.join(path),
+                };
+                if archive::is_browsable(&dest) || archive::split_virtual(&dest).is_some() {
+                    self.status_message = Some("Cannot extract into an archive".to_string());
+                    return Ok(false);
+                }
+                if dest.exists() && !dest.is_dir() {
+                    self.status_message = Some(format!("{input} is not a directory"));
+                    return Ok(false);
+                }
+                // Only the suggested fresh folder makes way for a single root
+                let suggested = [false, true]
+                    .into_iter()
+                    .any(|other| self.extract_suggestion(other).as_deref() == Some(input.as_str()));
+                let lift_root = prompt.subfolder.is_some() && suggested && !dest.exists();
+                self.input_mode = InputMode::Normal;
+                self.input_buffer.clear();
+                self.extract_prompt = None;
+                self.status_message = None;
+                self.extract_in_background(prompt.archive, dest, lift_root);
+            }
+            KeyCode::Backspace => {
+                self.input_buffer.pop();
+            }
+            KeyCode::Char(c) => {
+                self.input_buffer.push(c);
+            }
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    /// Extract `archive` into `dest`; with `lift_root`, `dest` is a new
+    /// folder that an archive with a single root directory skips
+    fn extract_in_background(&mut self, archive: PathBuf, dest: PathBuf, lift_root: bool) {
+        let name = archive
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        self.jobs
+            .spawn(JobKind::Extract, name.clone(), move |progress| {
+                let result = match (lift_root, dest.parent(), dest.file_name()) {
+                    (true, Some(parent), Some(folder)) => file_ops::extract_to_subfolder(
+                        &archive,
+                        parent,
+                        &folder.to_string_lossy(),
+                        progress,
+                    ),
+                    _ => file_ops::extract_archive(&archive, &dest, progress),
+                };
+                match result {
+                    Ok(report) => JobOutcome {
+                        message: extract_summary(&name, &report),
+                        undo: if report.created.is_empty() {
+                            Vec::new()
+                        } else {
+                            vec![undo::record_extract(&archive, &report)]
+                        },
+                        ..Default::default()
+                    },
+                    Err(e) => JobOutcome {
+                        message: format!("Extract error: {e}"),
+                        error: Some(e),
+                        ..Default::default()
+                    },
+                }
+            });
+    }
+
+    fn handle_confirm_discard_key(
+        &mut self,
+        key: KeyEvent,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        self.input_mode = InputMode::Normal;
+        let paths = std::mem::take(&mut self.pending_discard);
+        if key.code != KeyCode::Char('y') {
+            self.status_message = Some("Discard cancelled".to_string());
+            return Ok(false);
+        }
+        let run = git_status::discard_paths;
+        self.git_in_background(JobKind::Discard, paths, run, "Discarded changes to");
+        Ok(false)
+    }
+
+    /// Stage, unstage or discard `paths` with `run`; the index update walks
+    /// the whole worktree, which takes a while in a large repository
+    fn git_in_background(
+        &mut self,
+        kind: JobKind,
+        paths: Vec<PathBuf>,
+        run: fn(&[PathBuf]) -> Result<(), String>,
+        done: &'static str,
+    ) {
+        let description = describe_items(&paths);
+        let name = description.clone();
+        self.jobs
+            .spawn(kind, description, move |_| match run(&paths) {
+                Ok(()) => JobOutcome {
+                    message: format!("{done} {name}"),
+                    ..Default::default()
+                },
+                Err(e) => JobOutcome {
+                    message: format!("Git error: {e}"),
+                    error: Some(e),
+                    ..Default::default()
+                },
+            });
+    }
+
+    fn handle_compress_key(&mut self, key: KeyEvent) -> Result<bool, Box<dyn std::error::Error>> {
+        let format = self.compress_mode.unwrap_or(CompressFormat::Zip);
+        match key.code {
+            KeyCode::Esc => {
+                self.input_mode = InputMode::Normal;
+                self.input_buffer.clear();
+                self.compress_mode = None;
+            }
+            KeyCode::Tab => {
+                // Swap the extension along with the format
+                let next = format.next();
+                let suffix = format!(".{}", format.extension());
+                if let Some(stem) = self.input_buffer.strip_suffix(&suffix) {
+                    self.input_buffer = stem.to_string();
+                }
+                self.input_buffer.push('.');
+                self.input_buffer.push_str(next.extension());
+                self.compress_mode = Some(next);
+            }
+            KeyCode::Enter => {
+                let mut name = self.input_buffer.trim().to_string();
+                if name.is_empty() {
+                    self.status_message = Some("Archive name required".to_string());
+                    return Ok(false);
+                }
+                // A typed extension wins over the format picked with Tab
+                let format = CompressFormat::from_name(&name).unwrap_or_else(|| {
+                    name.push('.');
+                    name.push_str(format.extension());
+                    format
+                });
+                let dest = self.tab().current_dir.join(&name);
+                if dest.exists() {
+                    self.status_message = Some(format!("{name} already exists"));
+                    return Ok(false);
+                }
+                let sources = self.op_sources();
+                if let CompressFormat::Single(codec) = format {
+                    if sources.len() != 1 || sources[0].is_dir() {
+                        self.status_message = Some(format!(
+                            "{} compresses a single file; use .tar.{} for more",
+                            codec.name(),
+                            format.extension()
+                        ));
+                        return Ok(false);
+                    }
+                }
+                self.input_mode = InputMode::Normal;
+                self.input_buffer.clear();
+                self.compress_mode = None;
+                self.status_message = None;
+                self.tab_mut().selected.clear();
+                self.compress_in_background(sources, dest, format);
+            }
+            KeyCode::Backspace => {
+                self.input_buffer.pop();
+            }
+            KeyCode::Char(c) => {
+                self.input_buffer.push(c);
+            }
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    fn compress_in_background(
+        &mut self,
+        sources: Vec<PathBuf>,
+        dest: PathBuf,
+        format: CompressFormat,
+    ) {
+        let name = dest
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        self.jobs
+            .spawn(JobKind::Compress, name.clone(), move |progress| {
+                let (files, bytes) = sources
+                    .iter()
+                    .map(|p| jobs::measure(p))
+                    .fold((0, 0), |acc, (f, b)| (acc.0 + f, acc.1 + b));
+                progress.set_totals(files, bytes);
+                let result = match (format, format.tar_codec()) {
+                    (CompressFormat::Single(codec), _) => {
+                        file_ops::compress_single(&sources[0], &dest, codec, progress)
+                    }
+                    (_, Some(codec)) => file_ops::compress_tar(&sources, &dest, codec, progress),
+                    (_, None) => file_ops::compress_zip(&sources, &dest, progress),
+                };
+                match result {
+                    Ok(count) => JobOutcome {
+                        message: format!("Compressed {count} item(s) → {name}"),
+                        ..Default::default()
+                    },
+                    Err(e) => {
+                        // A half-written archive is useless
+                        let _ = fs::remove_file(&dest);
+                        JobOutcome {
+                            message: format!("Compress error: {e}"),
+                            error: Some(e),
+                            ..Default::default()
+                        }
+                    }
+                }
+            });
+    }
+
+    /// Apply background work that finished since the last tick: rendered
+    /// previews and completed jobs
+    pub fn tick(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.git_checked.elapsed() >= GIT_REVALIDATE_INTERVAL {
+            self.git_checked = Instant::now();
+            self.tab().git_cache.revalidate();
+        }
+        for tab in self.tabs.iter_mut().chain(self.dual_tab.as_mut()) {
+            if let Some(e) = tab.poll_archive() {
+                self.status_message = Some(format!("Cannot open archive: {e}"));
+            }
+            tab.poll_git_status();
+            tab.poll_preview();
+        }
+        self.poll_git_log();
+        let outcomes = self.jobs.poll();
+        if outcomes.is_empty() {
+            return Ok(());
+        }
+        let mut touched_files = false;
+        for (id, outcome) in outcomes {
+            if let Some(results) = outcome.search {
+                // Only the latest search is shown, and only if it wasn't
+                // cancelled
+                if self.search_job == Some(id) {
+                    self.search_job = None;
+                    if outcome.error.is_none() {
+                        self.status_message = Some(outcome.message);
+                        self.show_search_results(results);
+                    }
+                }
+                continue;
+            }
+            for action in outcome.undo {
+                self.undo_stack.push(action);
+            }
+            self.status_message = Some(outcome.message);
+            touched_files = true;
+        }
+        if touched_files {
+            self.refresh_all();
+        }
+        Ok(())
+    }
+
+    /// Present the results of a finished search, unless the user has
+    /// moved on to another prompt meanwhile
+    fn show_search_results(&mut self, results: Vec<SearchResult>) {
+        self.search_results = results;
+        self.search_cursor = 0;
+        if self.input_mode == InputMode::Normal && !self.search_results.is_empty() {
+            self.input_mode = InputMode::SearchResults;
+        }
+    }
+
+    /// Re-read every open directory, since a job may have touched any of them
+    fn refresh_all(&mut self) {
+        for tab in &mut self.tabs {
+            let _ = tab.refresh();
+        }
+        if let Some(dt) = self.dual_tab.as_mut() {
+            let _ = dt.refresh();
+        }
+        // Refreshing re-previewed the cursor entry under the log view
+        if self.input_mode == InputMode::GitLog {
+            self.preview_log_revision();
+        }
+    }
+
+    fn handle_jobs_key(&mut self, key: KeyEvent) -> Result<bool, Box<dyn std::error::Error>> {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => {
+                self.pending_keys.clear();
+                self.input_mode = InputMode::Normal;
+                return Ok(false);
+            }
+            KeyCode::Char('c') => {
+                if let Some(job) = self.jobs.jobs.get(self.job_cursor) {
+                    let id = job.id;
+                    if self.jobs.cancel(id) {
+                        self.status_message = Some(format!("Cancelling job {id}"));
+                    }
+                }
+                return Ok(false);
+            }
+            KeyCode::Char('C') => {
+                self.jobs.clear_finished();
+                self.job_cursor = self.job_cursor.min(self.jobs.jobs.len().saturating_sub(1));
+                return Ok(false);
+            }
+            _ => {}
+        }
+        match self.resolve_key(key) {
+            Some(Action::MoveDown) if self.job_cursor + 1 < self.jobs.jobs.len() => {
+                self.job_cursor += 1;
+            }
+            Some(Action::MoveUp) => self.job_cursor = self.job_cursor.saturating_sub(1),
+            Some(Action::JumpTop) => self.job_cursor = 0,
+            Some(Action::JumpBottom) => {
+                self.job_cursor = self.jobs.jobs.len().saturating_sub(1);
+            }
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    fn handle_paste_conflict_key(
+        &mut self,
+        key: KeyEvent,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let choice = match key.code {
+            KeyCode::Char('o') => ConflictChoice::Overwrite,
+            KeyCode::Char('s') => ConflictChoice::Skip,
+            KeyCode::Char('r') => ConflictChoice::Rename,
+            KeyCode::Char('a') => {
+                if let Some(state) = self.paste_state.as_mut() {
+                    state.apply_all = !state.apply_all;
+                }
+                return Ok(false);
+            }
+            KeyCode::Char('c') => {
+                if let Some(state) = self.paste_state.as_mut() {
+                    state.show_compare = !state.show_compare;
+                }
+                return Ok(false);
+            }
+            KeyCode::Esc => {
+                // Nothing has been touched yet; keep the register for another try
+                self.input_mode = InputMode::Normal;
+                if let Some(state) = self.paste_state.take() {
+                    self.pending_op = Some(state.op);
+                }
+                self.status_message = Some("Paste cancelled".to_string());
+                return Ok(false);
+            }
+            _ => return Ok(false),
+        };
+        if let Some(state) = self.paste_state.as_mut() {
+            if let Some(conflict) = state.conflict.take() {
+                if state.apply_all {
+                    state.remembered = Some(choice);
+                }
+                state.resolve(conflict.src, conflict.dest, choice);
+            }
+        }
+        self.continue_paste()?;
+        Ok(false)
+    }
+}
+
+/// Actions that would write to the current directory, refused inside archives
+fn changes_directory(action: Action) -> bool {
+    matches!(
+        action,
+        Action::Delete
+            | Action::Cut
+            | Action::Paste
+            | Action::Rename
+            | Action::NewFile
+            | Action::NewDir
+            | Action::Chmod
+            | Action::ExtractArchive
+            | Action::CompressArchive
+            | Action::ToggleTree
+            | Action::GitStage
+            | Action::GitUnstage
+            | Action::GitDiscard
+    )
+}
+
+/// Short label for a set of paths: the file name for one, a count for more
+fn describe_items(paths: &[PathBuf]) -> String {
+    match paths {
+        [single] => single
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| single.display().to_string()),
+        _ => format!("{} items", paths.len()),
+    }
+}
+
+/// Status line for a finished extraction, naming entries that were skipped
+/// for pointing outside the destination
+fn extract_summary(name: &str, report: &file_ops::ExtractReport) -> String {
+    let mut msg = format!("Extracted {} entries from {name}", report.extracted.len());
+    if let Some(dir) = report.dest.file_name() {
+        msg.push_str(&format!(" into {}/", dir.to_string_lossy()));
+    }
+    if !report.skipped.is_empty() {
+        let shown: Vec<&str> = report.skipped.iter().take(3).map(String::as_str).collect();
+        msg.push_str(&format!(
+            " — skipped {} unsafe: {}",
+            report.skipped.len(),
+            shown.join(", ")
+        ));
+        if report.skipped.len() > shown.len() {
+            msg.push_str(", …");
+        }
+    }
+    msg
+}
+
+fn paste_summary(kind: OpKind, count: usize, skipped: usize, failed: usize) -> String {
+    let verb = match kind {
+        OpKind::Copy => "Pasted",
+        OpKind::Move => "Moved",
+    };
+    let mut msg = format!("{verb} {count} item(s)");
+    if skipped > 0 {
+        msg.push_str(&format!(", {skipped} skipped"));
+    }
+    if failed > 0 {
+        msg.push_str(&format!(", {failed} failed"));
+    }
+    msg
+}
+
+/// Worker side of a paste: copy or move every planned item, stashing
+/// files that get overwritten so the whole thing can be undone
+fn run_paste(
+    kind: OpKind,
+    plan: &[PasteItem],
+    backup_dir: &Path,
+    skipped: usize,
+    progress: &Progress,
+) -> JobOutcome {
+    let sizes: Vec<(u64, u64)> = plan.iter().map(|item| jobs::measure(&item.src)).collect();
+    let (files_total, bytes_total) = sizes
+        .iter()
+        .fold((0, 0), |acc, (f, b)| (acc.0 + f, acc.1 + b));
+    progress.set_totals(files_total, bytes_total);
+
+    let mut outcome = JobOutcome::default();
+    let (mut count, mut failed) = (0, 0);
+    let (mut files_done, mut bytes_done) = (0, 0);
+    // Reported over anything else: the user's original is not where it was
+    let mut restore_error = None;
+    for (item, (files, bytes)) in plan.iter().zip(sizes) {
+        if progress.is_cancelled() {
+            break;
+        }
+        let backup = if item.overwrite {
+            match undo::stash(backup_dir, &item.dest) {
+                Ok(b) => Some(b),
+                Err(e) => {
+                    failed += 1;
+                    outcome.error = Some(e);
+                    continue;
+                }
+            }
+        } else {
+            None
+        };
+        let result = match (kind, archive::split_virtual(&item.src)) {
+            // Yanked from inside an archive: extract the entry in its place
+            (_, Some((archive, inner))) => {
+                file_ops::extract_subtree(&archive, &inner, &item.dest, progress).map(|_| ())
+            }
+            (OpKind::Copy, None) => file_ops::copy_with_progress(&item.src, &item.dest, progress),
+            (OpKind::Move, None) => file_ops::move_with_progress(&item.src, &item.dest, progress),
+        };
+        match result {
+            Ok(()) => {
+                count += 1;
+                let record = match kind {
+                    OpKind::Copy => undo::record_copy(&item.dest),
+                    OpKind::Move => undo::record_move(&item.src, &item.dest),
+                };
+                outcome.undo.push(match &backup {
+                    Some(b) => undo::record_overwrite(record, &item.dest, b),
+                    None => record,
+                });
+            }
+            Err(e) => {
+                // Put the file we were about to replace back in place
+                if let Some(b) = &backup {
+                    if let Err(e) = undo::unstash(b, &item.dest) {
+                        restore_error =
+                            Some(format!("{e}; the original is kept at {}", b.display()));
+                    }
+                }
+                if progress.is_cancelled() {
+                    break;
+                }
+                failed += 1;
+                outcome.error = Some(e);
+            }
+        }
+        files_done += files;
+        bytes_done += bytes;
+        progress.set_done(files_done, bytes_done);
+    }
+    outcome.message = paste_summary(kind, count, skipped, failed);
+    if progress.is_cancelled() {
+        outcome.message.push_str(" before cancel");
+        outcome.error = Some("Cancelled".to_string());
+    }
+    if let Some(e) = restore_error {
+        outcome.message = format!("{} — {e}", outcome.message);
+        outcome.error = Some(e);
+    }
+    outcome
+}
+
+/// A listing entry for an archive member, under the archive's virtual path
+fn archive_file_entry(archive: &Path, entry: &ArchiveEntry) -> FileEntry {
+    FileEntry {
+        name: entry
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        path: archive.join(&entry.path),
+        is_dir: entry.is_dir,
+        is_symlink: entry.is_symlink,
+        symlink_target: entry.symlink_target.clone(),
+        size: entry.size,
+        modified: entry.modified,
+        git_status: None,
+    }
+}
+
+fn read_dir(path: &Path, show_hidden: bool) -> Result<Vec<FileEntry>, Box<dyn std::error::Error>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let entry = match entry {
+            Ok(e) => e,
+            Err(_) => continue,
+        };
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !show_hidden && name.starts_with('.') {
+            continue;
+        }
+        let metadata = match entry.metadata() {
+            Ok(m) => m,
+            Err(_) => continue,
+        };
+        let is_symlink = entry.file_type().map(|t| t.is_symlink()).unwrap_or(false);
+        let symlink_target = if is_symlink {
+            fs::read_link(entry.path())
+                .ok()
+                .map(|p| p.display().to_string())
+        } else {
+            None
+        };
+        entries.push(FileEntry {
+            name,
+            path: entry.path(),
+            is_dir: metadata.is_dir(),
+            is_symlink,
+            symlink_target,
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            git_status: None,
+        });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn make_app(tmp: &TempDir) -> App {
+        let dir = tmp.path().canonicalize().unwrap();
+        App::with_dir(Config::default(), dir.clone(), dir.join(".cache")).unwrap()
+    }
+
+    #[test]
+    fn test_app_new() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        fs::write(dir.join("a.txt"), "hello").unwrap();
+        let app = make_app(&tmp);
+        assert_eq!(*app.current_dir(), dir);
+        assert!(!app.entries().is_empty());
+    }
+
+    #[test]
+    fn test_read_dir_hidden() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join(".hidden"), "").unwrap();
+        fs::write(tmp.path().join("visible"), "").unwrap();
+        let entries = read_dir(tmp.path(), false).unwrap();
+        assert!(entries.iter().all(|e| !e.name.starts_with('.')));
+        let entries = read_dir(tmp.path(), true).unwrap();
+        assert!(entries.iter().any(|e| e.name == ".hidden"));
+    }
+
+    #[test]
+    fn test_sort_by_name() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("b.txt"), "").unwrap();
+        fs::write(tmp.path().join("a.txt"), "").unwrap();
+        let app = make_app(&tmp);
+        let names: Vec<_> = app.entries().iter().map(|e| &e.name).collect();
+        assert_eq!(names, vec!["a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn test_sort_cycle() {
+        let tmp = TempDir::new().unwrap();
+        let mut app = make_app(&tmp);
+        assert_eq!(app.sort_by(), SortBy::Name);
+        app.tab_mut().sort_by = SortBy::Size;
+        assert_eq!(app.sort_by(), SortBy::Size);
+    }
+
+    #[test]
+    fn test_toggle_hidden() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join(".hidden"), "").unwrap();
+        let mut app = make_app(&tmp);
+        assert!(!app.show_hidden());
+        app.tab_mut().show_hidden = true;
+        app.tab_mut().refresh().unwrap();
+        assert!(app.entries().iter().any(|e| e.name == ".hidden"));
+    }
+
+    #[test]
+    fn test_filter() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("apple.txt"), "").unwrap();
+        fs::write(tmp.path().join("banana.txt"), "").unwrap();
+        let mut app = make_app(&tmp);
+        app.input_mode = InputMode::Filter;
+        app.tab_mut().filter_text = "app".to_string();
+        app.tab_mut().apply_filter();
+        assert_eq!(app.tab().filtered_entries.len(), 1);
+    }
+
+    #[test]
+    fn test_breadcrumb() {
+        let tmp = TempDir::new().unwrap();
+        let app = make_app(&tmp);
+        assert!(app.breadcrumb().contains(tmp.path().to_str().unwrap()));
+    }
+
+    #[test]
+    fn test_selection() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("a.txt"), "").unwrap();
+        let mut app = make_app(&tmp);
+        let path = app.entries()[0].path.clone();
+        app.tab_mut().selected.insert(path.clone());
+        assert_eq!(app.selection_count(), 1);
+        app.tab_mut().selected.remove(&path);
+        assert_eq!(app.selection_count(), 0);
+    }
+
+    #[test]
+    fn test_file_count() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("a.txt"), "").unwrap();
+        fs::write(tmp.path().join("b.txt"), "").unwrap();
+        let app = make_app(&tmp);
+        assert_eq!(app.file_count(), 2);
+    }
+
+    #[test]
+    fn test_bookmarks() {
+        let tmp = TempDir::new().unwrap();
+        let mut app = make_app(&tmp);
+        app.bookmarks.insert('a', tmp.path().to_path_buf());
+        assert_eq!(app.bookmarks.get(&'a'), Some(&tmp.path().to_path_buf()));
+    }
+
+    #[test]
+    fn test_yank_and_paste() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("a.txt"), "content").unwrap();
+        let dest = tmp.path().join("sub");
+        fs::create_dir(&dest).unwrap();
+        let mut app = make_app(&tmp);
+        app.yank_selected();
+        assert!(app.pending_op.is_some());
+    }
+
+    /// Tick until every background job has finished and been applied
+    fn finish_jobs(app: &mut App) {
+        while app.jobs.running_count() > 0 {
+            app.tick().unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+    }
+
+    fn press(app: &mut App, c: char) {
+        app.handle_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_key_sequence_jump_top() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("a.txt"), "").unwrap();
+        fs::write(tmp.path().join("b.txt"), "").unwrap();
+        let mut app = make_app(&tmp);
+        press(&mut app, 'G');
+        assert_eq!(app.cursor(), 1);
+        press(&mut app, 'g');
+        assert_eq!(app.cursor(), 1);
+        assert_eq!(app.pending_keys.len(), 1);
+        press(&mut app, 'g');
+        assert_eq!(app.cursor(), 0);
+        assert!(app.pending_keys.is_empty());
+    }
+
+    #[test]
+    fn test_unbound_sequence_is_discarded() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("a.txt"), "").unwrap();
+        fs::write(tmp.path().join("b.txt"), "").unwrap();
+        let mut app = make_app(&tmp);
+        press(&mut app, 'g');
+        press(&mut app, 'j');
+        assert!(app.pending_keys.is_empty());
+        assert_eq!(app.cursor(), 0);
+    }
+
+    #[test]
+    fn test_keybind_override() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("a.txt"), "").unwrap();
+        fs::write(tmp.path().join("b.txt"), "").unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        let mut config = Config::default();
+        config
+            .keybinds
+            .insert("move_down".to_string(), "J".to_string());
+        let mut app = App::with_dir(config, dir.clone(), dir.join(".cache")).unwrap();
+        press(&mut app, 'j');
+        assert_eq!(app.cursor(), 0);
+        press(&mut app, 'J');
+        assert_eq!(app.cursor(), 1);
+    }
+
+    #[test]
+    fn test_invalid_keybind_reported() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        let mut config = Config::default();
+        config
+            .keybinds
+            .insert("launch_rocket".to_string(), "R".to_string());
+        let app = App::with_dir(config.clone(), dir.clone(), dir.join(".cache")).unwrap();
+        assert!(app
+            .status_message
+            .as_deref()
+            .unwrap()
+            .starts_with("Keybind error: "));
+
+        // Every bad binding is reported, not just the first
+        config
+            .keybinds
+            .insert("open_portal".to_string(), "P".to_string());
+        let app = App::with_dir(config, dir.clone(), dir.join(".cache")).unwrap();
+        let message = app.status_message.unwrap();
+        assert!(message.starts_with("2 keybind errors: "));
+        assert!(message.contains("launch_rocket") && message.contains("open_portal"));
+    }
+
+    #[test]
+    fn test_cut_and_paste_moves() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        fs::write(dir.join("a.txt"), "content").unwrap();
+        fs::create_dir(dir.join("sub")).unwrap();
+        let mut app = make_app(&tmp);
+        let pos = app
+            .visible_entries()
+            .iter()
+            .position(|e| e.name == "a.txt")
+            .unwrap();
+        app.tab_mut().cursor = pos;
+        press(&mut app, 'x');
+        assert!(app.is_cut(&dir.join("a.txt")));
+        // Enter sub and paste
+        app.tab_mut().current_dir = dir.join("sub");
+        app.tab_mut().refresh().unwrap();
+        press(&mut app, 'p');
+        press(&mut app, 'p');
+        finish_jobs(&mut app);
+        assert!(!dir.join("a.txt").exists());
+        assert_eq!(
+            fs::read_to_string(dir.join("sub").join("a.txt")).unwrap(),
+            "content"
+        );
+        assert!(app.pending_op.is_none());
+        assert!(!app.is_cut(&dir.join("a.txt")));
+        // Undo moves it back
+        press(&mut app, 'u');
+        assert!(dir.join("a.txt").exists());
+    }
+
+    #[test]
+    fn test_paste_to_other_pane() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        fs::write(dir.join("a.txt"), "content").unwrap();
+        fs::create_dir(dir.join("other")).unwrap();
+        let mut app = make_app(&tmp);
+        app.toggle_dual_pane().unwrap();
+        let right = app.dual_tab.as_mut().unwrap();
+        right.current_dir = dir.join("other");
+        right.refresh().unwrap();
+        let pos = app
+            .visible_entries()
+            .iter()
+            .position(|e| e.name == "a.txt")
+            .unwrap();
+        app.tab_mut().cursor = pos;
+        app.cut_selected();
+        app.paste_to_other_pane().unwrap();
+        finish_jobs(&mut app);
+        assert!(dir.join("other").join("a.txt").exists());
+        assert!(!dir.join("a.txt").exists());
+        assert!(app
+            .dual_tab
+            .as_ref()
+            .unwrap()
+            .entries
+            .iter()
+            .any(|e| e.name == "a.txt"));
+    }
+
+    #[test]
+    fn test_paste_to_other_pane_requires_dual() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("a.txt"), "").unwrap();
+        let mut app = make_app(&tmp);
+        app.yank_selected();
+        app.paste_to_other_pane().unwrap();
+        assert!(app.pending_op.is_some());
+    }
+
+    /// Yank `name` from `dir` and paste it into `dir/sub`, which already has one
+    fn setup_conflict(tmp: &TempDir) -> (App, PathBuf) {
+        let dir = tmp.path().canonicalize().unwrap();
+        fs::write(dir.join("a.txt"), "new").unwrap();
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub").join("a.txt"), "old").unwrap();
+        let mut app = make_app(tmp);
+        let pos = app
+            .visible_entries()
+            .iter()
+            .position(|e| e.name == "a.txt")
+            .unwrap();
+        app.tab_mut().cursor = pos;
+        app.yank_selected();
+        app.paste_into(&dir.join("sub")).unwrap();
+        assert_eq!(app.input_mode, InputMode::PasteConflict);
+        (app, dir.join("sub"))
+    }
+
+    #[test]
+    fn test_paste_conflict_skip() {
+        let tmp = TempDir::new().unwrap();
+        let (mut app, sub) = setup_conflict(&tmp);
+        press(&mut app, 's');
+        assert_eq!(app.input_mode, InputMode::Normal);
+        assert_eq!(fs::read_to_string(sub.join("a.txt")).unwrap(), "old");
+        assert!(app.status_message.as_deref().unwrap().contains("1 skipped"));
+    }
+
+    #[test]
+    fn test_paste_conflict_rename() {
+        let tmp = TempDir::new().unwrap();
+        let (mut app, sub) = setup_conflict(&tmp);
+        press(&mut app, 'r');
+        finish_jobs(&mut app);
+        assert_eq!(fs::read_to_string(sub.join("a.txt")).unwrap(), "old");
+        assert_eq!(fs::read_to_string(sub.join("a (1).txt")).unwrap(), "new");
+    }
+
+    #[test]
+    fn test_paste_conflict_overwrite_and_undo() {
+        let tmp = TempDir::new().unwrap();
+        let (mut app, sub) = setup_conflict(&tmp);
+        press(&mut app, 'o');
+        finish_jobs(&mut app);
+        assert_eq!(fs::read_to_string(sub.join("a.txt")).unwrap(), "new");
+        press(&mut app, 'u');
+        assert_eq!(fs::read_to_string(sub.join("a.txt")).unwrap(), "old");
+    }
+
+    #[test]
+    fn test_paste_conflict_cancel_keeps_register() {
+        let tmp = TempDir::new().unwrap();
+        let (mut app, sub) = setup_conflict(&tmp);
+        press(&mut app, 'c');
+        assert!(app.paste_state.as_ref().unwrap().show_compare);
+        app.handle_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.input_mode, InputMode::Normal);
+        assert!(app.pending_op.is_some());
+        assert_eq!(fs::read_to_string(sub.join("a.txt")).unwrap(), "old");
+    }
+
+    #[test]
+    fn test_paste_conflict_apply_to_all() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        fs::create_dir(dir.join("sub")).unwrap();
+        for name in ["a.txt", "b.txt"] {
+            fs::write(dir.join(name), "new").unwrap();
+            fs::write(dir.join("sub").join(name), "old").unwrap();
+        }
+        let mut app = make_app(&tmp);
+        app.tab_mut().selected.insert(dir.join("a.txt"));
+        app.tab_mut().selected.insert(dir.join("b.txt"));
+        app.yank_selected();
+        app.paste_into(&dir.join("sub")).unwrap();
+        press(&mut app, 'a');
+        press(&mut app, 's');
+        // Second collision resolved without asking
+        assert_eq!(app.input_mode, InputMode::Normal);
+        assert!(app.status_message.as_deref().unwrap().contains("2 skipped"));
+    }
+
+    #[test]
+    fn test_paste_directory_into_itself_is_refused() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        fs::create_dir_all(dir.join("tree/sub")).unwrap();
+        fs::write(dir.join("tree/a.txt"), "a").unwrap();
+        let mut app = make_app(&tmp);
+        app.yank_selected();
+        for dest in [dir.join("tree"), dir.join("tree/sub")] {
+            app.paste_into(&dest).unwrap();
+            assert_eq!(
+                app.status_message.as_deref(),
+                Some("Cannot paste tree into itself")
+            );
+            assert!(app.jobs.jobs.is_empty());
+        }
+        // Still yanked, and fine to paste beside the original
+        app.paste_into(&dir).unwrap();
+        finish_jobs(&mut app);
+        assert!(dir.join("tree (1)/a.txt").exists());
+        assert!(!dir.join("tree/sub/tree").exists());
+    }
+
+    #[test]
+    fn test_paste_rename_avoids_planned_names() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/a.txt"), "old").unwrap();
+        fs::write(dir.join("a.txt"), "new").unwrap();
+        fs::write(dir.join("a (1).txt"), "literal").unwrap();
+        let mut app = make_app(&tmp);
+        for name in ["a.txt", "a (1).txt"] {
+            app.tab_mut().selected.insert(dir.join(name));
+        }
+        app.yank_selected();
+        app.paste_into(&dir.join("sub")).unwrap();
+        // Whichever comes first, the renamed copy and the file literally
+        // named like it both land
+        press(&mut app, 'r');
+        assert_eq!(app.input_mode, InputMode::Normal);
+        finish_jobs(&mut app);
+        let mut contents: Vec<String> = fs::read_dir(dir.join("sub"))
+            .unwrap()
+            .map(|e| fs::read_to_string(e.unwrap().path()).unwrap())
+            .collect();
+        contents.sort();
+        assert_eq!(contents, ["literal", "new", "old"]);
+        assert_eq!(fs::read_to_string(dir.join("sub/a.txt")).unwrap(), "old");
+    }
+
+    #[test]
+    fn test_paste_copy_into_same_dir_duplicates() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        fs::write(dir.join("a.txt"), "x").unwrap();
+        let mut app = make_app(&tmp);
+        app.yank_selected();
+        app.paste().unwrap();
+        assert_eq!(app.input_mode, InputMode::Normal);
+        finish_jobs(&mut app);
+        assert!(dir.join("a (1).txt").exists());
+    }
+
+    #[test]
+    fn test_extract_runs_as_job() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        fs::write(dir.join("a.txt"), "hello").unwrap();
+        file_ops::compress_zip(
+            &[dir.join("a.txt")],
+            &dir.join("pack.zip"),
+            &Progress::default(),
+        )
+        .unwrap();
+        fs::remove_file(dir.join("a.txt")).unwrap();
+        let mut app = make_app(&tmp);
+        press(&mut app, 'X');
+        assert_eq!(app.input_mode, InputMode::Extract);
+        assert_eq!(app.input_buffer, "pack");
+        app.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.jobs.jobs.len(), 1);
+        finish_jobs(&mut app);
+        assert_eq!(fs::read_to_string(dir.join("pack/a.txt")).unwrap(), "hello");
+        assert!(!dir.join("a.txt").exists());
+        assert!(app.entries().iter().any(|e| e.name == "pack"));
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Extracted 1 entries from pack.zip into pack/")
+        );
+
+        // Undo removes the folder it made
+        press(&mut app, 'u');
+        assert!(!dir.join("pack").exists());
+        assert!(dir.join("pack.zip").exists());
+    }
+
+    #[test]
+    fn test_extract_keeps_single_root_and_undo_spares_other_files() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        fs::create_dir_all(dir.join("proj/src")).unwrap();
+        fs::write(dir.join("proj/src/main.rs"), "fn main() {}").unwrap();
+        let archive = dir.join("proj-1.0.tar.gz");
+        file_ops::compress_tar(
+            &[dir.join("proj")],
+            &archive,
+            Codec::Gzip,
+            &Progress::default(),
+        )
+        .unwrap();
+        fs::remove_dir_all(dir.join("proj")).unwrap();
+        let mut app = make_app(&tmp);
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+
+        press(&mut app, 'X');
+        assert_eq!(app.input_buffer, "proj-1.0");
+        app.handle_key(enter).unwrap();
+        finish_jobs(&mut app);
+        assert!(dir.join("proj/src/main.rs").exists());
+        assert!(!dir.join("proj-1.0").exists());
+        press(&mut app, 'u');
+        assert!(!dir.join("proj").exists());
+
+        // A typed destination is used as is, even if it already exists
+        fs::create_dir(dir.join("out")).unwrap();
+        fs::write(dir.join("out/mine.txt"), "keep").unwrap();
+        press(&mut app, 'X');
+        app.input_buffer = "out".to_string();
+        app.handle_key(enter).unwrap();
+        finish_jobs(&mut app);
+        assert!(dir.join("out/proj/src/main.rs").exists());
+        press(&mut app, 'u');
+        assert!(!dir.join("out/proj").exists());
+        assert_eq!(
+            fs::read_to_string(dir.join("out/mine.txt")).unwrap(),
+            "keep"
+        );
+    }
+
+    #[test]
+    fn test_extract_prompt_other_pane() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        fs::write(dir.join("a.txt"), "hello").unwrap();
+        file_ops::compress_zip(
+            &[dir.join("a.txt")],
+            &dir.join("pack.zip"),
+            &Progress::default(),
+        )
+        .unwrap();
+        let other = dir.join("other");
+        fs::create_dir(&other).unwrap();
+        let mut app = make_app(&tmp);
+        let tab = KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE);
+        // Entries: other/, a.txt, pack.zip
+        press(&mut app, 'G');
+
+        press(&mut app, 'X');
+        app.handle_key(tab).unwrap();
+        assert!(app.status_message.as_deref().unwrap().contains("Dual pane"));
+        assert_eq!(app.input_buffer, "pack");
+        app.handle_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE))
+            .unwrap();
+
+        app.toggle_dual_pane().unwrap();
+        let right = app.dual_tab.as_mut().unwrap();
+        right.current_dir = other.clone();
+        right.refresh().unwrap();
+        press(&mut app, 'X');
+        app.handle_key(tab).unwrap();
+        assert_eq!(app.input_buffer, other.join("pack").display().to_string());
+        app.handle_key(tab).unwrap();
+        assert_eq!(app.input_buffer, "pack");
+        app.handle_key(tab).unwrap();
+        app.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
+            .unwrap();
+        finish_jobs(&mut app);
+        assert_eq!(
+            fs::read_to_string(other.join("pack/a.txt")).unwrap(),
+            "hello"
+        );
+        assert!(!dir.join("pack").exists());
+    }
+
+    #[test]
+    fn test_compress_prompt_cycles_format() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        fs::write(dir.join("a.txt"), "hello").unwrap();
+        let mut app = make_app(&tmp);
+        press(&mut app, 'Z');
+        assert_eq!(app.input_mode, InputMode::Compress);
+        assert_eq!(app.input_buffer, "a.zip");
+        let tab = KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE);
+        for expected in ["a.tar.gz", "a.tar.xz", "a.tar.bz2", "a.tar.zst", "a.tar"] {
+            app.handle_key(tab).unwrap();
+            assert_eq!(app.input_buffer, expected);
+        }
+        assert_eq!(app.compress_mode, Some(CompressFormat::Tar));
+        app.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.input_mode, InputMode::Normal);
+        finish_jobs(&mut app);
+        let mut tar = tar::Archive::new(fs::File::open(dir.join("a.tar")).unwrap());
+        assert_eq!(tar.entries().unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_compress_format_from_name() {
+        assert_eq!(
+            CompressFormat::from_name("a.TXZ"),
+            Some(CompressFormat::TarXz)
+        );
+        assert_eq!(
+            CompressFormat::from_name("a.tar.zst"),
+            Some(CompressFormat::TarZst)
+        );
+        assert_eq!(
+            CompressFormat::from_name("a.zip"),
+            Some(CompressFormat::Zip)
+        );
+        assert_eq!(
+            CompressFormat::from_name("notes.txt.xz"),
+            Some(CompressFormat::Single(Codec::Xz))
+        );
+        assert_eq!(CompressFormat::from_name("a.txt"), None);
+    }
+
+    #[test]
+    fn test_compress_prompt_typed_extension_and_existing_name() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        fs::write(dir.join("a.txt"), "hello").unwrap();
+        let mut app = make_app(&tmp);
+        press(&mut app, 'Z');
+        app.input_buffer = "a.txt".to_string();
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+        app.handle_key(enter).unwrap();
+        // No known extension: the picked format's is appended
+        finish_jobs(&mut app);
+        assert!(dir.join("a.txt.zip").exists());
+
+        press(&mut app, 'Z');
+        app.input_buffer = "bundle.tgz".to_string();
+        app.handle_key(enter).unwrap();
+        finish_jobs(&mut app);
+        let gz = flate2::read::GzDecoder::new(fs::File::open(dir.join("bundle.tgz")).unwrap());
+        assert_eq!(tar::Archive::new(gz).entries().unwrap().count(), 1);
+
+        press(&mut app, 'Z');
+        app.input_buffer = "bundle.tgz".to_string();
+        app.handle_key(enter).unwrap();
+        assert_eq!(app.input_mode, InputMode::Compress);
+        assert!(app
+            .status_message
+            .as_deref()
+            .unwrap()
+            .contains("already exists"));
+    }
+
+    #[test]
+    fn test_compress_single_file_codec() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        fs::write(dir.join("notes.txt"), "hello").unwrap();
+        fs::create_dir(dir.join("sub")).unwrap();
+        let mut app = make_app(&tmp);
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+        app.tab_mut().selected.insert(dir.join("notes.txt"));
+        press(&mut app, 'Z');
+        app.input_buffer = "notes.txt.zst".to_string();
+        app.handle_key(enter).unwrap();
+        finish_jobs(&mut app);
+        assert!(!dir.join("notes.txt.zst.zip").exists());
+        let data = zstd::decode_all(fs::File::open(dir.join("notes.txt.zst")).unwrap()).unwrap();
+        assert_eq!(data, b"hello");
+
+        // Several files, or a directory, need a tar around them
+        app.tab_mut().selected.insert(dir.join("notes.txt"));
+        app.tab_mut().selected.insert(dir.join("sub"));
+        press(&mut app, 'Z');
+        app.input_buffer = "both.xz".to_string();
+        app.handle_key(enter).unwrap();
+        assert_eq!(app.input_mode, InputMode::Compress);
+        assert!(app.status_message.as_deref().unwrap().contains(".tar.xz"));
+        assert!(!dir.join("both.xz").exists());
+    }
+
+    #[test]
+    fn test_job_list_and_quit_guard() {
+        let tmp = TempDir::new().unwrap();
+        let mut app = make_app(&tmp);
+        let id = app
+            .jobs
+            .spawn(JobKind::Copy, "slow".to_string(), |progress| {
+                while !progress.is_cancelled() {
+                    std::thread::sleep(std::time::Duration::from_millis(1));
                 }
-            }
-        } else {
-            let paths: Vec<_> = self.tab_mut().selected.drain().collect();
-            let mut count = 0;
-            for p in &paths {
-                if file_ops::delete_to_trash(p).is_ok() {
-                    count += 1;
+                JobOutcome {
+                    message: "stopped".to_string(),
+                    error: Some("Cancelled".to_string()),
+                    ..Default::default()
                 }
+            });
+        // First quit is refused while the job runs
+        press(&mut app, 'q');
+        assert!(app.status_message.as_deref().unwrap().contains("running"));
+        press(&mut app, 'J');
+        assert_eq!(app.input_mode, InputMode::Jobs);
+        press(&mut app, 'c');
+        finish_jobs(&mut app);
+        assert_eq!(app.jobs.jobs[0].id, id);
+        assert_eq!(app.jobs.jobs[0].state, crate::jobs::JobState::Cancelled);
+        press(&mut app, 'C');
+        assert!(app.jobs.jobs.is_empty());
+        app.handle_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.input_mode, InputMode::Normal);
+        assert!(app
+            .handle_key(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE))
+            .unwrap());
+    }
+
+    #[test]
+    fn test_preview_renders_in_background() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("a.txt"), "alpha").unwrap();
+        fs::write(tmp.path().join("b.txt"), "beta").unwrap();
+        let mut app = make_app(&tmp);
+        press(&mut app, 'j');
+        for _ in 0..1000 {
+            if !app.preview_loading() {
+                break;
             }
-            self.status_message = Some(format!("Deleted {count} items to trash"));
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            app.tick().unwrap();
         }
-        self.tab_mut().refresh()?;
-        Ok(())
+        assert!(!app.preview_loading());
+        assert!(app.preview_lines()[0].text.contains("beta"));
+        // Moving back hits the cache, so nothing is pending
+        press(&mut app, 'k');
+        press(&mut app, 'j');
+        assert!(!app.preview_loading());
+        assert!(app.preview_lines()[0].text.contains("beta"));
     }
 
-    fn yank_selected(&mut self) {
-        let sources = if self.tab().selected.is_empty() {
-            self.tab()
-                .selected_entry()
-                .map(|e| vec![e.path.clone()])
-                .unwrap_or_default()
-        } else {
-            self.tab().selected.iter().cloned().collect()
-        };
-        let count = sources.len();
-        self.pending_op = Some(PendingOp {
-            kind: OpKind::Copy,
-            sources,
-        });
-        self.status_message = Some(format!("Yanked {count} item(s)"));
+    fn make_zip(dir: &Path) -> PathBuf {
+        use std::io::Write;
+        let path = dir.join("pack.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("readme.txt", options).unwrap();
+        zip.write_all(b"hello from the archive").unwrap();
+        zip.start_file("src/main.rs", options).unwrap();
+        zip.write_all(b"fn main() {}").unwrap();
+        zip.finish().unwrap();
+        path
     }
 
-    fn paste(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(op) = self.pending_op.take() {
-            let current_dir = self.tab().current_dir.clone();
-            let mut count = 0;
-            for src in &op.sources {
-                let result = match op.kind {
-                    OpKind::Copy => file_ops::copy_file(src, &current_dir),
-                    OpKind::Move => file_ops::move_file(src, &current_dir),
-                };
-                if let Ok(dest) = result {
-                    count += 1;
-                    match op.kind {
-                        OpKind::Copy => {
-                            self.undo_stack.push(undo::record_copy(&dest));
-                        }
-                        OpKind::Move => {
-                            self.undo_stack.push(undo::record_move(src, &dest));
-                        }
-                    }
-                }
+    /// Let a background archive index reach the tab
+    fn wait_for_archive(app: &mut App) {
+        for _ in 0..1000 {
+            if app.tab().archive_loading.is_none() {
+                break;
             }
-            self.tab_mut().selected.clear();
-            self.status_message = Some(format!("Pasted {count} item(s)"));
-            self.tab_mut().refresh()?;
-        } else {
-            self.status_message = Some("Nothing to paste".to_string());
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            app.tick().unwrap();
         }
-        Ok(())
+        assert!(app.tab().archive_loading.is_none());
     }
-}
 
-fn read_dir(path: &Path, show_hidden: bool) -> Result<Vec<FileEntry>, Box<dyn std::error::Error>> {
-    let mut entries = Vec::new();
-    for entry in fs::read_dir(path)? {
-        let entry = match entry {
-            Ok(e) => e,
-            Err(_) => continue,
-        };
-        let name = entry.file_name().to_string_lossy().to_string();
-        if !show_hidden && name.starts_with('.') {
-            continue;
+    /// Let a file's history be read in the background
+    fn wait_for_git_log(app: &mut App) {
+        for _ in 0..1000 {
+            if app.git_log_loading.is_none() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            app.tick().unwrap();
         }
-        let metadata = match entry.metadata() {
-            Ok(m) => m,
-            Err(_) => continue,
-        };
-        let is_symlink = entry.file_type().map(|t| t.is_symlink()).unwrap_or(false);
-        let symlink_target = if is_symlink {
-            fs::read_link(entry.path())
-                .ok()
-                .map(|p| p.display().to_string())
-        } else {
-            None
-        };
-        entries.push(FileEntry {
-            name,
-            path: entry.path(),
-            is_dir: metadata.is_dir(),
-            is_symlink,
-            symlink_target,
-            size: metadata.len(),
-            modified: metadata.modified().ok(),
-            git_status: None,
-        });
+        assert!(app.git_log_loading.is_none());
     }
-    Ok(entries)
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::TempDir;
+    fn wait_for_preview(app: &mut App) {
+        for _ in 0..1000 {
+            if !app.preview_loading() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            app.tick().unwrap();
+        }
+        assert!(!app.preview_loading());
+    }
 
-    fn make_app(tmp: &TempDir) -> App {
-        let dir = tmp.path().canonicalize().unwrap();
-        App::with_dir(Config::default(), dir).unwrap()
+    /// Let background git status scans finish and reach the tab
+    fn wait_for_git(app: &mut App) {
+        for _ in 0..1000 {
+            app.tick().unwrap();
+            if !app.tab().git_pending {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        assert!(!app.tab().git_pending);
     }
 
     #[test]
-    fn test_app_new() {
+    fn test_browse_archive_as_directory() {
         let tmp = TempDir::new().unwrap();
         let dir = tmp.path().canonicalize().unwrap();
-        fs::write(dir.join("a.txt"), "hello").unwrap();
-        let app = make_app(&tmp);
-        assert_eq!(*app.current_dir(), dir);
-        assert!(!app.entries().is_empty());
-    }
+        let archive = make_zip(&dir);
+        let mut app = make_app(&tmp);
+        press(&mut app, 'l');
+        // Listed once indexed in the background, and read-only meanwhile
+        assert_eq!(app.tab().current_dir, archive);
+        assert!(app.tab().in_archive());
+        wait_for_archive(&mut app);
+        assert!(app.tab().archive.is_some());
+        let names: Vec<_> = app.entries().iter().map(|e| e.name.clone()).collect();
+        assert_eq!(names, vec!["src", "readme.txt"]);
+        assert_eq!(app.entries()[1].size, 22);
+        assert!(app.entries()[1].modified.is_some());
+        // The parent pane shows the real directory holding the archive
+        let parent = &app.tab().parent_entries[app.tab().parent_cursor];
+        assert_eq!(parent.path, archive);
 
-    #[test]
-    fn test_read_dir_hidden() {
-        let tmp = TempDir::new().unwrap();
-        fs::write(tmp.path().join(".hidden"), "").unwrap();
-        fs::write(tmp.path().join("visible"), "").unwrap();
-        let entries = read_dir(tmp.path(), false).unwrap();
-        assert!(entries.iter().all(|e| !e.name.starts_with('.')));
-        let entries = read_dir(tmp.path(), true).unwrap();
-        assert!(entries.iter().any(|e| e.name == ".hidden"));
+        // Directories preview from the index, files once extracted
+        assert!(app
+            .preview_lines()
+            .iter()
+            .any(|l| l.text.contains("main.rs")));
+        press(&mut app, 'j');
+        wait_for_preview(&mut app);
+        assert!(app.preview_lines()[0]
+            .text
+            .contains("hello from the archive"));
+
+        press(&mut app, 'k');
+        press(&mut app, 'l');
+        assert_eq!(app.tab().current_dir, archive.join("src"));
+        assert_eq!(app.entries()[0].name, "main.rs");
+        press(&mut app, 'h');
+        press(&mut app, 'h');
+        assert!(app.tab().archive.is_none());
+        assert_eq!(app.tab().current_dir, dir);
+        assert_eq!(app.tab().selected_entry().unwrap().path, archive);
+        assert!(dir.join(".cache").join(archive::SCRATCH_DIR).exists());
     }
 
     #[test]
-    fn test_sort_by_name() {
+    fn test_unreadable_archive_backs_out() {
         let tmp = TempDir::new().unwrap();
-        fs::write(tmp.path().join("b.txt"), "").unwrap();
-        fs::write(tmp.path().join("a.txt"), "").unwrap();
-        let app = make_app(&tmp);
-        let names: Vec<_> = app.entries().iter().map(|e| &e.name).collect();
-        assert_eq!(names, vec!["a.txt", "b.txt"]);
+        let dir = tmp.path().canonicalize().unwrap();
+        fs::write(dir.join("a.txt"), "").unwrap();
+        fs::write(dir.join("broken.zip"), b"PK\x03\x04 truncated").unwrap();
+        let mut app = make_app(&tmp);
+        press(&mut app, 'j');
+        press(&mut app, 'l');
+        wait_for_archive(&mut app);
+        assert!(!app.tab().in_archive());
+        assert_eq!(app.tab().current_dir, dir);
+        assert_eq!(
+            app.tab().selected_entry().unwrap().path,
+            dir.join("broken.zip")
+        );
+        assert!(app
+            .status_message
+            .as_deref()
+            .unwrap()
+            .starts_with("Cannot open archive"));
     }
 
     #[test]
-    fn test_sort_cycle() {
+    fn test_archive_is_read_only_and_entries_paste_out() {
         let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        let archive = make_zip(&dir);
         let mut app = make_app(&tmp);
-        assert_eq!(app.sort_by(), SortBy::Name);
-        app.tab_mut().sort_by = SortBy::Size;
-        assert_eq!(app.sort_by(), SortBy::Size);
+        press(&mut app, 'l');
+        press(&mut app, 'd');
+        press(&mut app, 'd');
+        assert!(app.status_message.as_deref().unwrap().contains("read-only"));
+        wait_for_archive(&mut app);
+        press(&mut app, 'd');
+        press(&mut app, 'd');
+        assert!(app.status_message.as_deref().unwrap().contains("read-only"));
+        assert!(app.jobs.jobs.is_empty());
+
+        // Yank a directory and a file, then paste them outside
+        press(&mut app, ' ');
+        press(&mut app, ' ');
+        press(&mut app, 'y');
+        press(&mut app, 'y');
+        press(&mut app, 'p');
+        press(&mut app, 'p');
+        assert!(app.status_message.as_deref().unwrap().contains("read-only"));
+        press(&mut app, 'h');
+        press(&mut app, 'p');
+        press(&mut app, 'p');
+        finish_jobs(&mut app);
+        assert_eq!(
+            fs::read_to_string(dir.join("readme.txt")).unwrap(),
+            "hello from the archive"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("src/main.rs")).unwrap(),
+            "fn main() {}"
+        );
+        assert!(archive.is_file());
     }
 
     #[test]
-    fn test_toggle_hidden() {
+    fn test_repo_info_follows_directory() {
         let tmp = TempDir::new().unwrap();
-        fs::write(tmp.path().join(".hidden"), "").unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
         let mut app = make_app(&tmp);
-        assert!(!app.show_hidden());
-        app.tab_mut().show_hidden = true;
+        assert!(app.repo_info().is_none());
+        let repo = git2::Repository::init(&dir).unwrap();
+        repo.set_head("refs/heads/trunk").unwrap();
         app.tab_mut().refresh().unwrap();
-        assert!(app.entries().iter().any(|e| e.name == ".hidden"));
+        wait_for_git(&mut app);
+        assert_eq!(
+            app.repo_info().unwrap().head,
+            git_status::Head::Unborn("trunk".to_string())
+        );
     }
 
     #[test]
-    fn test_filter() {
+    fn test_stage_and_discard_from_list() {
         let tmp = TempDir::new().unwrap();
-        fs::write(tmp.path().join("apple.txt"), "").unwrap();
-        fs::write(tmp.path().join("banana.txt"), "").unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        let repo = git2::Repository::init(&dir).unwrap();
+        fs::write(dir.join("a.txt"), "original").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+        fs::write(dir.join("a.txt"), "edited").unwrap();
         let mut app = make_app(&tmp);
-        app.input_mode = InputMode::Filter;
-        app.tab_mut().filter_text = "app".to_string();
-        app.tab_mut().apply_filter();
-        assert_eq!(app.tab().filtered_entries.len(), 1);
+        let status = |app: &App| app.entries()[0].git_status;
+        wait_for_git(&mut app);
+        assert_eq!(status(&app), Some(GitFileStatus::StagedModified));
+
+        press(&mut app, 'g');
+        press(&mut app, 'a');
+        assert_eq!(app.jobs.jobs[0].kind, JobKind::Stage);
+        finish_jobs(&mut app);
+        assert_eq!(app.status_message.as_deref(), Some("Staged a.txt"));
+        wait_for_git(&mut app);
+        assert_eq!(status(&app), Some(GitFileStatus::Staged));
+
+        fs::write(dir.join("a.txt"), "edited again").unwrap();
+        app.tab_mut().refresh().unwrap();
+        press(&mut app, 'g');
+        press(&mut app, 'r');
+        assert_eq!(app.input_mode, InputMode::ConfirmDiscard);
+        press(&mut app, 'n');
+        assert_eq!(app.status_message.as_deref(), Some("Discard cancelled"));
+        assert_eq!(
+            fs::read_to_string(dir.join("a.txt")).unwrap(),
+            "edited again"
+        );
+        press(&mut app, 'g');
+        press(&mut app, 'r');
+        press(&mut app, 'y');
+        finish_jobs(&mut app);
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Discarded changes to a.txt")
+        );
+        assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "edited");
+        wait_for_git(&mut app);
+        assert_eq!(status(&app), Some(GitFileStatus::Staged));
+
+        press(&mut app, 'g');
+        press(&mut app, 'u');
+        finish_jobs(&mut app);
+        wait_for_git(&mut app);
+        assert_eq!(status(&app), Some(GitFileStatus::Untracked));
     }
 
     #[test]
-    fn test_breadcrumb() {
+    fn test_toggle_ignored_files() {
         let tmp = TempDir::new().unwrap();
-        let app = make_app(&tmp);
-        assert!(app.breadcrumb().contains(tmp.path().to_str().unwrap()));
+        let dir = tmp.path().canonicalize().unwrap();
+        git2::Repository::init(&dir).unwrap();
+        fs::write(dir.join(".gitignore"), "target/\n").unwrap();
+        fs::write(dir.join(".ignore"), "*.bak\n").unwrap();
+        fs::create_dir(dir.join("target")).unwrap();
+        fs::write(dir.join("main.rs"), "").unwrap();
+        fs::write(dir.join("main.rs.bak"), "").unwrap();
+        let mut app = make_app(&tmp);
+        let names = |app: &App| -> Vec<String> {
+            app.tab()
+                .visible_entries()
+                .iter()
+                .map(|e| e.name.clone())
+                .collect()
+        };
+        // Shown by default, marked before git has even reported
+        assert_eq!(names(&app), ["target", "main.rs", "main.rs.bak"]);
+        assert_eq!(app.entries()[0].git_status, Some(GitFileStatus::Ignored));
+        assert_eq!(app.entries()[2].git_status, Some(GitFileStatus::Ignored));
+
+        press(&mut app, 'j');
+        press(&mut app, 'g');
+        press(&mut app, 'i');
+        assert_eq!(app.status_message.as_deref(), Some("Ignored files hidden"));
+        assert_eq!(names(&app), ["main.rs"]);
+        wait_for_git(&mut app);
+        assert_eq!(names(&app), ["main.rs"]);
+        assert_eq!(app.tab().selected_entry().unwrap().name, "main.rs");
+
+        // Hidden files are a separate toggle
+        press(&mut app, '.');
+        assert_eq!(names(&app), [".git", ".gitignore", ".ignore", "main.rs"]);
+
+        press(&mut app, 'g');
+        press(&mut app, 'i');
+        assert_eq!(app.status_message.as_deref(), Some("Ignored files shown"));
+        assert_eq!(names(&app).len(), 6);
     }
 
     #[test]
-    fn test_selection() {
+    fn test_git_diff_preview_toggle() {
         let tmp = TempDir::new().unwrap();
-        fs::write(tmp.path().join("a.txt"), "").unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        let repo = git2::Repository::init(&dir).unwrap();
+        fs::write(dir.join("a.txt"), "old line\n").unwrap();
+        fs::write(dir.join("b.txt"), "untracked\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+        fs::write(dir.join("a.txt"), "new line\n").unwrap();
         let mut app = make_app(&tmp);
-        let path = app.entries()[0].path.clone();
-        app.tab_mut().selected.insert(path.clone());
-        assert_eq!(app.selection_count(), 1);
-        app.tab_mut().selected.remove(&path);
-        assert_eq!(app.selection_count(), 0);
+        press(&mut app, 'g');
+        press(&mut app, 'd');
+        assert_eq!(app.status_message.as_deref(), Some("Git diff preview ON"));
+        wait_for_preview(&mut app);
+        let lines = app.preview_lines();
+        assert_eq!(lines[0].text, "± Diff: a.txt");
+        let removed = lines.iter().find(|l| l.text == "-old line").unwrap();
+        assert_eq!(removed.style, PreviewStyle::Removed);
+        let added = lines.iter().find(|l| l.text == "+new line").unwrap();
+        assert_eq!(added.style, PreviewStyle::Added);
+
+        // Files git has nothing to diff for keep their normal preview
+        press(&mut app, 'j');
+        wait_for_preview(&mut app);
+        assert!(app.preview_lines()[0].text.contains("untracked"));
+
+        press(&mut app, 'k');
+        press(&mut app, 'g');
+        press(&mut app, 'd');
+        wait_for_preview(&mut app);
+        assert!(app.preview_lines()[0].text.contains("new line"));
     }
 
-    #[test]
-    fn test_file_count() {
-        let tmp = TempDir::new().unwrap();
-        fs::write(tmp.path().join("a.txt"), "").unwrap();
-        fs::write(tmp.path().join("b.txt"), "").unwrap();
-        let app = make_app(&tmp);
-        assert_eq!(app.file_count(), 2);
+    /// Commit the whole worktree on top of HEAD
+    fn git_commit(repo: &git2::Repository, message: &str) {
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = git2::Signature::now("Ada", "ada@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let parents: Vec<_> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents)
+            .unwrap();
     }
 
     #[test]
-    fn test_bookmarks() {
+    fn test_git_log_view() {
         let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        let repo = git2::Repository::init(&dir).unwrap();
+        fs::write(dir.join("a.txt"), "first version\n").unwrap();
+        git_commit(&repo, "Add a");
+        fs::write(dir.join("a.txt"), "second version\n").unwrap();
+        git_commit(&repo, "Rewrite a");
         let mut app = make_app(&tmp);
-        app.bookmarks.insert('a', tmp.path().to_path_buf());
-        assert_eq!(app.bookmarks.get(&'a'), Some(&tmp.path().to_path_buf()));
+
+        press(&mut app, 'g');
+        press(&mut app, 'l');
+        assert!(app
+            .status_message
+            .as_deref()
+            .unwrap()
+            .starts_with("Reading"));
+        wait_for_git_log(&mut app);
+        assert_eq!(app.input_mode, InputMode::GitLog);
+        let subjects: Vec<_> = app.git_log.iter().map(|c| c.subject.as_str()).collect();
+        assert_eq!(subjects, ["Rewrite a", "Add a"]);
+        let ids: Vec<_> = app.git_log.iter().map(|c| c.id.clone()).collect();
+        wait_for_preview(&mut app);
+        assert!(app.preview_lines()[0].text.contains("second version"));
+
+        // Moving down previews the file as of the older commit
+        press(&mut app, 'j');
+        wait_for_preview(&mut app);
+        assert!(app.preview_lines()[0].text.contains("first version"));
+
+        press(&mut app, 'g');
+        press(&mut app, 'b');
+        wait_for_preview(&mut app);
+        let lines = app.preview_lines();
+        assert!(lines[0].text.starts_with("± Blame: a.txt @ "));
+        assert!(lines[1].text.contains("Ada"));
+        assert!(lines[1].text.ends_with("│ first version"));
+
+        app.handle_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.input_mode, InputMode::Normal);
+        assert!(app.git_log.is_empty());
+        // Blame was only on for the log view
+        assert_eq!(app.tab().git_preview, GitPreview::Contents);
+        wait_for_preview(&mut app);
+        assert!(app.preview_lines()[0].text.contains("second version"));
+
+        fs::write(dir.join("new.txt"), "untracked").unwrap();
+        app.tab_mut().refresh().unwrap();
+        press(&mut app, 'j');
+        press(&mut app, 'g');
+        press(&mut app, 'l');
+        wait_for_git_log(&mut app);
+        assert_eq!(app.input_mode, InputMode::Normal);
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("No commits touch new.txt")
+        );
+        for id in ids {
+            assert!(dir
+                .join(".cache")
+                .join(git_status::REVISIONS_DIR)
+                .join(id)
+                .join("a.txt")
+                .exists());
+        }
     }
 
     #[test]
-    fn test_yank_and_paste() {
+    fn test_quit_waits_for_running_jobs() {
         let tmp = TempDir::new().unwrap();
-        fs::write(tmp.path().join("a.txt"), "content").unwrap();
-        let dest = tmp.path().join("sub");
-        fs::create_dir(&dest).unwrap();
         let mut app = make_app(&tmp);
-        app.yank_selected();
-        assert!(app.pending_op.is_some());
+        app.jobs
+            .spawn(JobKind::Move, "slow".to_string(), |progress| {
+                while !progress.is_cancelled() {
+                    std::thread::sleep(std::time::Duration::from_millis(2));
+                }
+                JobOutcome::default()
+            });
+        let ctrl = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+        for quit in [
+            KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE),
+            ctrl('c'),
+            ctrl('w'),
+        ] {
+            assert!(!app.handle_key(quit).unwrap());
+            assert!(app
+                .status_message
+                .as_deref()
+                .unwrap()
+                .contains("still running"));
+            assert!(app.handle_key(quit).unwrap());
+            // Anything else in between disarms it again
+            press(&mut app, 'j');
+        }
+        app.jobs.jobs[0].progress.cancel();
+        finish_jobs(&mut app);
+        assert!(app.handle_key(ctrl('c')).unwrap());
     }
 
     #[test]
@@ -1774,12 +4527,15 @@ mod tests {
 
     // Archive tests
     #[test]
-    fn test_is_archive() {
-        assert!(file_ops::is_archive(std::path::Path::new("test.zip")));
-        assert!(file_ops::is_archive(std::path::Path::new("test.tar.gz")));
-        assert!(file_ops::is_archive(std::path::Path::new("test.tgz")));
-        assert!(file_ops::is_archive(std::path::Path::new("test.tar")));
-        assert!(!file_ops::is_archive(std::path::Path::new("test.txt")));
+    fn test_detect_archive_by_name() {
+        assert!(file_ops::detect_archive(std::path::Path::new("test.zip")).is_some());
+        assert!(file_ops::detect_archive(std::path::Path::new("test.tar.gz")).is_some());
+        assert!(file_ops::detect_archive(std::path::Path::new("test.tgz")).is_some());
+        assert!(file_ops::detect_archive(std::path::Path::new("test.tar")).is_some());
+        assert!(file_ops::detect_archive(std::path::Path::new("test.tar.zst")).is_some());
+        assert!(file_ops::detect_archive(std::path::Path::new("test.txz")).is_some());
+        assert!(file_ops::detect_archive(std::path::Path::new("notes.bz2")).is_some());
+        assert!(file_ops::detect_archive(std::path::Path::new("test.txt")).is_none());
     }
 
     #[test]
@@ -1790,14 +4546,16 @@ mod tests {
         fs::write(dir.join("b.txt"), "world").unwrap();
         let archive = dir.join("test.zip");
         let sources = vec![dir.join("a.txt"), dir.join("b.txt")];
-        let count = file_ops::compress_zip(&sources, &archive).unwrap();
+        let count = file_ops::compress_zip(&sources, &archive, &Progress::default()).unwrap();
         assert_eq!(count, 2);
         assert!(archive.exists());
         // Extract
         let extract_dir = dir.join("extracted");
         fs::create_dir(&extract_dir).unwrap();
-        let files = file_ops::extract_archive(&archive, &extract_dir).unwrap();
-        assert_eq!(files.len(), 2);
+        let report =
+            file_ops::extract_archive(&archive, &extract_dir, &Progress::default()).unwrap();
+        assert_eq!(report.extracted.len(), 2);
+        assert!(report.skipped.is_empty());
         assert_eq!(
             fs::read_to_string(extract_dir.join("a.txt")).unwrap(),
             "hello"
@@ -1811,13 +4569,15 @@ mod tests {
         fs::write(dir.join("c.txt"), "data").unwrap();
         let archive = dir.join("test.tar.gz");
         let sources = vec![dir.join("c.txt")];
-        let count = file_ops::compress_tar_gz(&sources, &archive).unwrap();
+        let count =
+            file_ops::compress_tar(&sources, &archive, Codec::Gzip, &Progress::default()).unwrap();
         assert_eq!(count, 1);
         assert!(archive.exists());
         let extract_dir = dir.join("out");
         fs::create_dir(&extract_dir).unwrap();
-        let files = file_ops::extract_archive(&archive, &extract_dir).unwrap();
-        assert!(!files.is_empty());
+        let report =
+            file_ops::extract_archive(&archive, &extract_dir, &Progress::default()).unwrap();
+        assert!(!report.extracted.is_empty());
     }
 
     #[test]
@@ -1828,7 +4588,7 @@ mod tests {
         fs::create_dir(&sub).unwrap();
         fs::write(sub.join("inner.txt"), "inside").unwrap();
         let archive = dir.join("dir.zip");
-        let count = file_ops::compress_zip(&[sub], &archive).unwrap();
+        let count = file_ops::compress_zip(&[sub], &archive, &Progress::default()).unwrap();
         assert!(count >= 1);
         assert!(archive.exists());
     }
@@ -1838,10 +4598,32 @@ mod tests {
         let result = file_ops::extract_archive(
             std::path::Path::new("test.txt"),
             std::path::Path::new("/tmp"),
+            &Progress::default(),
         );
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_extract_summary_lists_skipped() {
+        let report = file_ops::ExtractReport {
+            extracted: vec!["a".to_string()],
+            skipped: ["../x", "/y", "../../z", "../w"].map(String::from).to_vec(),
+            ..Default::default()
+        };
+        assert_eq!(
+            extract_summary("evil.zip", &report),
+            "Extracted 1 entries from evil.zip — skipped 4 unsafe: ../x, /y, ../../z, …"
+        );
+        let clean = file_ops::ExtractReport {
+            extracted: vec!["a".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            extract_summary("ok.zip", &clean),
+            "Extracted 1 entries from ok.zip"
+        );
+    }
+
     #[test]
     fn test_mouse_scroll() {
         let tmp = TempDir::new().unwrap();
@@ -1934,6 +4716,7 @@ mod tests {
         app.input_buffer = "needle".to_string();
         app.handle_search_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
             .unwrap();
+        finish_jobs(&mut app);
         assert_eq!(app.input_mode, InputMode::SearchResults);
         assert!(!app.search_results.is_empty());
     }
@@ -1947,10 +4730,88 @@ mod tests {
         app.input_buffer = "zzzznotfound".to_string();
         app.handle_search_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
             .unwrap();
+        finish_jobs(&mut app);
         assert_eq!(app.input_mode, InputMode::Normal);
         assert!(app.search_results.is_empty());
     }
 
+    #[test]
+    fn test_search_prompt_options() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("a.txt"), "foo = 1\nfood = 2").unwrap();
+        let mut app = make_app(&tmp);
+        press(&mut app, 'F');
+        assert_eq!(app.input_mode, InputMode::Search);
+        let alt = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::ALT);
+        app.handle_key(alt('r')).unwrap();
+        app.handle_key(alt('w')).unwrap();
+        assert_eq!(
+            app.search_prompt(),
+            "Search [regex word] (Alt-r/c/w, Tab: files): "
+        );
+        // Alt-c cycles insensitive, smart and sensitive case
+        app.handle_key(alt('c')).unwrap();
+        assert_eq!(app.search_options.case, file_ops::CaseMode::Smart);
+        app.handle_key(alt('c')).unwrap();
+        assert!(app.search_prompt().starts_with("Search [regex case word]"));
+        app.handle_key(alt('c')).unwrap();
+        assert_eq!(app.search_options.case, file_ops::CaseMode::Insensitive);
+
+        // A broken regex keeps the prompt open for fixing
+        app.input_buffer = "fo(".to_string();
+        app.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.input_mode, InputMode::Search);
+        assert!(app
+            .status_message
+            .as_deref()
+            .unwrap()
+            .starts_with("Invalid pattern"));
+
+        app.input_buffer = "fo+".to_string();
+        app.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
+            .unwrap();
+        finish_jobs(&mut app);
+        assert_eq!(app.input_mode, InputMode::SearchResults);
+        assert_eq!(app.search_results.len(), 1);
+        assert_eq!(app.search_results[0].matches.len(), 1);
+        assert_eq!(app.search_results[0].matches[0], 0..3);
+        // The options stay for the next search
+        assert!(app.search_options.regex && app.search_options.whole_word);
+    }
+
+    #[test]
+    fn test_search_prompt_globs() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("a.rs"), "needle").unwrap();
+        fs::write(tmp.path().join("b.txt"), "needle").unwrap();
+        let mut app = make_app(&tmp);
+        press(&mut app, 'F');
+        for c in "needle".chars() {
+            press(&mut app, c);
+        }
+        app.handle_key(KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE))
+            .unwrap();
+        assert!(app.search_globs_focus);
+        for c in "*.rs".chars() {
+            press(&mut app, c);
+        }
+        assert_eq!(app.input_buffer, "needle");
+        assert_eq!(app.search_globs, "*.rs");
+        app.handle_key(KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE))
+            .unwrap();
+        assert!(app.search_prompt().ends_with("needle │ files: *.rs"));
+
+        app.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
+            .unwrap();
+        finish_jobs(&mut app);
+        assert_eq!(app.search_results.len(), 1);
+        assert!(app.search_results[0].path.ends_with("a.rs"));
+        // The globs stay for the next search
+        assert_eq!(app.search_globs, "*.rs");
+        assert!(!app.search_globs_focus);
+    }
+
     #[test]
     fn test_search_results_navigation() {
         let tmp = TempDir::new().unwrap();
@@ -1961,6 +4822,7 @@ mod tests {
         app.input_buffer = "line".to_string();
         app.handle_search_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
             .unwrap();
+        finish_jobs(&mut app);
         assert_eq!(app.input_mode, InputMode::SearchResults);
         assert!(app.search_results.len() >= 3);
         assert_eq!(app.search_cursor, 0);
@@ -1974,6 +4836,34 @@ mod tests {
         assert_eq!(app.search_cursor, 0);
     }
 
+    #[test]
+    fn test_search_runs_as_job() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("a.txt"), "alpha\nbeta").unwrap();
+        let mut app = make_app(&tmp);
+        for pattern in ["alpha", "beta"] {
+            app.input_mode = InputMode::Search;
+            app.input_buffer = pattern.to_string();
+            app.handle_search_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
+                .unwrap();
+            assert_eq!(app.input_mode, InputMode::Normal);
+        }
+        // Starting the second search cancelled the first
+        assert!(app.jobs.jobs[0].progress.is_cancelled());
+        assert_eq!(app.jobs.jobs[1].kind, JobKind::Search);
+        finish_jobs(&mut app);
+        assert_eq!(app.input_mode, InputMode::SearchResults);
+        assert_eq!(app.search_results.len(), 1);
+        assert_eq!(app.search_results[0].line_text, "beta");
+        // Whether it finished or was cut short, the first search is dropped
+        assert!(app
+            .status_message
+            .as_deref()
+            .unwrap()
+            .starts_with("1 results for \"beta\""));
+        assert!(app.search_job.is_none());
+    }
+
     #[test]
     fn test_search_results_esc() {
         let tmp = TempDir::new().unwrap();
@@ -1984,6 +4874,7 @@ mod tests {
             path: dir.join("a.txt"),
             line_number: 1,
             line_text: "test content".to_string(),
+            matches: Vec::new(),
         }];
         app.search_cursor = 0;
         app.input_mode = InputMode::SearchResults;
@@ -2005,6 +4896,7 @@ mod tests {
             path: sub.join("target.txt"),
             line_number: 1,
             line_text: "found it".to_string(),
+            matches: Vec::new(),
         }];
         app.search_cursor = 0;
         app.input_mode = InputMode::SearchResults;
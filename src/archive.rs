@@ -0,0 +1,343 @@
+use crate::file_ops::{self, ArchiveKind};
+use crate::jobs::Progress;
+use chrono::TimeZone;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Directory of velo's cache holding extracted archive entries
+pub const SCRATCH_DIR: &str = "archives";
+
+/// Larger entries are not extracted just to preview them
+pub const PREVIEW_EXTRACT_LIMIT: u64 = 16 * 1024 * 1024; // 16 MB
+
+/// One file or directory inside an archive
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+    /// Path inside the archive, without `./` or a trailing `/`
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub is_symlink: bool,
+    pub symlink_target: Option<String>,
+    pub size: u64,
+    /// Stored size, where the archive compresses entries one by one (zip)
+    pub compressed_size: Option<u64>,
+    pub modified: Option<SystemTime>,
+}
+
+impl ArchiveEntry {
+    fn dir(path: PathBuf) -> Self {
+        Self {
+            path,
+            is_dir: true,
+            is_symlink: false,
+            symlink_target: None,
+            size: 0,
+            compressed_size: None,
+            modified: None,
+        }
+    }
+}
+
+/// Table of contents of a zip or tar archive, read once when it is opened
+/// so it can be browsed like a read-only directory
+#[derive(Debug)]
+pub struct ArchiveIndex {
+    /// The archive file on disk
+    pub path: PathBuf,
+    pub kind: ArchiveKind,
+    modified: Option<SystemTime>,
+    /// Size of the archive file itself
+    pub size: u64,
+    /// Sorted by path; directories missing from the archive are filled in
+    entries: Vec<ArchiveEntry>,
+}
+
+impl ArchiveIndex {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let meta = fs::metadata(path).map_err(|e| e.to_string())?;
+        let (kind, listed) = match file_ops::detect_archive(path) {
+            Some(kind @ ArchiveKind::Zip) => (kind, read_zip_index(path)?),
+            Some(kind @ ArchiveKind::Tar(codec)) => {
+                let file = fs::File::open(path).map_err(|e| e.to_string())?;
+                let reader = codec.decoder(file).map_err(|e| e.to_string())?;
+                (kind, read_tar_index(tar::Archive::new(reader))?)
+            }
+            _ => return Err("Not a browsable archive".to_string()),
+        };
+        let mut by_path = BTreeMap::new();
+        for entry in listed {
+            for parent in entry.path.ancestors().skip(1) {
+                if !parent.as_os_str().is_empty() && !by_path.contains_key(parent) {
+                    by_path.insert(
+                        parent.to_path_buf(),
+                        ArchiveEntry::dir(parent.to_path_buf()),
+                    );
+                }
+            }
+            // A later entry for the same path replaces the earlier one,
+            // as it would on extraction
+            by_path.insert(entry.path.clone(), entry);
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            kind,
+            modified: meta.modified().ok(),
+            size: meta.len(),
+            entries: by_path.into_values().collect(),
+        })
+    }
+
+    /// Whether the archive file changed since it was indexed
+    pub fn is_stale(&self) -> bool {
+        match fs::metadata(&self.path) {
+            Ok(meta) => meta.modified().ok() != self.modified || meta.len() != self.size,
+            Err(_) => true,
+        }
+    }
+
+    /// Every entry, sorted by path
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    pub fn entry(&self, inner: &Path) -> Option<&ArchiveEntry> {
+        self.entries
+            .binary_search_by(|e| e.path.as_path().cmp(inner))
+            .ok()
+            .map(|i| &self.entries[i])
+    }
+
+    /// Entries directly inside `inner_dir`; an empty path is the root
+    pub fn children<'a>(&'a self, inner_dir: &'a Path) -> impl Iterator<Item = &'a ArchiveEntry> {
+        self.entries
+            .iter()
+            .filter(move |e| e.path.parent() == Some(inner_dir))
+    }
+
+    /// The part of a virtual path below this archive, `""` for the root
+    pub fn inner_path<'a>(&self, path: &'a Path) -> Option<&'a Path> {
+        path.strip_prefix(&self.path).ok()
+    }
+
+    /// Private copy of this version of the archive's extracted entries,
+    /// under velo's `cache_dir`
+    fn scratch_dir(&self, cache_dir: &Path) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        self.path.hash(&mut hasher);
+        self.modified.hash(&mut hasher);
+        self.size.hash(&mut hasher);
+        cache_dir
+            .join(SCRATCH_DIR)
+            .join(format!("{:016x}", hasher.finish()))
+    }
+
+    /// Where [`Self::materialize`] puts `inner`
+    pub fn scratch_path(&self, cache_dir: &Path, inner: &Path) -> PathBuf {
+        self.scratch_dir(cache_dir).join(inner)
+    }
+
+    /// Extract `inner` into the scratch directory, unless an earlier call
+    /// already did, and return its real path there
+    pub fn materialize(&self, cache_dir: &Path, inner: &Path) -> Result<PathBuf, String> {
+        let target = self.scratch_path(cache_dir, inner);
+        if target.symlink_metadata().is_ok() {
+            return Ok(target);
+        }
+        let parent = target.parent().ok_or("Invalid entry")?;
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        // Extract under a temporary name so an interrupted run is not
+        // mistaken for a finished one next time
+        let name = target.file_name().ok_or("Invalid entry")?;
+        let partial = parent.join(format!(".{}.part", name.to_string_lossy()));
+        let _ = fs::remove_dir_all(&partial).or_else(|_| fs::remove_file(&partial));
+        file_ops::extract_subtree(&self.path, inner, &partial, &Progress::default())?;
+        fs::rename(&partial, &target).map_err(|e| e.to_string())?;
+        Ok(target)
+    }
+}
+
+fn read_zip_index(path: &Path) -> Result<Vec<ArchiveEntry>, String> {
+    let file = fs::File::open(path).map_err(|e| e.to_string())?;
+    let mut zip = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+    let mut entries = Vec::with_capacity(zip.len());
+    for i in 0..zip.len() {
+        let entry = zip.by_index_raw(i).map_err(|e| e.to_string())?;
+        let name = entry.name().replace('\\', "/");
+        // Entries extraction would refuse are not shown either
+        let Some(path) = file_ops::entry_path(Path::new(&name)) else {
+            continue;
+        };
+        entries.push(ArchiveEntry {
+            path,
+            is_dir: entry.is_dir(),
+            is_symlink: entry.is_symlink(),
+            symlink_target: None,
+            size: entry.size(),
+            compressed_size: Some(entry.compressed_size()),
+            modified: entry.last_modified().and_then(zip_time),
+        });
+    }
+    Ok(entries)
+}
+
+/// Zip timestamps are in local time without a zone
+fn zip_time(t: zip::DateTime) -> Option<SystemTime> {
+    let naive = chrono::NaiveDate::from_ymd_opt(t.year().into(), t.month().into(), t.day().into())?
+        .and_hms_opt(t.hour().into(), t.minute().into(), t.second().into())?;
+    let local = chrono::Local.from_local_datetime(&naive).earliest()?;
+    Some(SystemTime::from(local))
+}
+
+fn read_tar_index<R: std::io::Read>(mut tar: tar::Archive<R>) -> Result<Vec<ArchiveEntry>, String> {
+    let mut entries = Vec::new();
+    for entry in tar.entries().map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let raw = entry.path().map_err(|e| e.to_string())?;
+        let Some(path) = file_ops::entry_path(&raw) else {
+            continue;
+        };
+        let header = entry.header();
+        let kind = header.entry_type();
+        let symlink_target = if kind.is_symlink() {
+            entry
+                .link_name()
+                .ok()
+                .flatten()
+                .map(|t| t.display().to_string())
+        } else {
+            None
+        };
+        entries.push(ArchiveEntry {
+            path,
+            is_dir: kind.is_dir(),
+            is_symlink: kind.is_symlink(),
+            symlink_target,
+            size: header.size().unwrap_or(0),
+            compressed_size: None,
+            modified: header
+                .mtime()
+                .ok()
+                .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs)),
+        });
+    }
+    Ok(entries)
+}
+
+/// Whether `path` is an archive that can be browsed like a directory
+pub fn is_browsable(path: &Path) -> bool {
+    path.is_file()
+        && matches!(
+            file_ops::detect_archive(path),
+            Some(ArchiveKind::Zip | ArchiveKind::Tar(_))
+        )
+}
+
+/// Split a path that runs through an archive, such as
+/// `/src/lib.zip/util/mod.rs`, into the archive and the path inside it
+pub fn split_virtual(path: &Path) -> Option<(PathBuf, PathBuf)> {
+    for ancestor in path.ancestors().skip(1) {
+        match fs::metadata(ancestor) {
+            Ok(meta) if meta.is_file() && is_browsable(ancestor) => {
+                let inner = path.strip_prefix(ancestor).ok()?;
+                return Some((ancestor.to_path_buf(), inner.to_path_buf()));
+            }
+            // A real directory or plain file: nothing virtual about it
+            Ok(_) => return None,
+            Err(_) => continue,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    fn sample_zip(dir: &Path) -> PathBuf {
+        let path = dir.join("sample.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("readme.txt", options).unwrap();
+        zip.write_all(b"hello").unwrap();
+        // No explicit entry for `src/`; the index fills it in
+        zip.start_file("src/main.rs", options).unwrap();
+        zip.write_all(b"fn main() {}").unwrap();
+        zip.start_file("../evil.txt", options).unwrap();
+        zip.write_all(b"x").unwrap();
+        zip.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn test_zip_index() {
+        let tmp = TempDir::new().unwrap();
+        let index = ArchiveIndex::open(&sample_zip(tmp.path())).unwrap();
+        let root: Vec<_> = index
+            .children(Path::new(""))
+            .map(|e| e.path.display().to_string())
+            .collect();
+        assert_eq!(root, vec!["readme.txt", "src"]);
+        assert!(index.entry(Path::new("src")).unwrap().is_dir);
+        let main = index.entry(Path::new("src/main.rs")).unwrap();
+        assert_eq!(main.size, 12);
+        assert!(main.modified.is_some());
+        assert!(!index.is_stale());
+    }
+
+    #[test]
+    fn test_tar_index() {
+        let tmp = TempDir::new().unwrap();
+        let src = tmp.path().join("pkg");
+        fs::create_dir(&src).unwrap();
+        fs::write(src.join("a.txt"), "abc").unwrap();
+        let archive = tmp.path().join("pkg.tar.zst");
+        file_ops::compress_tar(
+            &[src],
+            &archive,
+            file_ops::Codec::Zstd,
+            &Progress::default(),
+        )
+        .unwrap();
+        let index = ArchiveIndex::open(&archive).unwrap();
+        let inner: Vec<_> = index
+            .children(Path::new("pkg"))
+            .map(|e| (e.path.display().to_string(), e.size))
+            .collect();
+        assert_eq!(inner, vec![("pkg/a.txt".to_string(), 3)]);
+    }
+
+    #[test]
+    fn test_materialize_entry() {
+        let tmp = TempDir::new().unwrap();
+        let index = ArchiveIndex::open(&sample_zip(tmp.path())).unwrap();
+        let cache = tmp.path().join("cache");
+        let main = Path::new("src/main.rs");
+        let path = index.materialize(&cache, main).unwrap();
+        assert!(path.starts_with(cache.join(SCRATCH_DIR)));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fn main() {}");
+        assert_eq!(path, index.scratch_path(&cache, main));
+        // A second call reuses the extracted copy
+        assert_eq!(index.materialize(&cache, main).unwrap(), path);
+        assert!(index.materialize(&cache, Path::new("missing.txt")).is_err());
+    }
+
+    #[test]
+    fn test_split_virtual() {
+        let tmp = TempDir::new().unwrap();
+        let archive = sample_zip(tmp.path());
+        assert_eq!(
+            split_virtual(&archive.join("src/main.rs")),
+            Some((archive.clone(), PathBuf::from("src/main.rs")))
+        );
+        assert_eq!(split_virtual(&archive), None);
+        assert_eq!(split_virtual(&tmp.path().join("missing/file")), None);
+        fs::write(tmp.path().join("plain.txt"), "x").unwrap();
+        assert_eq!(split_virtual(&tmp.path().join("plain.txt/inner")), None);
+    }
+}
@@ -11,10 +11,37 @@ pub struct Config {
     pub sort_by: SortBy,
     #[serde(default)]
     pub colors: ColorConfig,
+    /// Action name → key sequences, e.g. `move_down = "j <Down>"`
     #[serde(default)]
     pub keybinds: HashMap<String, String>,
     #[serde(default = "default_theme")]
     pub theme: ThemeName,
+    /// Syntect theme for code previews; follows `theme` when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub syntax_theme: Option<String>,
+    #[serde(default)]
+    pub preview: PreviewConfig,
+}
+
+/// Limits of the preview pane, under `[preview]`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreviewConfig {
+    /// Lines shown from the top of a file, and entries of a directory or
+    /// archive
+    #[serde(default = "default_preview_max_lines")]
+    pub max_lines: usize,
+    /// Lines also shown from the end of files too long to show whole
+    #[serde(default)]
+    pub tail_lines: usize,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        Self {
+            max_lines: default_preview_max_lines(),
+            tail_lines: 0,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -70,6 +97,9 @@ fn default_selected_color() -> String {
 fn default_theme() -> ThemeName {
     ThemeName::Default
 }
+fn default_preview_max_lines() -> usize {
+    100
+}
 
 impl Default for Config {
     fn default() -> Self {
@@ -79,6 +109,8 @@ impl Default for Config {
             colors: ColorConfig::default(),
             keybinds: HashMap::new(),
             theme: ThemeName::Default,
+            syntax_theme: None,
+            preview: PreviewConfig::default(),
         }
     }
 }
@@ -102,6 +134,13 @@ impl Config {
             .join("velo")
             .join("config.toml")
     }
+
+    /// Where velo keeps scratch copies and overwrite backups
+    pub fn cache_dir() -> PathBuf {
+        dirs::cache_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("velo")
+    }
 }
 
 #[cfg(test)]
@@ -129,6 +168,35 @@ mod tests {
         assert_eq!(config.colors.directory, "green");
     }
 
+    #[test]
+    fn test_config_keybinds() {
+        let toml_str = r#"
+            [keybinds]
+            move_down = "J <Down>"
+            delete = ""
+        "#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.keybinds.get("move_down").unwrap(), "J <Down>");
+        assert_eq!(config.keybinds.get("delete").unwrap(), "");
+    }
+
+    #[test]
+    fn test_config_syntax_theme() {
+        let config: Config = toml::from_str("theme = \"nord\"").unwrap();
+        assert_eq!(config.syntax_theme, None);
+        let config: Config = toml::from_str("syntax_theme = \"InspiredGitHub\"").unwrap();
+        assert_eq!(config.syntax_theme.as_deref(), Some("InspiredGitHub"));
+    }
+
+    #[test]
+    fn test_config_preview_limits() {
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.preview, PreviewConfig::default());
+        let config: Config = toml::from_str("[preview]\ntail_lines = 20").unwrap();
+        assert_eq!(config.preview.max_lines, 100);
+        assert_eq!(config.preview.tail_lines, 20);
+    }
+
     #[test]
     fn test_config_serialize() {
         let config = Config::default();
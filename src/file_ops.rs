@@ -1,5 +1,17 @@
+use crate::jobs::Progress;
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::{WalkBuilder, WalkState};
+use regex::{Regex, RegexBuilder};
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::io::{self, BufRead, Read, Write};
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Buffer size for chunked copies, small enough for smooth progress
+const COPY_CHUNK: usize = 256 * 1024;
 
 #[derive(Debug, Clone)]
 pub struct PendingOp {
@@ -8,28 +20,215 @@ pub struct PendingOp {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[allow(dead_code)]
 pub enum OpKind {
     Copy,
     Move,
 }
 
-pub fn copy_file(src: &Path, dest_dir: &Path) -> Result<PathBuf, String> {
-    let file_name = src.file_name().ok_or_else(|| "No filename".to_string())?;
-    let dest = dest_dir.join(file_name);
+/// Copy a file or directory to the exact path `dest`, reporting bytes and
+/// files to `progress` and stopping (without leaving a partial file
+/// behind) once it is cancelled
+pub fn copy_with_progress(src: &Path, dest: &Path, progress: &Progress) -> Result<(), String> {
     if src.is_dir() {
-        copy_dir_recursive(src, &dest).map_err(|e| e.to_string())?;
+        copy_dir_recursive(src, dest, progress).map_err(|e| e.to_string())
     } else {
-        fs::copy(src, &dest).map_err(|e| e.to_string())?;
+        copy_contents(src, dest, progress).map_err(|e| e.to_string())
     }
-    Ok(dest)
 }
 
-pub fn move_file(src: &Path, dest_dir: &Path) -> Result<PathBuf, String> {
-    let file_name = src.file_name().ok_or_else(|| "No filename".to_string())?;
-    let dest = dest_dir.join(file_name);
-    fs::rename(src, &dest).map_err(|e| e.to_string())?;
-    Ok(dest)
+/// Copy one file's bytes and permission bits in chunks
+fn copy_contents(src: &Path, dest: &Path, progress: &Progress) -> io::Result<()> {
+    let mut reader = fs::File::open(src)?;
+    let mut writer = fs::File::create(dest)?;
+    let mut buf = vec![0; COPY_CHUNK];
+    loop {
+        if progress.is_cancelled() {
+            drop(writer);
+            let _ = fs::remove_file(dest);
+            return Err(io::Error::other("Cancelled"));
+        }
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        writer.write_all(&buf[..n])?;
+        progress.add_bytes(n as u64);
+    }
+    fs::set_permissions(dest, reader.metadata()?.permissions())?;
+    progress.add_files(1);
+    Ok(())
+}
+
+/// First free path for `name` in `dir`: `name`, then `name (1).ext`, `name (2).ext`, …
+pub fn unique_dest(dir: &Path, name: &str) -> PathBuf {
+    unique_dest_except(dir, name, |_| false)
+}
+
+/// [`unique_dest`], also passing over paths `reserved` for something else
+pub fn unique_dest_except(dir: &Path, name: &str, reserved: impl Fn(&Path) -> bool) -> PathBuf {
+    let free = |p: &Path| p.symlink_metadata().is_err() && !reserved(p);
+    let candidate = dir.join(name);
+    if free(&candidate) {
+        return candidate;
+    }
+    let path = Path::new(name);
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| name.to_string());
+    let ext = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| dir.join(format!("{stem} ({n}){ext}")))
+        .find(|p| free(p))
+        .expect("unbounded range always yields a free name")
+}
+
+/// Move `src` to `dest`. A plain rename is tried first; when the two paths
+/// are on different filesystems (EXDEV) the tree is copied with its
+/// metadata, verified, and only then is the source removed.
+pub fn move_path(src: &Path, dest: &Path) -> Result<(), String> {
+    move_with_progress(src, dest, &Progress::default())
+}
+
+/// [`move_path`], reporting to `progress` while a cross-device copy runs.
+/// A plain rename reports nothing; callers account for it themselves.
+pub fn move_with_progress(src: &Path, dest: &Path, progress: &Progress) -> Result<(), String> {
+    match fs::rename(src, dest) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => move_by_copy(src, dest, progress),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn move_by_copy(src: &Path, dest: &Path, progress: &Progress) -> Result<(), String> {
+    if dest.symlink_metadata().is_ok() {
+        return Err(format!("{} already exists", dest.display()));
+    }
+    let copied = copy_preserving(src, dest, progress)
+        .map_err(|e| e.to_string())
+        .and_then(|_| verify_copy(src, dest));
+    if let Err(e) = copied {
+        // Never leave a half-copied tree behind; the source is untouched
+        let _ = remove_path(dest);
+        return Err(format!("Move failed, source kept: {e}"));
+    }
+    remove_path(src).map_err(|e| format!("Copied, but could not remove source: {e}"))
+}
+
+/// Copy a file, directory or symlink, keeping permissions and timestamps
+fn copy_preserving(src: &Path, dest: &Path, progress: &Progress) -> io::Result<()> {
+    let meta = fs::symlink_metadata(src)?;
+    let file_type = meta.file_type();
+    if file_type.is_symlink() {
+        let target = fs::read_link(src)?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&target, dest)?;
+        #[cfg(not(unix))]
+        {
+            let _ = target;
+            fs::copy(src, dest)?;
+        }
+        return Ok(());
+    }
+    if file_type.is_dir() {
+        fs::create_dir(dest)?;
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            copy_preserving(&entry.path(), &dest.join(entry.file_name()), progress)?;
+        }
+        // Set directory metadata last so adding children doesn't bump mtime
+        fs::set_permissions(dest, meta.permissions())?;
+        let _ = set_times(dest, &meta);
+    } else {
+        copy_contents(src, dest, progress)?;
+        set_times(dest, &meta)?;
+    }
+    Ok(())
+}
+
+fn set_times(path: &Path, meta: &fs::Metadata) -> io::Result<()> {
+    let mut times = fs::FileTimes::new();
+    if let Ok(modified) = meta.modified() {
+        times = times.set_modified(modified);
+    }
+    if let Ok(accessed) = meta.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    // Read-only files can't be opened for writing; a read handle is
+    // enough for futimens on unix
+    let file = fs::File::open(path).or_else(|_| fs::OpenOptions::new().write(true).open(path))?;
+    file.set_times(times)
+}
+
+/// Check that `dest` mirrors `src`: same entries, types and file sizes
+fn verify_copy(src: &Path, dest: &Path) -> Result<(), String> {
+    let src_meta = fs::symlink_metadata(src).map_err(|e| e.to_string())?;
+    let dest_meta = fs::symlink_metadata(dest).map_err(|e| e.to_string())?;
+    let mismatch = || format!("copy of {} does not match source", src.display());
+    if src_meta.file_type().is_symlink() {
+        return if dest_meta.file_type().is_symlink() || cfg!(not(unix)) {
+            Ok(())
+        } else {
+            Err(mismatch())
+        };
+    }
+    if src_meta.is_dir() != dest_meta.is_dir() {
+        return Err(mismatch());
+    }
+    if src_meta.is_dir() {
+        let mut count = 0;
+        for entry in fs::read_dir(src).map_err(|e| e.to_string())? {
+            let entry = entry.map_err(|e| e.to_string())?;
+            verify_copy(&entry.path(), &dest.join(entry.file_name()))?;
+            count += 1;
+        }
+        let dest_count = fs::read_dir(dest).map_err(|e| e.to_string())?.count();
+        if count != dest_count {
+            return Err(mismatch());
+        }
+    } else if src_meta.len() != dest_meta.len() {
+        return Err(mismatch());
+    }
+    Ok(())
+}
+
+/// Remove a file, symlink or whole directory tree
+pub fn remove_path(path: &Path) -> io::Result<()> {
+    let meta = fs::symlink_metadata(path)?;
+    if meta.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Trim a cache directory to its `keep` newest entries, removing any
+/// older than `max_age` as well
+pub fn prune_cache(dir: &Path, keep: usize, max_age: Duration) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<(PathBuf, SystemTime)> = entries
+        .flatten()
+        .map(|e| {
+            let modified = e
+                .metadata()
+                .and_then(|m| m.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            (e.path(), modified)
+        })
+        .collect();
+    entries.sort_by_key(|(_, modified)| std::cmp::Reverse(*modified));
+    let now = SystemTime::now();
+    for (i, (path, modified)) in entries.iter().enumerate() {
+        let age = now.duration_since(*modified).unwrap_or_default();
+        if i >= keep || age > max_age {
+            let _ = remove_path(path);
+        }
+    }
 }
 
 pub fn delete_to_trash(path: &Path) -> Result<(), String> {
@@ -146,162 +345,915 @@ pub fn chmod_file(path: &Path, mode_str: &str) -> Result<(), Box<dyn std::error:
     }
 }
 
-/// Recursively search for a pattern in files under a directory
-pub fn search_recursive(dir: &Path, pattern: &str, max_results: usize) -> Vec<SearchResult> {
+/// Letter case handling of a content search
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CaseMode {
+    #[default]
+    Insensitive,
+    /// Sensitive only when the pattern has an uppercase letter
+    Smart,
+    Sensitive,
+}
+
+impl CaseMode {
+    /// Next mode in the prompt's toggle cycle
+    pub fn next(self) -> Self {
+        match self {
+            Self::Insensitive => Self::Smart,
+            Self::Smart => Self::Sensitive,
+            Self::Sensitive => Self::Insensitive,
+        }
+    }
+}
+
+/// How a [`ContentSearch`] matches lines. The default is a literal,
+/// case-insensitive search.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchOptions {
+    /// The pattern is a regular expression rather than literal text
+    pub regex: bool,
+    /// Whether letter case has to match
+    pub case: CaseMode,
+    /// Only match whole words
+    pub whole_word: bool,
+}
+
+impl SearchOptions {
+    /// The regex lines are matched against, or why `pattern` isn't one
+    pub fn matcher(&self, pattern: &str) -> Result<Regex, String> {
+        let mut source = if self.regex {
+            pattern.to_string()
+        } else {
+            regex::escape(pattern)
+        };
+        if self.whole_word {
+            source = format!(r"\b(?:{source})\b");
+        }
+        let case_sensitive = match self.case {
+            CaseMode::Insensitive => false,
+            CaseMode::Smart => pattern.chars().any(char::is_uppercase),
+            CaseMode::Sensitive => true,
+        };
+        RegexBuilder::new(&source)
+            .case_insensitive(!case_sensitive)
+            .build()
+            .map_err(|e| e.to_string())
+    }
+
+    /// The options that are on, such as `regex word`
+    pub fn labels(&self) -> String {
+        let mut labels = vec![if self.regex { "regex" } else { "literal" }];
+        match self.case {
+            CaseMode::Insensitive => {}
+            CaseMode::Smart => labels.push("smart-case"),
+            CaseMode::Sensitive => labels.push("case"),
+        }
+        if self.whole_word {
+            labels.push("word");
+        }
+        labels.join(" ")
+    }
+}
+
+/// Longest part of a line searched; the rest is skipped unread into
+/// memory, so a huge file without newlines can't fill it
+const SEARCH_LINE_CAP: usize = 64 * 1024;
+
+/// A content search whose pattern and globs are known to be valid, ready
+/// to run, typically as a background job
+#[derive(Debug)]
+pub struct ContentSearch {
+    dir: PathBuf,
+    matcher: Regex,
+    overrides: Override,
+}
+
+impl ContentSearch {
+    /// Search files below `dir` for `pattern`. `globs` narrow the files
+    /// searched the way ripgrep's `-g` does: `*.rs` to include, `!tests/`
+    /// to exclude.
+    pub fn new(
+        dir: &Path,
+        pattern: &str,
+        options: SearchOptions,
+        globs: &[&str],
+    ) -> Result<Self, String> {
+        let matcher = options.matcher(pattern)?;
+        let mut overrides = OverrideBuilder::new(dir);
+        for glob in globs {
+            overrides.add(glob).map_err(|e| e.to_string())?;
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            matcher,
+            overrides: overrides.build().map_err(|e| e.to_string())?,
+        })
+    }
+
+    /// Up to `max_results` matching lines, searched for on all cores.
+    /// Hidden files and whatever `.gitignore`/`.ignore` files exclude are
+    /// skipped. Results come sorted by path and line; a cancelled search
+    /// returns what it found so far.
+    pub fn run(&self, max_results: usize, progress: &Progress) -> Vec<SearchResult> {
+        let results = Mutex::new(Vec::new());
+        let found = AtomicUsize::new(0);
+        WalkBuilder::new(&self.dir)
+            .overrides(self.overrides.clone())
+            .build_parallel()
+            .run(|| {
+                Box::new(|entry| {
+                    if found.load(Ordering::Relaxed) >= max_results || progress.is_cancelled() {
+                        return WalkState::Quit;
+                    }
+                    let Ok(entry) = entry else {
+                        return WalkState::Continue;
+                    };
+                    if entry.file_type().is_some_and(|t| t.is_file()) {
+                        let hits = search_file(entry.path(), &self.matcher, max_results, progress);
+                        progress.add_files(1);
+                        if !hits.is_empty() {
+                            found.fetch_add(hits.len(), Ordering::Relaxed);
+                            if let Ok(mut results) = results.lock() {
+                                results.extend(hits);
+                            }
+                        }
+                    }
+                    WalkState::Continue
+                })
+            });
+        let mut results = results.into_inner().unwrap_or_default();
+        results.sort_by(|a, b| a.path.cmp(&b.path).then(a.line_number.cmp(&b.line_number)));
+        results.truncate(max_results);
+        results
+    }
+}
+
+/// Matching lines of one file, read a line at a time so size doesn't
+/// matter. Invalid UTF-8 is replaced; files with NUL bytes are binary and
+/// yield nothing.
+fn search_file(
+    path: &Path,
+    matcher: &Regex,
+    max_results: usize,
+    progress: &Progress,
+) -> Vec<SearchResult> {
+    let Ok(file) = fs::File::open(path) else {
+        return Vec::new();
+    };
+    let mut reader = io::BufReader::new(ProgressReader {
+        inner: file,
+        progress,
+    });
+    // Like ripgrep, judge by the first block before reading any further
+    match reader.fill_buf() {
+        Ok(head) if !head.contains(&0) => {}
+        _ => return Vec::new(),
+    }
     let mut results = Vec::new();
-    let pattern_lower = pattern.to_lowercase();
-    search_recursive_inner(dir, &pattern_lower, max_results, &mut results);
+    let mut buf = Vec::new();
+    let mut line_number = 0;
+    while results.len() < max_results {
+        buf.clear();
+        match read_capped_line(&mut reader, &mut buf, SEARCH_LINE_CAP) {
+            Ok(true) => {}
+            // The end, or cancelled
+            Ok(false) | Err(_) => break,
+        }
+        if buf.contains(&0) {
+            return Vec::new();
+        }
+        line_number += 1;
+        let text = String::from_utf8_lossy(&buf);
+        let line = text.trim_end_matches(['\n', '\r']);
+        if matcher.is_match(line) {
+            results.push(SearchResult {
+                path: path.to_path_buf(),
+                line_number,
+                line_text: line.to_string(),
+                matches: matcher
+                    .find_iter(line)
+                    .map(|m| m.range())
+                    .filter(|r| !r.is_empty())
+                    .collect(),
+            });
+        }
+    }
     results
 }
 
+/// Read the next line into `buf`, keeping at most `cap` bytes of it; the
+/// rest of a longer line is consumed without being kept. False at the end.
+fn read_capped_line(reader: &mut impl BufRead, buf: &mut Vec<u8>, cap: usize) -> io::Result<bool> {
+    let mut read_any = false;
+    loop {
+        let chunk = reader.fill_buf()?;
+        if chunk.is_empty() {
+            return Ok(read_any);
+        }
+        read_any = true;
+        let newline = chunk.iter().position(|&b| b == b'\n');
+        let end = newline.map_or(chunk.len(), |i| i + 1);
+        let room = cap.saturating_sub(buf.len());
+        buf.extend_from_slice(&chunk[..end.min(room)]);
+        reader.consume(end);
+        if newline.is_some() {
+            return Ok(true);
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SearchResult {
     pub path: PathBuf,
     pub line_number: usize,
     pub line_text: String,
+    /// Byte ranges of the matches within `line_text`
+    pub matches: Vec<Range<usize>>,
 }
 
-fn search_recursive_inner(
-    dir: &Path,
-    pattern: &str,
-    max_results: usize,
-    results: &mut Vec<SearchResult>,
-) {
-    if results.len() >= max_results {
-        return;
+/// Compression wrapped around a tar archive or a single file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    None,
+    Gzip,
+    Xz,
+    Bzip2,
+    Zstd,
+}
+
+impl Codec {
+    /// Identify a compressed stream by its leading bytes
+    fn from_magic(head: &[u8]) -> Option<Self> {
+        if head.starts_with(b"\x1f\x8b") {
+            Some(Self::Gzip)
+        } else if head.starts_with(b"\xfd7zXZ\0") {
+            Some(Self::Xz)
+        } else if head.starts_with(b"BZh") {
+            Some(Self::Bzip2)
+        } else if head.starts_with(b"\x28\xb5\x2f\xfd") {
+            Some(Self::Zstd)
+        } else {
+            None
+        }
     }
-    let entries = match fs::read_dir(dir) {
-        Ok(e) => e,
-        Err(_) => return,
-    };
-    for entry in entries.flatten() {
-        if results.len() >= max_results {
-            return;
+
+    /// Short name for display, such as `gzip`
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => "uncompressed",
+            Self::Gzip => "gzip",
+            Self::Xz => "xz",
+            Self::Bzip2 => "bzip2",
+            Self::Zstd => "zstd",
         }
-        let path = entry.path();
-        let name = entry.file_name().to_string_lossy().to_string();
-        if name.starts_with('.') {
-            continue;
+    }
+
+    /// File extensions of a single compressed file, without the dot
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Self::None => &[],
+            Self::Gzip => &["gz"],
+            Self::Xz => &["xz"],
+            Self::Bzip2 => &["bz2"],
+            Self::Zstd => &["zst"],
         }
-        if path.is_dir() {
-            search_recursive_inner(&path, pattern, max_results, results);
-        } else if path.is_file() {
-            // Skip large/binary files
-            let size = entry.metadata().map(|m| m.len()).unwrap_or(0);
-            if size > 1024 * 1024 || size == 0 {
-                continue;
-            }
-            if let Ok(content) = fs::read_to_string(&path) {
-                for (i, line) in content.lines().enumerate() {
-                    if results.len() >= max_results {
-                        return;
-                    }
-                    if line.to_lowercase().contains(pattern) {
-                        results.push(SearchResult {
-                            path: path.clone(),
-                            line_number: i + 1,
-                            line_text: line.to_string(),
-                        });
-                    }
-                }
-            }
+    }
+
+    /// Wrap `reader` so it yields decompressed bytes
+    pub fn decoder<'a, R: Read + 'a>(self, reader: R) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Self::None => Box::new(reader),
+            Self::Gzip => Box::new(flate2::read::GzDecoder::new(reader)),
+            Self::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(reader)),
+            Self::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(reader)),
+            Self::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+        })
+    }
+
+    fn encoder<W: Write>(self, writer: W) -> io::Result<Encoder<W>> {
+        Ok(match self {
+            Self::None => Encoder::Plain(writer),
+            Self::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(
+                writer,
+                flate2::Compression::default(),
+            )),
+            Self::Xz => Encoder::Xz(xz2::write::XzEncoder::new(writer, 6)),
+            Self::Bzip2 => Encoder::Bzip2(bzip2::write::BzEncoder::new(
+                writer,
+                bzip2::Compression::default(),
+            )),
+            Self::Zstd => Encoder::Zstd(zstd::stream::write::Encoder::new(writer, 0)?),
+        })
+    }
+}
+
+/// A compressing writer that has to be finished explicitly, so errors
+/// writing the trailer are not lost in a `Drop`
+enum Encoder<W: Write> {
+    Plain(W),
+    Gzip(flate2::write::GzEncoder<W>),
+    Xz(xz2::write::XzEncoder<W>),
+    Bzip2(bzip2::write::BzEncoder<W>),
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Self::Plain(w) => Ok(w),
+            Self::Gzip(e) => e.finish(),
+            Self::Xz(e) => e.finish(),
+            Self::Bzip2(e) => e.finish(),
+            Self::Zstd(e) => e.finish(),
         }
     }
 }
 
-/// Check if a path is an extractable archive
-pub fn is_archive(path: &Path) -> bool {
-    let ext = path
-        .extension()
-        .and_then(|e| e.to_str())
-        .map(|e| e.to_lowercase())
-        .unwrap_or_default();
-    matches!(ext.as_str(), "zip" | "gz" | "tar" | "tgz")
-        || path.to_string_lossy().to_lowercase().ends_with(".tar.gz")
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(w) => w.write(buf),
+            Self::Gzip(e) => e.write(buf),
+            Self::Xz(e) => e.write(buf),
+            Self::Bzip2(e) => e.write(buf),
+            Self::Zstd(e) => e.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(w) => w.flush(),
+            Self::Gzip(e) => e.flush(),
+            Self::Xz(e) => e.flush(),
+            Self::Bzip2(e) => e.flush(),
+            Self::Zstd(e) => e.flush(),
+        }
+    }
 }
 
-/// Extract an archive (zip, tar.gz, tar, tgz) into dest_dir
-pub fn extract_archive(archive: &Path, dest_dir: &Path) -> Result<Vec<String>, String> {
-    let name = archive.to_string_lossy().to_lowercase();
-    if name.ends_with(".zip") {
-        extract_zip(archive, dest_dir)
-    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
-        extract_tar_gz(archive, dest_dir)
-    } else if name.ends_with(".tar") {
-        extract_tar(archive, dest_dir)
-    } else if name.ends_with(".gz") {
-        extract_gz(archive, dest_dir)
+/// What an archive file holds, as far as extraction is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar(Codec),
+    /// One compressed file, such as `notes.txt.xz`
+    Single(Codec),
+}
+
+/// Offset of the `ustar` magic in a tar header
+const TAR_MAGIC_OFFSET: usize = 257;
+
+fn is_tar_header(block: &[u8]) -> bool {
+    block
+        .get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + 5)
+        .is_some_and(|m| m == b"ustar")
+}
+
+/// Work out an archive's kind from its magic bytes, peeking inside
+/// compressed streams for a tar header. Falls back to the file extension
+/// for files that can't be read or old tars without the `ustar` magic.
+pub fn detect_archive(path: &Path) -> Option<ArchiveKind> {
+    let mut head = Vec::with_capacity(512);
+    if let Ok(file) = fs::File::open(path) {
+        let _ = file.take(512).read_to_end(&mut head);
+    }
+    let by_name = archive_kind_from_name(path);
+    if head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06") {
+        return Some(ArchiveKind::Zip);
+    }
+    if is_tar_header(&head) {
+        return Some(ArchiveKind::Tar(Codec::None));
+    }
+    if let Some(codec) = Codec::from_magic(&head) {
+        let mut inner = Vec::with_capacity(512);
+        if let Ok(decoder) = fs::File::open(path).and_then(|f| codec.decoder(f)) {
+            let _ = decoder.take(512).read_to_end(&mut inner);
+        }
+        let tar_by_name = matches!(by_name, Some(ArchiveKind::Tar(_)));
+        return Some(if is_tar_header(&inner) || tar_by_name {
+            ArchiveKind::Tar(codec)
+        } else {
+            ArchiveKind::Single(codec)
+        });
+    }
+    if head.is_empty() {
+        by_name
     } else {
-        Err("Unsupported archive format".to_string())
+        // Readable but no known magic: only trust a plain tar extension,
+        // since old tars have no magic of their own
+        by_name.filter(|k| *k == ArchiveKind::Tar(Codec::None))
     }
 }
 
-fn extract_zip(archive: &Path, dest_dir: &Path) -> Result<Vec<String>, String> {
+/// File name endings of tar archives, compressed or not
+const TAR_SUFFIXES: &[(&str, Codec)] = &[
+    (".tar", Codec::None),
+    (".tar.gz", Codec::Gzip),
+    (".tgz", Codec::Gzip),
+    (".tar.xz", Codec::Xz),
+    (".txz", Codec::Xz),
+    (".tar.bz2", Codec::Bzip2),
+    (".tbz2", Codec::Bzip2),
+    (".tbz", Codec::Bzip2),
+    (".tar.zst", Codec::Zstd),
+    (".tzst", Codec::Zstd),
+];
+
+/// Archive kind implied by the file name alone
+pub fn archive_kind_from_name(path: &Path) -> Option<ArchiveKind> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    if name.ends_with(".zip") {
+        return Some(ArchiveKind::Zip);
+    }
+    if let Some((_, codec)) = TAR_SUFFIXES.iter().find(|(s, _)| name.ends_with(s)) {
+        return Some(ArchiveKind::Tar(*codec));
+    }
+    [Codec::Gzip, Codec::Xz, Codec::Bzip2, Codec::Zstd]
+        .into_iter()
+        .find(|c| {
+            c.extensions()
+                .iter()
+                .any(|e| name.ends_with(&format!(".{e}")))
+        })
+        .map(ArchiveKind::Single)
+}
+
+/// An archive's name without its archive extension: `src-1.0.tar.gz`
+/// gives `src-1.0`
+pub fn archive_stem(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let lower = name.to_lowercase();
+    let suffix = TAR_SUFFIXES
+        .iter()
+        .map(|(s, _)| *s)
+        .chain([".zip"])
+        .find(|s| lower.ends_with(s) && lower.len() > s.len());
+    match suffix {
+        // Suffixes are ASCII, so lowercasing kept their byte length
+        Some(s) => name[..name.len() - s.len()].to_string(),
+        None => path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or(name),
+    }
+}
+
+/// Result of an extraction: entries written, and entries refused because
+/// they would have landed outside the destination
+#[derive(Debug, Default)]
+pub struct ExtractReport {
+    pub extracted: Vec<String>,
+    pub skipped: Vec<String>,
+    /// Files and directories that did not exist before, parents first.
+    /// Entries that replaced an existing file are not listed.
+    pub created: Vec<PathBuf>,
+    /// Directory the entries were written to
+    pub dest: PathBuf,
+    /// Directory the entry paths are relative to: `dest`, or its parent
+    /// when [`extract_to_subfolder`] lifted a single root directory
+    pub into: PathBuf,
+}
+
+/// Extract an archive (zip, or tar and single files compressed with gzip,
+/// xz, bzip2 or zstd) into dest_dir. Entries with absolute paths, `..`
+/// components or links escaping dest_dir are skipped.
+pub fn extract_archive(
+    archive: &Path,
+    dest_dir: &Path,
+    progress: &Progress,
+) -> Result<ExtractReport, String> {
+    let kind = detect_archive(archive).ok_or("Unsupported archive format")?;
+    let mut created = Vec::new();
+    make_dirs(dest_dir, &mut created).map_err(|e| e.to_string())?;
+    let mut report = match kind {
+        ArchiveKind::Zip => extract_zip(archive, dest_dir, &|p| Some(p.to_path_buf()), progress)?,
+        ArchiveKind::Tar(codec) => extract_tar(archive, codec, dest_dir, progress)?,
+        ArchiveKind::Single(codec) => extract_single(archive, codec, dest_dir, progress)?,
+    };
+    created.append(&mut report.created);
+    report.created = created;
+    report.dest = dest_dir.to_path_buf();
+    report.into = dest_dir.to_path_buf();
+    Ok(report)
+}
+
+/// Extract into a new folder `subfolder` of dest_dir. An archive whose
+/// entries all sit in one directory gets that directory in dest_dir
+/// instead, rather than nested in the folder (`src/src-1.0/…`).
+pub fn extract_to_subfolder(
+    archive: &Path,
+    dest_dir: &Path,
+    subfolder: &str,
+    progress: &Progress,
+) -> Result<ExtractReport, String> {
+    let sub = unique_dest(dest_dir, subfolder);
+    let mut report = extract_archive(archive, &sub, progress)?;
+    let mut children = fs::read_dir(&sub).map_err(|e| e.to_string())?;
+    let only = match (children.next(), children.next()) {
+        (Some(Ok(only)), None) if only.file_type().is_ok_and(|t| t.is_dir()) => only,
+        _ => return Ok(report),
+    };
+    let root = dest_dir.join(only.file_name());
+    if root != sub && root.symlink_metadata().is_ok() {
+        return Ok(report);
+    }
+    // Step aside first, since the root may share the folder's name
+    let staging = unique_dest(dest_dir, &format!(".{subfolder}.extract"));
+    fs::rename(&sub, &staging).map_err(|e| e.to_string())?;
+    fs::rename(staging.join(only.file_name()), &root).map_err(|e| e.to_string())?;
+    fs::remove_dir(&staging).map_err(|e| e.to_string())?;
+    let nested = sub.join(only.file_name());
+    report.created = report
+        .created
+        .iter()
+        .filter_map(|p| p.strip_prefix(&nested).ok())
+        .map(|rest| root.join(rest))
+        .collect();
+    report.dest = root;
+    report.into = dest_dir.to_path_buf();
+    Ok(report)
+}
+
+/// `create_dir_all`, noting each directory it had to create
+fn make_dirs(dir: &Path, created: &mut Vec<PathBuf>) -> io::Result<()> {
+    let missing: Vec<&Path> = dir
+        .ancestors()
+        .take_while(|d| !d.as_os_str().is_empty() && d.symlink_metadata().is_err())
+        .collect();
+    fs::create_dir_all(dir)?;
+    created.extend(missing.into_iter().rev().map(Path::to_path_buf));
+    Ok(())
+}
+
+/// An archive entry name as a plain relative path, or `None` if it is
+/// absolute, empty or contains `..` (zip-slip)
+pub fn entry_path(name: &Path) -> Option<PathBuf> {
+    let mut out = PathBuf::new();
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => out.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!out.as_os_str().is_empty()).then_some(out)
+}
+
+/// Join an archive entry name onto dest_dir, unless it would escape it
+fn contained_path(dest_dir: &Path, name: &Path) -> Option<PathBuf> {
+    entry_path(name).map(|rel| dest_dir.join(rel))
+}
+
+/// Whether writing `path` would go through a symlink below dest_dir, such
+/// as one planted by an earlier entry of the same archive
+fn through_symlink(dest_dir: &Path, path: &Path) -> bool {
+    let Ok(rel) = path.strip_prefix(dest_dir) else {
+        return true;
+    };
+    let mut current = dest_dir.to_path_buf();
+    let mut parts = rel.components().peekable();
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            break;
+        }
+        current.push(part);
+        if fs::symlink_metadata(&current).is_ok_and(|m| m.file_type().is_symlink()) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Whether a symlink stored at `entry` (relative to the archive root) and
+/// pointing at `target` stays inside the archive root. `..` is only allowed
+/// as a prefix, since after a normal component it may climb out of a
+/// directory reached through another link.
+fn link_stays_inside(entry: &Path, target: &Path) -> bool {
+    let mut depth = entry
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .count()
+        .saturating_sub(1);
+    let mut descended = false;
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => descended = true,
+            Component::CurDir => {}
+            Component::ParentDir if !descended && depth > 0 => depth -= 1,
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Remove a symlink sitting where a file is about to be written, so the
+/// write replaces the link instead of following it
+fn clear_symlink(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_symlink() => fs::remove_file(path),
+        _ => Ok(()),
+    }
+}
+
+/// Reader that reports how much of the underlying file has been consumed
+struct ProgressReader<'a, R> {
+    inner: R,
+    progress: &'a Progress,
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.progress.is_cancelled() {
+            return Err(io::Error::other("Cancelled"));
+        }
+        let n = self.inner.read(buf)?;
+        self.progress.add_bytes(n as u64);
+        Ok(n)
+    }
+}
+
+/// Open `archive` for streaming, with progress measured in compressed bytes
+fn open_tracked<'a>(
+    archive: &Path,
+    progress: &'a Progress,
+) -> Result<ProgressReader<'a, fs::File>, String> {
+    let file = fs::File::open(archive).map_err(|e| e.to_string())?;
+    let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+    progress.set_totals(0, size);
+    Ok(ProgressReader {
+        inner: file,
+        progress,
+    })
+}
+
+/// Picks which archive entries to extract and where: maps an entry's
+/// sanitized path to its path below dest_dir, or `None` to leave it out
+type EntrySelector<'a> = &'a dyn Fn(&Path) -> Option<PathBuf>;
+
+/// Extract one entry of a zip or tar archive, with everything below it
+/// when it is a directory, so that the entry itself lands at `dest`
+pub fn extract_subtree(
+    archive: &Path,
+    inner: &Path,
+    dest: &Path,
+    progress: &Progress,
+) -> Result<ExtractReport, String> {
+    let (Some(dest_dir), Some(dest_name)) = (dest.parent(), dest.file_name()) else {
+        return Err(format!("Invalid destination {}", dest.display()));
+    };
+    let select = |entry: &Path| {
+        entry.strip_prefix(inner).ok().map(|rest| {
+            // Joining "" would add a trailing slash to a file name
+            if rest.as_os_str().is_empty() {
+                PathBuf::from(dest_name)
+            } else {
+                Path::new(dest_name).join(rest)
+            }
+        })
+    };
+    let report = match detect_archive(archive) {
+        Some(ArchiveKind::Zip) => extract_zip(archive, dest_dir, &select, progress)?,
+        Some(ArchiveKind::Tar(codec)) => {
+            let file = open_tracked(archive, progress)?;
+            let reader = codec.decoder(file).map_err(|e| e.to_string())?;
+            unpack_tar(tar::Archive::new(reader), dest_dir, &select, progress)?
+        }
+        _ => return Err("Not a browsable archive".to_string()),
+    };
+    if report.extracted.is_empty() && report.skipped.is_empty() {
+        return Err(format!("{} not found in archive", inner.display()));
+    }
+    Ok(report)
+}
+
+fn extract_zip(
+    archive: &Path,
+    dest_dir: &Path,
+    select: EntrySelector,
+    progress: &Progress,
+) -> Result<ExtractReport, String> {
     let file = fs::File::open(archive).map_err(|e| e.to_string())?;
     let mut zip = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
-    let mut extracted = Vec::new();
+    progress.set_totals(zip.len() as u64, 0);
+    let mut report = ExtractReport::default();
     for i in 0..zip.len() {
+        progress.check()?;
+        progress.add_files(1);
         let mut entry = zip.by_index(i).map_err(|e| e.to_string())?;
         let name = entry.name().to_string();
-        let out_path = dest_dir.join(&name);
+        // Zips written on Windows may use backslashes as separators
+        let Some(rel) = entry_path(Path::new(&name.replace('\\', "/"))) else {
+            report.skipped.push(name);
+            continue;
+        };
+        let Some(rel) = select(&rel) else {
+            continue;
+        };
+        let out_path = dest_dir.join(&rel);
+        if through_symlink(dest_dir, &out_path) {
+            report.skipped.push(name);
+            continue;
+        }
         if entry.is_dir() {
-            fs::create_dir_all(&out_path).map_err(|e| e.to_string())?;
+            make_dirs(&out_path, &mut report.created).map_err(|e| e.to_string())?;
         } else {
             if let Some(parent) = out_path.parent() {
-                fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+                make_dirs(parent, &mut report.created).map_err(|e| e.to_string())?;
+            }
+            let fresh = out_path.symlink_metadata().is_err();
+            clear_symlink(&out_path).map_err(|e| e.to_string())?;
+            if entry.is_symlink() {
+                let mut target = String::new();
+                entry
+                    .read_to_string(&mut target)
+                    .map_err(|e| e.to_string())?;
+                if !link_stays_inside(&rel, Path::new(&target)) {
+                    report.skipped.push(name);
+                    continue;
+                }
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(&target, &out_path).map_err(|e| e.to_string())?;
+                #[cfg(not(unix))]
+                fs::write(&out_path, &target).map_err(|e| e.to_string())?;
+            } else {
+                let mut out = fs::File::create(&out_path).map_err(|e| e.to_string())?;
+                std::io::copy(&mut entry, &mut out).map_err(|e| e.to_string())?;
+            }
+            if fresh {
+                report.created.push(out_path);
             }
-            let mut out = fs::File::create(&out_path).map_err(|e| e.to_string())?;
-            std::io::copy(&mut entry, &mut out).map_err(|e| e.to_string())?;
         }
-        extracted.push(name);
+        report.extracted.push(name);
     }
-    Ok(extracted)
+    Ok(report)
 }
 
-fn extract_tar_gz(archive: &Path, dest_dir: &Path) -> Result<Vec<String>, String> {
-    let file = fs::File::open(archive).map_err(|e| e.to_string())?;
-    let gz = flate2::read::GzDecoder::new(file);
-    let mut tar = tar::Archive::new(gz);
-    let mut extracted = Vec::new();
+fn extract_tar(
+    archive: &Path,
+    codec: Codec,
+    dest_dir: &Path,
+    progress: &Progress,
+) -> Result<ExtractReport, String> {
+    let file = open_tracked(archive, progress)?;
+    let reader = codec.decoder(file).map_err(|e| e.to_string())?;
+    let everything = |p: &Path| Some(p.to_path_buf());
+    unpack_tar(tar::Archive::new(reader), dest_dir, &everything, progress)
+}
+
+fn unpack_tar<R: Read>(
+    mut tar: tar::Archive<R>,
+    dest_dir: &Path,
+    select: EntrySelector,
+    progress: &Progress,
+) -> Result<ExtractReport, String> {
+    let mut report = ExtractReport::default();
     for entry in tar.entries().map_err(|e| e.to_string())? {
         let mut entry = entry.map_err(|e| e.to_string())?;
         let path = entry.path().map_err(|e| e.to_string())?.to_path_buf();
         let name = path.display().to_string();
-        entry.unpack_in(dest_dir).map_err(|e| e.to_string())?;
-        extracted.push(name);
+        progress.add_files(1);
+        let Some(rel) = entry_path(&path) else {
+            report.skipped.push(name);
+            continue;
+        };
+        let Some(rel) = select(&rel) else {
+            continue;
+        };
+        let out_path = dest_dir.join(&rel);
+        let Some(link_source) =
+            tar_link_source(&entry, &rel, dest_dir, select).map_err(|e| e.to_string())?
+        else {
+            report.skipped.push(name);
+            continue;
+        };
+        if through_symlink(dest_dir, &out_path) {
+            report.skipped.push(name);
+            continue;
+        }
+        if let Some(parent) = out_path.parent() {
+            make_dirs(parent, &mut report.created).map_err(|e| e.to_string())?;
+        }
+        let fresh = out_path.symlink_metadata().is_err();
+        match link_source {
+            LinkSource::Hard(source) => {
+                // Replace whatever is there, without following a symlink.
+                // A directory stays, and the link is skipped instead.
+                let _ = fs::remove_file(&out_path);
+                if fs::hard_link(&source, &out_path).is_err() {
+                    report.skipped.push(name);
+                    continue;
+                }
+            }
+            LinkSource::Unpack => {
+                entry.unpack(&out_path).map_err(|e| e.to_string())?;
+            }
+        }
+        if fresh {
+            report.created.push(out_path);
+        }
+        report.extracted.push(name);
     }
-    Ok(extracted)
+    Ok(report)
 }
 
-fn extract_tar(archive: &Path, dest_dir: &Path) -> Result<Vec<String>, String> {
-    let file = fs::File::open(archive).map_err(|e| e.to_string())?;
-    let mut tar = tar::Archive::new(file);
-    let mut extracted = Vec::new();
-    for entry in tar.entries().map_err(|e| e.to_string())? {
-        let mut entry = entry.map_err(|e| e.to_string())?;
-        let path = entry.path().map_err(|e| e.to_string())?.to_path_buf();
-        let name = path.display().to_string();
-        entry.unpack_in(dest_dir).map_err(|e| e.to_string())?;
-        extracted.push(name);
+/// How a tar entry that passed the safety checks gets written
+enum LinkSource {
+    /// Unpacked by the tar crate: files, directories and checked symlinks
+    Unpack,
+    /// A hard link to this already extracted file
+    Hard(PathBuf),
+}
+
+/// Check a tar link entry's target, given the entry's path `rel` below
+/// dest_dir. `None` means the entry must be skipped.
+fn tar_link_source<R: Read>(
+    entry: &tar::Entry<R>,
+    rel: &Path,
+    dest_dir: &Path,
+    select: EntrySelector,
+) -> io::Result<Option<LinkSource>> {
+    let kind = entry.header().entry_type();
+    let Some(target) = entry.link_name()? else {
+        return Ok(Some(LinkSource::Unpack));
+    };
+    if kind.is_symlink() {
+        Ok(link_stays_inside(rel, &target).then_some(LinkSource::Unpack))
+    } else if kind.is_hard_link() {
+        // Hard link targets are relative to the archive root, and must
+        // be something this extraction already wrote. Linking a symlink
+        // copies it to `rel`, where its target may now climb out.
+        Ok(entry_path(&target)
+            .and_then(|t| select(&t))
+            .map(|t| dest_dir.join(t))
+            .filter(|t| !through_symlink(dest_dir, t))
+            .filter(|t| match t.symlink_metadata() {
+                Ok(meta) if meta.file_type().is_symlink() => {
+                    fs::read_link(t).is_ok_and(|link| link_stays_inside(rel, &link))
+                }
+                Ok(_) => true,
+                Err(_) => false,
+            })
+            .map(LinkSource::Hard))
+    } else {
+        Ok(Some(LinkSource::Unpack))
     }
-    Ok(extracted)
 }
 
-fn extract_gz(archive: &Path, dest_dir: &Path) -> Result<Vec<String>, String> {
-    let file = fs::File::open(archive).map_err(|e| e.to_string())?;
-    let mut gz = flate2::read::GzDecoder::new(file);
-    let stem = archive
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("output");
-    let out_path = dest_dir.join(stem);
+/// Decompress a single file next to where it will be listed: `notes.txt.xz`
+/// becomes `notes.txt`, and a name without the codec's extension gets `.out`
+fn extract_single(
+    archive: &Path,
+    codec: Codec,
+    dest_dir: &Path,
+    progress: &Progress,
+) -> Result<ExtractReport, String> {
+    let file = open_tracked(archive, progress)?;
+    let mut reader = codec.decoder(file).map_err(|e| e.to_string())?;
+    let file_name = archive
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let stem = codec
+        .extensions()
+        .iter()
+        .find_map(|ext| {
+            let cut = file_name.len().checked_sub(ext.len() + 1)?;
+            let (stem, suffix) = file_name.split_at(cut);
+            suffix[1..]
+                .eq_ignore_ascii_case(ext)
+                .then(|| stem.to_string())
+        })
+        .unwrap_or_else(|| format!("{file_name}.out"));
+    let mut report = ExtractReport::default();
+    let Some(out_path) = contained_path(dest_dir, Path::new(&stem)) else {
+        report.skipped.push(stem);
+        return Ok(report);
+    };
+    let fresh = out_path.symlink_metadata().is_err();
+    clear_symlink(&out_path).map_err(|e| e.to_string())?;
     let mut out = fs::File::create(&out_path).map_err(|e| e.to_string())?;
-    std::io::copy(&mut gz, &mut out).map_err(|e| e.to_string())?;
-    Ok(vec![stem.to_string()])
+    if let Err(e) = std::io::copy(&mut reader, &mut out) {
+        drop(out);
+        let _ = fs::remove_file(&out_path);
+        return Err(e.to_string());
+    }
+    progress.add_files(1);
+    if fresh {
+        report.created.push(out_path);
+    }
+    report.extracted.push(stem);
+    Ok(report)
 }
 
 /// Compress files into a zip archive at dest_path
-pub fn compress_zip(paths: &[PathBuf], dest_path: &Path) -> Result<usize, String> {
+pub fn compress_zip(
+    paths: &[PathBuf],
+    dest_path: &Path,
+    progress: &Progress,
+) -> Result<usize, String> {
     let file = fs::File::create(dest_path).map_err(|e| e.to_string())?;
     let mut zip = zip::ZipWriter::new(file);
     let options = zip::write::SimpleFileOptions::default()
@@ -309,12 +1261,17 @@ pub fn compress_zip(paths: &[PathBuf], dest_path: &Path) -> Result<usize, String
     let mut count = 0;
     for path in paths {
         if path.is_dir() {
-            count += add_dir_to_zip(&mut zip, path, path.parent().unwrap_or(path), options)?;
+            count += add_dir_to_zip(
+                &mut zip,
+                path,
+                path.parent().unwrap_or(path),
+                options,
+                progress,
+            )?;
         } else {
             let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("file");
             zip.start_file(name, options).map_err(|e| e.to_string())?;
-            let content = fs::read(path).map_err(|e| e.to_string())?;
-            std::io::Write::write_all(&mut zip, &content).map_err(|e| e.to_string())?;
+            add_file_to_zip(&mut zip, path, progress)?;
             count += 1;
         }
     }
@@ -322,11 +1279,28 @@ pub fn compress_zip(paths: &[PathBuf], dest_path: &Path) -> Result<usize, String
     Ok(count)
 }
 
+/// Stream one file into the zip entry that was just started
+fn add_file_to_zip(
+    zip: &mut zip::ZipWriter<fs::File>,
+    path: &Path,
+    progress: &Progress,
+) -> Result<(), String> {
+    let file = fs::File::open(path).map_err(|e| e.to_string())?;
+    let mut reader = ProgressReader {
+        inner: file,
+        progress,
+    };
+    std::io::copy(&mut reader, zip).map_err(|e| e.to_string())?;
+    progress.add_files(1);
+    Ok(())
+}
+
 fn add_dir_to_zip(
     zip: &mut zip::ZipWriter<fs::File>,
     dir: &Path,
     base: &Path,
     options: zip::write::SimpleFileOptions,
+    progress: &Progress,
 ) -> Result<usize, String> {
     let mut count = 0;
     for entry in fs::read_dir(dir).map_err(|e| e.to_string())? {
@@ -340,48 +1314,125 @@ fn add_dir_to_zip(
         if path.is_dir() {
             zip.add_directory(format!("{rel}/"), options)
                 .map_err(|e| e.to_string())?;
-            count += add_dir_to_zip(zip, &path, base, options)?;
+            count += add_dir_to_zip(zip, &path, base, options, progress)?;
         } else {
             zip.start_file(&rel, options).map_err(|e| e.to_string())?;
-            let content = fs::read(&path).map_err(|e| e.to_string())?;
-            std::io::Write::write_all(zip, &content).map_err(|e| e.to_string())?;
+            add_file_to_zip(zip, &path, progress)?;
             count += 1;
         }
     }
     Ok(count)
 }
 
-/// Compress files into a tar.gz archive at dest_path
-#[allow(dead_code)]
-pub fn compress_tar_gz(paths: &[PathBuf], dest_path: &Path) -> Result<usize, String> {
+/// Bundle files into a tar archive at dest_path, compressed with `codec`
+pub fn compress_tar(
+    paths: &[PathBuf],
+    dest_path: &Path,
+    codec: Codec,
+    progress: &Progress,
+) -> Result<usize, String> {
     let file = fs::File::create(dest_path).map_err(|e| e.to_string())?;
-    let gz = flate2::write::GzEncoder::new(file, flate2::Compression::default());
-    let mut tar = tar::Builder::new(gz);
+    let encoder = codec.encoder(file).map_err(|e| e.to_string())?;
+    let mut tar = tar::Builder::new(encoder);
+    let count = append_all_to_tar(&mut tar, paths, progress)?;
+    let encoder = tar.into_inner().map_err(|e| e.to_string())?;
+    encoder.finish().map_err(|e| e.to_string())?;
+    Ok(count)
+}
+
+/// Compress the single file `src` with `codec` alone, as `notes.txt.xz`
+pub fn compress_single(
+    src: &Path,
+    dest_path: &Path,
+    codec: Codec,
+    progress: &Progress,
+) -> Result<usize, String> {
+    let input = fs::File::open(src).map_err(|e| e.to_string())?;
+    let mut reader = ProgressReader {
+        inner: input,
+        progress,
+    };
+    let file = fs::File::create(dest_path).map_err(|e| e.to_string())?;
+    let mut encoder = codec.encoder(file).map_err(|e| e.to_string())?;
+    std::io::copy(&mut reader, &mut encoder).map_err(|e| e.to_string())?;
+    encoder.finish().map_err(|e| e.to_string())?;
+    progress.add_files(1);
+    Ok(1)
+}
+
+fn append_all_to_tar<W: Write>(
+    tar: &mut tar::Builder<W>,
+    paths: &[PathBuf],
+    progress: &Progress,
+) -> Result<usize, String> {
     let mut count = 0;
     for path in paths {
-        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("file");
-        if path.is_dir() {
-            tar.append_dir_all(name, path).map_err(|e| e.to_string())?;
-            count += 1; // count dir as 1
-        } else {
-            let mut f = fs::File::open(path).map_err(|e| e.to_string())?;
-            tar.append_file(name, &mut f).map_err(|e| e.to_string())?;
-            count += 1;
-        }
+        let name = path.file_name().map(PathBuf::from).unwrap_or("file".into());
+        count += append_to_tar(tar, path, &name, progress)?;
     }
-    tar.finish().map_err(|e| e.to_string())?;
     Ok(count)
 }
 
-fn copy_dir_recursive(src: &Path, dest: &Path) -> std::io::Result<()> {
+/// Add `path` to the archive as `name`, recursing into directories. Unix
+/// permissions and times are kept and symlinks are stored as links rather
+/// than followed. Returns the number of non-directory entries written.
+fn append_to_tar<W: Write>(
+    tar: &mut tar::Builder<W>,
+    path: &Path,
+    name: &Path,
+    progress: &Progress,
+) -> Result<usize, String> {
+    progress.check()?;
+    let meta = fs::symlink_metadata(path).map_err(|e| e.to_string())?;
+    let mut header = tar::Header::new_gnu();
+    header.set_metadata_in_mode(&meta, tar::HeaderMode::Complete);
+    let kind = meta.file_type();
+    if kind.is_symlink() {
+        let target = fs::read_link(path).map_err(|e| e.to_string())?;
+        header.set_size(0);
+        tar.append_link(&mut header, name, &target)
+            .map_err(|e| e.to_string())?;
+    } else if kind.is_dir() {
+        header.set_size(0);
+        tar.append_data(&mut header, name, io::empty())
+            .map_err(|e| e.to_string())?;
+        let mut entries = fs::read_dir(path)
+            .map_err(|e| e.to_string())?
+            .collect::<io::Result<Vec<_>>>()
+            .map_err(|e| e.to_string())?;
+        entries.sort_by_key(|e| e.file_name());
+        let mut count = 0;
+        for entry in entries {
+            count += append_to_tar(tar, &entry.path(), &name.join(entry.file_name()), progress)?;
+        }
+        return Ok(count);
+    } else if kind.is_file() {
+        let file = fs::File::open(path).map_err(|e| e.to_string())?;
+        // Never write more than the header promises, even if the file grew
+        let reader = ProgressReader {
+            inner: file,
+            progress,
+        }
+        .take(meta.len());
+        tar.append_data(&mut header, name, reader)
+            .map_err(|e| e.to_string())?;
+    } else {
+        // Sockets, fifos and devices have no place in an archive
+        return Ok(0);
+    }
+    progress.add_files(1);
+    Ok(1)
+}
+
+fn copy_dir_recursive(src: &Path, dest: &Path, progress: &Progress) -> std::io::Result<()> {
     fs::create_dir_all(dest)?;
     for entry in fs::read_dir(src)? {
         let entry = entry?;
         let target = dest.join(entry.file_name());
         if entry.file_type()?.is_dir() {
-            copy_dir_recursive(&entry.path(), &target)?;
+            copy_dir_recursive(&entry.path(), &target, progress)?;
         } else {
-            fs::copy(entry.path(), &target)?;
+            copy_contents(&entry.path(), &target, progress)?;
         }
     }
     Ok(())
@@ -413,11 +1464,8 @@ mod tests {
         let tmp = TempDir::new().unwrap();
         let src = tmp.path().join("src.txt");
         fs::write(&src, "hello").unwrap();
-        let dest_dir = tmp.path().join("dest");
-        fs::create_dir(&dest_dir).unwrap();
-        let result = copy_file(&src, &dest_dir);
-        assert!(result.is_ok());
-        let dest = result.unwrap();
+        let dest = tmp.path().join("dest.txt");
+        assert!(copy_with_progress(&src, &dest, &Progress::default()).is_ok());
         assert_eq!(fs::read_to_string(dest).unwrap(), "hello");
     }
 
@@ -426,12 +1474,140 @@ mod tests {
         let tmp = TempDir::new().unwrap();
         let src = tmp.path().join("src.txt");
         fs::write(&src, "world").unwrap();
-        let dest_dir = tmp.path().join("dest");
-        fs::create_dir(&dest_dir).unwrap();
-        let result = move_file(&src, &dest_dir);
-        assert!(result.is_ok());
+        let dest = tmp.path().join("dest.txt");
+        assert!(move_path(&src, &dest).is_ok());
+        assert!(!src.exists());
+        assert_eq!(fs::read_to_string(dest).unwrap(), "world");
+    }
+
+    #[test]
+    fn test_unique_dest() {
+        let tmp = TempDir::new().unwrap();
+        assert_eq!(unique_dest(tmp.path(), "a.txt"), tmp.path().join("a.txt"));
+        fs::write(tmp.path().join("a.txt"), "").unwrap();
+        assert_eq!(
+            unique_dest(tmp.path(), "a.txt"),
+            tmp.path().join("a (1).txt")
+        );
+        fs::write(tmp.path().join("a (1).txt"), "").unwrap();
+        assert_eq!(
+            unique_dest(tmp.path(), "a.txt"),
+            tmp.path().join("a (2).txt")
+        );
+        fs::create_dir(tmp.path().join("dir")).unwrap();
+        assert_eq!(unique_dest(tmp.path(), "dir"), tmp.path().join("dir (1)"));
+    }
+
+    #[test]
+    fn test_move_path_renames() {
+        let tmp = TempDir::new().unwrap();
+        let src = tmp.path().join("a.txt");
+        let dest = tmp.path().join("b.txt");
+        fs::write(&src, "x").unwrap();
+        move_path(&src, &dest).unwrap();
+        assert!(!src.exists());
+        assert_eq!(fs::read_to_string(dest).unwrap(), "x");
+    }
+
+    #[test]
+    fn test_prune_cache() {
+        let tmp = TempDir::new().unwrap();
+        let now = SystemTime::now();
+        for (name, age) in [("a", 10), ("b", 20), ("c", 30), ("stale", 1000)] {
+            let path = tmp.path().join(name);
+            fs::create_dir(&path).unwrap();
+            fs::write(path.join("entry"), "x").unwrap();
+            fs::File::open(&path)
+                .unwrap()
+                .set_modified(now - Duration::from_secs(age))
+                .unwrap();
+        }
+        prune_cache(tmp.path(), 2, Duration::from_secs(500));
+        let mut left: Vec<_> = fs::read_dir(tmp.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["a", "b"]);
+        prune_cache(&tmp.path().join("missing"), 2, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_move_by_copy_file_keeps_metadata() {
+        let tmp = TempDir::new().unwrap();
+        let src = tmp.path().join("src.txt");
+        fs::write(&src, "payload").unwrap();
+        let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&src)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&src, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+        let dest = tmp.path().join("dest.txt");
+        move_by_copy(&src, &dest, &Progress::default()).unwrap();
         assert!(!src.exists());
-        assert_eq!(fs::read_to_string(result.unwrap()).unwrap(), "world");
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "payload");
+        let meta = fs::metadata(&dest).unwrap();
+        assert_eq!(meta.modified().unwrap(), old);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(meta.permissions().mode() & 0o777, 0o640);
+        }
+    }
+
+    #[test]
+    fn test_move_by_copy_tree() {
+        let tmp = TempDir::new().unwrap();
+        let src = tmp.path().join("tree");
+        fs::create_dir_all(src.join("nested")).unwrap();
+        fs::write(src.join("a.txt"), "a").unwrap();
+        fs::write(src.join("nested").join("b.txt"), "bb").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("a.txt", src.join("link")).unwrap();
+        let dest = tmp.path().join("moved");
+        move_by_copy(&src, &dest, &Progress::default()).unwrap();
+        assert!(!src.exists());
+        assert_eq!(fs::read_to_string(dest.join("nested/b.txt")).unwrap(), "bb");
+        #[cfg(unix)]
+        assert_eq!(
+            fs::read_link(dest.join("link")).unwrap(),
+            PathBuf::from("a.txt")
+        );
+    }
+
+    #[test]
+    fn test_move_by_copy_refuses_existing_dest() {
+        let tmp = TempDir::new().unwrap();
+        let src = tmp.path().join("src.txt");
+        let dest = tmp.path().join("dest.txt");
+        fs::write(&src, "new").unwrap();
+        fs::write(&dest, "old").unwrap();
+        assert!(move_by_copy(&src, &dest, &Progress::default()).is_err());
+        assert!(src.exists());
+        assert_eq!(fs::read_to_string(dest).unwrap(), "old");
+    }
+
+    #[test]
+    fn test_verify_copy_detects_mismatch() {
+        let tmp = TempDir::new().unwrap();
+        let a = tmp.path().join("a");
+        let b = tmp.path().join("b");
+        fs::create_dir(&a).unwrap();
+        fs::create_dir(&b).unwrap();
+        fs::write(a.join("f"), "1234").unwrap();
+        fs::write(b.join("f"), "12").unwrap();
+        assert!(verify_copy(&a, &b).is_err());
+        fs::write(b.join("f"), "abcd").unwrap();
+        assert!(verify_copy(&a, &b).is_ok());
+        fs::write(b.join("extra"), "").unwrap();
+        assert!(verify_copy(&a, &b).is_err());
     }
 
     #[test]
@@ -456,7 +1632,7 @@ mod tests {
         fs::write(sub.join("b.txt"), "b").unwrap();
 
         let dest_dir = tmp.path().join("dest");
-        copy_dir_recursive(&src_dir, &dest_dir).unwrap();
+        copy_dir_recursive(&src_dir, &dest_dir, &Progress::default()).unwrap();
         assert!(dest_dir.join("a.txt").exists());
         assert!(dest_dir.join("sub").join("b.txt").exists());
     }
@@ -468,9 +1644,8 @@ mod tests {
         let tmp = TempDir::new().unwrap();
         let src = tmp.path().join("data.txt");
         fs::write(&src, "important data here").unwrap();
-        let dest_dir = tmp.path().join("out");
-        fs::create_dir(&dest_dir).unwrap();
-        let dest = copy_file(&src, &dest_dir).unwrap();
+        let dest = tmp.path().join("out.txt");
+        copy_with_progress(&src, &dest, &Progress::default()).unwrap();
         assert_eq!(fs::read_to_string(&src).unwrap(), "important data here");
         assert_eq!(fs::read_to_string(dest).unwrap(), "important data here");
     }
@@ -489,6 +1664,431 @@ mod tests {
         assert!(result.is_err());
     }
 
+    /// Write a tar entry with raw name and link fields, bypassing the
+    /// path checks `tar::Header::set_path` would apply
+    fn raw_tar_entry(
+        builder: &mut tar::Builder<fs::File>,
+        name: &str,
+        kind: tar::EntryType,
+        link: &str,
+        data: &[u8],
+    ) {
+        let mut header = tar::Header::new_gnu();
+        header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+        header.as_old_mut().linkname[..link.len()].copy_from_slice(link.as_bytes());
+        header.set_entry_type(kind);
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, data).unwrap();
+    }
+
+    #[test]
+    fn test_contained_path() {
+        let dest = Path::new("/dest");
+        assert_eq!(
+            contained_path(dest, Path::new("a/./b")),
+            Some(PathBuf::from("/dest/a/b"))
+        );
+        assert_eq!(contained_path(dest, Path::new("../x")), None);
+        assert_eq!(contained_path(dest, Path::new("a/../../x")), None);
+        assert_eq!(contained_path(dest, Path::new("/etc/passwd")), None);
+        assert_eq!(contained_path(dest, Path::new(".")), None);
+    }
+
+    #[test]
+    fn test_link_stays_inside() {
+        assert!(link_stays_inside(Path::new("a/link"), Path::new("../b")));
+        assert!(link_stays_inside(Path::new("link"), Path::new("sub/file")));
+        assert!(!link_stays_inside(Path::new("link"), Path::new("../x")));
+        assert!(!link_stays_inside(
+            Path::new("a/link"),
+            Path::new("../../x")
+        ));
+        assert!(!link_stays_inside(Path::new("link"), Path::new("/etc")));
+        // A `..` after a normal component may climb out through another link
+        assert!(!link_stays_inside(Path::new("link"), Path::new("sub/..")));
+    }
+
+    #[test]
+    fn test_extract_zip_rejects_traversal() {
+        let tmp = TempDir::new().unwrap();
+        let archive = tmp.path().join("evil.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        for name in [
+            "../evil.txt",
+            "/abs.txt",
+            "a/../../evil2.txt",
+            "..\\win.txt",
+            "good.txt",
+        ] {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(b"payload").unwrap();
+        }
+        zip.add_symlink("up", "../../etc", options).unwrap();
+        zip.add_symlink("inner", "sub", options).unwrap();
+        zip.start_file("inner/through.txt", options).unwrap();
+        zip.write_all(b"payload").unwrap();
+        zip.finish().unwrap();
+
+        let dest = tmp.path().join("out");
+        fs::create_dir(&dest).unwrap();
+        let report = extract_archive(&archive, &dest, &Progress::default()).unwrap();
+        assert_eq!(report.extracted, vec!["good.txt", "inner"]);
+        assert_eq!(report.skipped.len(), 6);
+        assert!(!tmp.path().join("evil.txt").exists());
+        assert!(!tmp.path().join("evil2.txt").exists());
+        assert!(!tmp.path().join("win.txt").exists());
+        assert!(!dest.join("up").exists());
+        assert!(!dest.join("sub").exists());
+        assert_eq!(
+            fs::read_to_string(dest.join("good.txt")).unwrap(),
+            "payload"
+        );
+    }
+
+    #[test]
+    fn test_extract_tar_rejects_traversal_and_links() {
+        let tmp = TempDir::new().unwrap();
+        let archive = tmp.path().join("evil.tar");
+        let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+        raw_tar_entry(
+            &mut builder,
+            "../evil.txt",
+            tar::EntryType::Regular,
+            "",
+            b"x",
+        );
+        raw_tar_entry(&mut builder, "/abs.txt", tar::EntryType::Regular, "", b"x");
+        raw_tar_entry(&mut builder, "escape", tar::EntryType::Symlink, "/etc", b"");
+        raw_tar_entry(
+            &mut builder,
+            "up",
+            tar::EntryType::Symlink,
+            "../outside",
+            b"",
+        );
+        raw_tar_entry(
+            &mut builder,
+            "hard",
+            tar::EntryType::Link,
+            "../outside",
+            b"",
+        );
+        raw_tar_entry(&mut builder, "ok.txt", tar::EntryType::Regular, "", b"fine");
+        raw_tar_entry(&mut builder, "self", tar::EntryType::Symlink, "ok.txt", b"");
+        builder.finish().unwrap();
+        drop(builder);
+        fs::write(tmp.path().join("outside"), "secret").unwrap();
+
+        let dest = tmp.path().join("out");
+        fs::create_dir(&dest).unwrap();
+        let report = extract_archive(&archive, &dest, &Progress::default()).unwrap();
+        assert_eq!(report.extracted, vec!["ok.txt", "self"]);
+        assert_eq!(
+            report.skipped,
+            vec!["../evil.txt", "/abs.txt", "escape", "up", "hard"]
+        );
+        assert!(!tmp.path().join("evil.txt").exists());
+        assert!(fs::symlink_metadata(dest.join("escape")).is_err());
+        assert!(fs::symlink_metadata(dest.join("up")).is_err());
+        assert!(!dest.join("hard").exists());
+        assert_eq!(fs::read_to_string(dest.join("self")).unwrap(), "fine");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract_tar_rejects_hard_link_moving_symlink() {
+        let tmp = TempDir::new().unwrap();
+        let archive = tmp.path().join("evil.tar");
+        let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+        // Fine two levels down, but not at the top
+        raw_tar_entry(&mut builder, "a/b/l", tar::EntryType::Symlink, "../..", b"");
+        raw_tar_entry(&mut builder, "esc", tar::EntryType::Link, "a/b/l", b"");
+        raw_tar_entry(&mut builder, "a/b/same", tar::EntryType::Link, "a/b/l", b"");
+        builder.finish().unwrap();
+        drop(builder);
+
+        let dest = tmp.path().join("out");
+        fs::create_dir(&dest).unwrap();
+        let report = extract_archive(&archive, &dest, &Progress::default()).unwrap();
+        assert_eq!(report.extracted, vec!["a/b/l", "a/b/same"]);
+        assert_eq!(report.skipped, vec!["esc"]);
+        assert!(fs::symlink_metadata(dest.join("esc")).is_err());
+
+        // Pasting the subtree out of the archive goes through the same check
+        let pasted = tmp.path().join("pasted");
+        let report = extract_subtree(&archive, Path::new(""), &pasted, &Progress::default());
+        let report = report.unwrap();
+        assert_eq!(report.skipped, vec!["esc"]);
+        assert!(fs::symlink_metadata(pasted.join("esc")).is_err());
+    }
+
+    #[test]
+    fn test_extract_tar_skips_hard_link_onto_directory() {
+        let tmp = TempDir::new().unwrap();
+        let archive = tmp.path().join("clash.tar");
+        let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+        raw_tar_entry(&mut builder, "f", tar::EntryType::Regular, "", b"file");
+        raw_tar_entry(&mut builder, "d/x.txt", tar::EntryType::Regular, "", b"x");
+        raw_tar_entry(&mut builder, "d", tar::EntryType::Link, "f", b"");
+        raw_tar_entry(&mut builder, "after", tar::EntryType::Regular, "", b"more");
+        builder.finish().unwrap();
+        drop(builder);
+
+        let dest = tmp.path().join("out");
+        fs::create_dir(&dest).unwrap();
+        let report = extract_archive(&archive, &dest, &Progress::default()).unwrap();
+        assert_eq!(report.extracted, vec!["f", "d/x.txt", "after"]);
+        assert_eq!(report.skipped, vec!["d"]);
+        assert_eq!(fs::read_to_string(dest.join("d/x.txt")).unwrap(), "x");
+        assert_eq!(fs::read_to_string(dest.join("after")).unwrap(), "more");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract_tar_refuses_writing_through_symlink() {
+        let tmp = TempDir::new().unwrap();
+        let archive = tmp.path().join("evil.tar");
+        let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+        raw_tar_entry(&mut builder, "dir/x.txt", tar::EntryType::Regular, "", b"x");
+        builder.finish().unwrap();
+        drop(builder);
+        let outside = tmp.path().join("elsewhere");
+        fs::create_dir(&outside).unwrap();
+        let dest = tmp.path().join("out");
+        fs::create_dir(&dest).unwrap();
+        std::os::unix::fs::symlink(&outside, dest.join("dir")).unwrap();
+
+        let report = extract_archive(&archive, &dest, &Progress::default()).unwrap();
+        assert!(report.extracted.is_empty());
+        assert_eq!(report.skipped, vec!["dir/x.txt"]);
+        assert!(!outside.join("x.txt").exists());
+    }
+
+    #[test]
+    fn test_extract_gz_rejects_dot_name() {
+        let tmp = TempDir::new().unwrap();
+        let archive = tmp.path().join("..gz");
+        let mut gz =
+            flate2::write::GzEncoder::new(fs::File::create(&archive).unwrap(), Default::default());
+        gz.write_all(b"data").unwrap();
+        gz.finish().unwrap();
+        let report = extract_archive(&archive, tmp.path(), &Progress::default()).unwrap();
+        assert!(report.extracted.is_empty());
+        assert_eq!(report.skipped.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_compress_tar_keeps_modes_and_symlinks() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmp = TempDir::new().unwrap();
+        let src = tmp.path().join("pkg");
+        fs::create_dir(&src).unwrap();
+        fs::write(src.join("run.sh"), "#!/bin/sh").unwrap();
+        fs::set_permissions(src.join("run.sh"), fs::Permissions::from_mode(0o750)).unwrap();
+        std::os::unix::fs::symlink("run.sh", src.join("link")).unwrap();
+        let archive = tmp.path().join("pkg.tar");
+        let count = compress_tar(&[src], &archive, Codec::None, &Progress::default()).unwrap();
+        assert_eq!(count, 2);
+
+        let mut tar = tar::Archive::new(fs::File::open(&archive).unwrap());
+        let headers: Vec<(String, tar::EntryType, u32, Option<PathBuf>)> = tar
+            .entries()
+            .unwrap()
+            .map(|e| {
+                let e = e.unwrap();
+                (
+                    e.path().unwrap().display().to_string(),
+                    e.header().entry_type(),
+                    e.header().mode().unwrap() & 0o777,
+                    e.link_name().unwrap().map(|l| l.into_owned()),
+                )
+            })
+            .collect();
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers[0].0, "pkg");
+        assert_eq!(headers[0].1, tar::EntryType::Directory);
+        assert_eq!(headers[1].0, "pkg/link");
+        assert_eq!(headers[1].1, tar::EntryType::Symlink);
+        assert_eq!(headers[1].3, Some(PathBuf::from("run.sh")));
+        assert_eq!(headers[2].0, "pkg/run.sh");
+        assert_eq!(headers[2].2, 0o750);
+
+        let out = tmp.path().join("out");
+        fs::create_dir(&out).unwrap();
+        extract_archive(&archive, &out, &Progress::default()).unwrap();
+        assert_eq!(
+            fs::read_link(out.join("pkg/link")).unwrap(),
+            PathBuf::from("run.sh")
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("pkg/link")).unwrap(),
+            "#!/bin/sh"
+        );
+    }
+
+    #[test]
+    fn test_compress_tar_gz_reports_progress() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("a.txt"), "hello").unwrap();
+        let archive = tmp.path().join("a.tar.gz");
+        let progress = Progress::default();
+        let count = compress_tar(
+            &[tmp.path().join("a.txt")],
+            &archive,
+            Codec::Gzip,
+            &progress,
+        )
+        .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(progress.snapshot().files_done, 1);
+        assert_eq!(progress.snapshot().bytes_done, 5);
+        let out = tmp.path().join("out");
+        fs::create_dir(&out).unwrap();
+        let report = extract_archive(&archive, &out, &Progress::default()).unwrap();
+        assert_eq!(report.extracted, vec!["a.txt"]);
+        assert_eq!(fs::read_to_string(out.join("a.txt")).unwrap(), "hello");
+    }
+
+    #[test]
+    fn test_tar_codecs_round_trip() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("a.txt"), "hello").unwrap();
+        for (codec, name) in [
+            (Codec::Gzip, "a.tar.gz"),
+            (Codec::Xz, "a.tar.xz"),
+            (Codec::Bzip2, "a.tar.bz2"),
+            (Codec::Zstd, "a.tar.zst"),
+        ] {
+            let archive = tmp.path().join(name);
+            let progress = Progress::default();
+            compress_tar(&[tmp.path().join("a.txt")], &archive, codec, &progress).unwrap();
+            assert_eq!(detect_archive(&archive), Some(ArchiveKind::Tar(codec)));
+            let out = tmp.path().join(format!("out-{name}"));
+            fs::create_dir(&out).unwrap();
+            let report = extract_archive(&archive, &out, &progress).unwrap();
+            assert_eq!(report.extracted, vec!["a.txt"], "{name}");
+            assert_eq!(fs::read_to_string(out.join("a.txt")).unwrap(), "hello");
+        }
+    }
+
+    #[test]
+    fn test_detect_archive_by_magic() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(tmp.path().join("a.txt"), "hello").unwrap();
+        // A tar.xz with a misleading name is still found by its content
+        let disguised = tmp.path().join("build.bin");
+        compress_tar(
+            &[tmp.path().join("a.txt")],
+            &disguised,
+            Codec::Xz,
+            &Progress::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            detect_archive(&disguised),
+            Some(ArchiveKind::Tar(Codec::Xz))
+        );
+        // And a text file named like an archive is not one
+        let fake = tmp.path().join("fake.zip");
+        fs::write(&fake, "not a zip").unwrap();
+        assert_eq!(detect_archive(&fake), None);
+        assert!(extract_archive(&fake, tmp.path(), &Progress::default()).is_err());
+    }
+
+    #[test]
+    fn test_extract_single_compressed_files() {
+        let tmp = TempDir::new().unwrap();
+        let out = tmp.path().join("out");
+        fs::create_dir(&out).unwrap();
+        for (codec, name) in [
+            (Codec::Gzip, "notes.txt.gz"),
+            (Codec::Xz, "notes.txt.xz"),
+            (Codec::Bzip2, "notes.txt.bz2"),
+            (Codec::Zstd, "notes.txt.zst"),
+            (Codec::Zstd, "blob"),
+        ] {
+            let archive = tmp.path().join(name);
+            let mut encoder = codec.encoder(fs::File::create(&archive).unwrap()).unwrap();
+            encoder.write_all(b"plain text").unwrap();
+            encoder.finish().unwrap();
+            assert_eq!(detect_archive(&archive), Some(ArchiveKind::Single(codec)));
+            let report = extract_archive(&archive, &out, &Progress::default()).unwrap();
+            let expected = if name == "blob" {
+                "blob.out"
+            } else {
+                "notes.txt"
+            };
+            assert_eq!(report.extracted, vec![expected]);
+            assert_eq!(
+                fs::read_to_string(out.join(expected)).unwrap(),
+                "plain text"
+            );
+        }
+    }
+
+    #[test]
+    fn test_archive_stem() {
+        assert_eq!(archive_stem(Path::new("/x/src-1.0.tar.gz")), "src-1.0");
+        assert_eq!(archive_stem(Path::new("Photos.ZIP")), "Photos");
+        assert_eq!(archive_stem(Path::new("a.b.tzst")), "a.b");
+        assert_eq!(archive_stem(Path::new("notes.txt.xz")), "notes.txt");
+        assert_eq!(archive_stem(Path::new(".tar")), ".tar");
+    }
+
+    #[test]
+    fn test_extract_to_subfolder() {
+        let tmp = TempDir::new().unwrap();
+        let src = tmp.path().join("src");
+        fs::create_dir_all(src.join("proj/lib")).unwrap();
+        fs::write(src.join("proj/lib/a.rs"), "a").unwrap();
+        fs::write(src.join("loose.txt"), "l").unwrap();
+        let out = tmp.path().join("out");
+        fs::create_dir(&out).unwrap();
+        let progress = Progress::default();
+
+        // Loose entries go in the folder
+        let loose = tmp.path().join("loose.tar");
+        compress_tar(&[src.join("loose.txt")], &loose, Codec::None, &progress).unwrap();
+        let report = extract_to_subfolder(&loose, &out, "loose", &progress).unwrap();
+        assert_eq!(report.dest, out.join("loose"));
+        assert_eq!(
+            report.created,
+            [out.join("loose"), out.join("loose/loose.txt")]
+        );
+
+        // A single root takes the folder's place, whether or not the
+        // names match
+        let single = tmp.path().join("proj.tar");
+        compress_tar(&[src.join("proj")], &single, Codec::None, &progress).unwrap();
+        for folder in ["proj", "bundle"] {
+            let report = extract_to_subfolder(&single, &out, folder, &progress).unwrap();
+            assert_eq!(report.dest, out.join("proj"));
+            assert_eq!(fs::read_to_string(out.join("proj/lib/a.rs")).unwrap(), "a");
+            assert!(!out.join("proj/proj").exists());
+            assert!(!out.join("bundle").exists());
+            assert_eq!(report.created[0], out.join("proj"));
+            assert!(report.created.contains(&out.join("proj/lib/a.rs")));
+            if folder == "proj" {
+                fs::rename(out.join("proj"), out.join("first")).unwrap();
+            }
+        }
+
+        // The root's name is taken: stay nested in a fresh folder
+        let report = extract_to_subfolder(&single, &out, "proj", &progress).unwrap();
+        assert_eq!(report.dest, out.join("proj (1)"));
+        assert!(out.join("proj (1)/proj/lib/a.rs").exists());
+        let leftovers: Vec<_> = fs::read_dir(&out)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|n| n.starts_with('.'))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+
     #[test]
     fn test_op_kind_eq() {
         assert_eq!(OpKind::Copy, OpKind::Copy);
@@ -505,4 +2105,165 @@ mod tests {
         assert_eq!(cloned.kind, OpKind::Copy);
         assert_eq!(cloned.sources.len(), 1);
     }
+
+    fn search_recursive(
+        dir: &Path,
+        pattern: &str,
+        options: SearchOptions,
+        globs: &[&str],
+        max_results: usize,
+    ) -> Result<Vec<SearchResult>, String> {
+        let search = ContentSearch::new(dir, pattern, options, globs)?;
+        Ok(search.run(max_results, &Progress::default()))
+    }
+
+    #[test]
+    fn test_search_options() {
+        let tmp = TempDir::new().unwrap();
+        fs::write(
+            tmp.path().join("a.rs"),
+            "let Count = 1;\nlet counter = count + 1;\nfn recount() {}\n",
+        )
+        .unwrap();
+        let lines = |pattern: &str, options: SearchOptions| -> Vec<usize> {
+            search_recursive(tmp.path(), pattern, options, &[], 100)
+                .unwrap()
+                .iter()
+                .map(|r| r.line_number)
+                .collect()
+        };
+        let defaults = SearchOptions::default();
+        assert_eq!(lines("count", defaults), [1, 2, 3]);
+        assert_eq!(lines("COUNT", defaults), [1, 2, 3]);
+        // A literal pattern's regex syntax matches itself only
+        assert_eq!(lines("count.", defaults), Vec::<usize>::new());
+        assert_eq!(lines("() {", defaults), [3]);
+
+        let smart = SearchOptions {
+            case: CaseMode::Smart,
+            ..defaults
+        };
+        assert_eq!(lines("count", smart), [1, 2, 3]);
+        assert_eq!(lines("Count", smart), [1]);
+        let sensitive = SearchOptions {
+            case: CaseMode::Sensitive,
+            ..defaults
+        };
+        assert_eq!(lines("count", sensitive), [2, 3]);
+        assert_eq!(lines("COUNT", sensitive), Vec::<usize>::new());
+
+        let word = SearchOptions {
+            whole_word: true,
+            ..defaults
+        };
+        assert_eq!(lines("count", word), [1, 2]);
+
+        let regex = SearchOptions {
+            regex: true,
+            ..defaults
+        };
+        assert_eq!(lines(r"count\w+", regex), [2]);
+        assert_eq!(
+            lines(
+                "re|er",
+                SearchOptions {
+                    whole_word: true,
+                    ..regex
+                }
+            ),
+            Vec::<usize>::new()
+        );
+        assert!(search_recursive(tmp.path(), "(", regex, &[], 100).is_err());
+
+        let results = search_recursive(tmp.path(), "count", defaults, &[], 100).unwrap();
+        assert_eq!(results[1].matches, [4..9, 14..19]);
+        assert_eq!(&results[1].line_text[14..19], "count");
+        assert_eq!(regex.labels(), "regex");
+        assert_eq!(
+            SearchOptions {
+                case: CaseMode::Smart,
+                whole_word: true,
+                ..defaults
+            }
+            .labels(),
+            "literal smart-case word"
+        );
+        assert_eq!(sensitive.labels(), "literal case");
+    }
+
+    #[test]
+    fn test_search_ignores_globs_and_big_files() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path();
+        git2::Repository::init(dir).unwrap();
+        fs::write(dir.join(".gitignore"), "target/\n").unwrap();
+        fs::write(dir.join(".ignore"), "*.snap\n").unwrap();
+        fs::create_dir_all(dir.join("target/debug")).unwrap();
+        fs::create_dir(dir.join("src")).unwrap();
+        fs::write(dir.join("target/debug/out.rs"), "needle").unwrap();
+        fs::write(dir.join("src/a.snap"), "needle").unwrap();
+        fs::write(dir.join("src/lib.rs"), "// needle\n").unwrap();
+        fs::write(dir.join("notes.md"), "needle\r\n").unwrap();
+        // Past the old 1 MB cutoff, and not valid UTF-8
+        let mut big = "filler line\n".repeat(200_000).into_bytes();
+        big.extend_from_slice(b"\xff needle\n");
+        fs::write(dir.join("big.log"), &big).unwrap();
+        fs::write(dir.join("blob.bin"), b"needle\0\x01").unwrap();
+
+        let search = |globs: &[&str]| -> Vec<(String, usize)> {
+            search_recursive(dir, "needle", SearchOptions::default(), globs, 100)
+                .unwrap()
+                .iter()
+                .map(|r| {
+                    let rel = r.path.strip_prefix(dir).unwrap();
+                    (rel.to_string_lossy().to_string(), r.line_number)
+                })
+                .collect()
+        };
+        assert_eq!(
+            search(&[]),
+            [
+                ("big.log".to_string(), 200_001),
+                ("notes.md".to_string(), 1),
+                ("src/lib.rs".to_string(), 1)
+            ]
+        );
+        let results = search_recursive(dir, "needle", SearchOptions::default(), &[], 100).unwrap();
+        assert_eq!(results[0].line_text, "\u{fffd} needle");
+        assert_eq!(results[1].line_text, "needle");
+
+        assert_eq!(search(&["*.rs"]), [("src/lib.rs".to_string(), 1)]);
+        assert_eq!(search(&["!src/", "!*.log"]), [("notes.md".to_string(), 1)]);
+        assert!(search_recursive(dir, "x", SearchOptions::default(), &["a[b"], 100).is_err());
+        assert_eq!(
+            search_recursive(dir, "filler", SearchOptions::default(), &[], 50)
+                .unwrap()
+                .len(),
+            50
+        );
+    }
+
+    #[test]
+    fn test_search_caps_lines_and_checks_head() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path();
+        let mut long = vec![b'x'; SEARCH_LINE_CAP * 4];
+        long.extend_from_slice(b"needle\nneedle\n");
+        fs::write(dir.join("long.txt"), &long).unwrap();
+        // NUL only at the start, followed by a newline-free megabyte
+        let mut blob = vec![0u8; 4];
+        blob.extend(std::iter::repeat_n(b'n', 1 << 20));
+        blob.extend_from_slice(b"needle");
+        fs::write(dir.join("blob.bin"), &blob).unwrap();
+
+        let results = search_recursive(dir, "needle", SearchOptions::default(), &[], 100).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].path.ends_with("long.txt"));
+        assert_eq!(results[0].line_number, 2);
+
+        let progress = Progress::default();
+        progress.cancel();
+        let search = ContentSearch::new(dir, "needle", SearchOptions::default(), &[]).unwrap();
+        assert!(search.run(100, &progress).is_empty());
+    }
 }
@@ -1,15 +1,29 @@
-use git2::{Repository, StatusOptions};
+use git2::build::CheckoutBuilder;
+use git2::{
+    BlameOptions, BranchType, Commit, Diff, DiffFormat, DiffOptions, IndexAddOption, IndexEntry,
+    IndexTime, Oid, Repository, RepositoryState, Sort, Status, StatusOptions,
+};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use std::collections::HashMap;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, SystemTime};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-#[allow(dead_code)]
 pub enum GitFileStatus {
     Modified,
     Staged,
+    /// Changes staged, and more changes on top in the worktree
+    StagedModified,
     Untracked,
     Conflict,
     Deleted,
+    /// Rename staged in the index
     Renamed,
     Ignored,
 }
@@ -19,6 +33,7 @@ impl GitFileStatus {
         match self {
             Self::Modified => "M",
             Self::Staged => "S",
+            Self::StagedModified => "SM",
             Self::Untracked => "?",
             Self::Conflict => "!",
             Self::Deleted => "D",
@@ -26,71 +41,925 @@ impl GitFileStatus {
             Self::Ignored => "I",
         }
     }
+
+    /// How much a status demands attention, for summarizing a directory
+    fn priority(self) -> u8 {
+        match self {
+            Self::Conflict => 5,
+            Self::StagedModified => 4,
+            Self::Staged | Self::Renamed | Self::Modified => 3,
+            Self::Deleted => 2,
+            Self::Untracked => 1,
+            Self::Ignored => 0,
+        }
+    }
+
+    /// Status of a directory holding entries with both statuses: the more
+    /// severe one, except that staged and unstaged changes add up
+    pub fn combine(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Staged | Self::Renamed, Self::Modified)
+            | (Self::Modified, Self::Staged | Self::Renamed) => Self::StagedModified,
+            (Self::Staged, Self::Renamed) | (Self::Renamed, Self::Staged) => Self::Staged,
+            (a, b) if b.priority() > a.priority() => b,
+            (a, _) => a,
+        }
+    }
+
+    /// Status of one file from libgit2's flags
+    fn of(status: Status) -> Option<Self> {
+        let staged = status.intersects(
+            Status::INDEX_NEW
+                | Status::INDEX_MODIFIED
+                | Status::INDEX_DELETED
+                | Status::INDEX_RENAMED
+                | Status::INDEX_TYPECHANGE,
+        );
+        let changed = status.intersects(
+            Status::WT_MODIFIED | Status::WT_DELETED | Status::WT_RENAMED | Status::WT_TYPECHANGE,
+        );
+        Some(if status.is_conflicted() {
+            Self::Conflict
+        } else if staged && changed {
+            Self::StagedModified
+        } else if status.is_index_renamed() {
+            Self::Renamed
+        } else if staged {
+            Self::Staged
+        } else if status
+            .intersects(Status::WT_MODIFIED | Status::WT_RENAMED | Status::WT_TYPECHANGE)
+        {
+            Self::Modified
+        } else if status.is_wt_deleted() {
+            Self::Deleted
+        } else if status.is_wt_new() {
+            Self::Untracked
+        } else if status.is_ignored() {
+            Self::Ignored
+        } else {
+            return None;
+        })
+    }
 }
 
-pub fn get_git_statuses(dir: &Path) -> HashMap<String, GitFileStatus> {
-    let mut map = HashMap::new();
-    let repo = match Repository::discover(dir) {
-        Ok(r) => r,
-        Err(_) => return map,
-    };
-    let workdir = match repo.workdir() {
-        Some(w) => w.to_path_buf(),
-        None => return map,
-    };
+/// Per directory of the worktree (relative to it), the combined status
+/// of each child with changes below it
+type DirStatuses = HashMap<PathBuf, HashMap<String, GitFileStatus>>;
 
+fn collect_statuses(repo: &Repository) -> DirStatuses {
+    let mut dirs = DirStatuses::new();
     let mut opts = StatusOptions::new();
     opts.include_untracked(true)
         .recurse_untracked_dirs(true)
-        .include_ignored(false);
+        .include_ignored(true)
+        // An ignored directory is reported once, not file by file
+        .recurse_ignored_dirs(false)
+        .renames_head_to_index(true);
 
     let statuses = match repo.statuses(Some(&mut opts)) {
         Ok(s) => s,
-        Err(_) => return map,
+        Err(_) => return dirs,
     };
 
     for entry in statuses.iter() {
-        let path_str = match entry.path() {
-            Some(p) => p.to_string(),
-            None => continue,
-        };
         let status = entry.status();
-        let full_path = workdir.join(&path_str);
+        let Some(file_status) = GitFileStatus::of(status) else {
+            continue;
+        };
+        // A staged rename is listed under its new name
+        let renamed_to = entry
+            .head_to_index()
+            .filter(|_| status.is_index_renamed())
+            .and_then(|d| d.new_file().path().map(|p| p.to_path_buf()));
+        let path = match renamed_to {
+            Some(p) => p,
+            None => match entry.path() {
+                Some(p) => p.into(),
+                None => continue,
+            },
+        };
+
+        // Every directory above the file sees it through one child
+        let components: Vec<_> = path.components().collect();
+        let mut parent = PathBuf::new();
+        for (depth, child) in components.iter().enumerate() {
+            // Ignored files deeper down don't make their directory ignored
+            if file_status != GitFileStatus::Ignored || depth + 1 == components.len() {
+                let name = child.as_os_str().to_string_lossy().to_string();
+                dirs.entry(parent.clone())
+                    .or_default()
+                    .entry(name)
+                    .and_modify(|s| *s = s.combine(file_status))
+                    .or_insert(file_status);
+            }
+            parent.push(child);
+        }
+    }
+
+    dirs
+}
+
+/// The worktree holding `dir`: its nearest ancestor with a `.git`
+pub fn workdir_of(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|a| a.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// `.gitignore` and `.ignore` rules for the entries of one directory,
+/// read the way git and ripgrep do, so ignored files are known without
+/// waiting for a status scan
+#[derive(Debug, Default)]
+pub struct IgnoreRules {
+    /// Innermost directory first; within one, `.ignore` beats `.gitignore`
+    levels: Vec<Gitignore>,
+}
 
-        // Get the component relative to `dir`
-        let rel = match full_path.strip_prefix(dir) {
-            Ok(r) => r,
-            Err(_) => continue,
+impl IgnoreRules {
+    /// Rules from `dir` and its ancestors. `.gitignore` files and
+    /// `.git/info/exclude` only count inside a git worktree.
+    pub fn for_dir(dir: &Path) -> Self {
+        let workdir = workdir_of(dir);
+        let mut levels = Vec::new();
+        for ancestor in dir.ancestors() {
+            let in_repo = workdir.as_deref().is_some_and(|w| ancestor.starts_with(w));
+            let mut files = vec![ancestor.join(".ignore")];
+            if in_repo {
+                files.push(ancestor.join(".gitignore"));
+            }
+            if workdir.as_deref() == Some(ancestor) {
+                files.push(ancestor.join(".git/info/exclude"));
+            }
+            levels.extend(files.iter().filter_map(|f| load_ignore_file(ancestor, f)));
+        }
+        Self { levels }
+    }
+
+    /// Whether the entry at `path`, directly inside the directory the
+    /// rules are for, is ignored
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        for level in &self.levels {
+            match level.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+}
+
+fn load_ignore_file(root: &Path, file: &Path) -> Option<Gitignore> {
+    if !file.is_file() {
+        return None;
+    }
+    let mut builder = GitignoreBuilder::new(root);
+    // A bad line only drops that line
+    let _ = builder.add(file);
+    builder.build().ok().filter(|rules| !rules.is_empty())
+}
+
+/// Files in the git directory that change whenever the index, HEAD,
+/// stashes or an operation in progress do
+const STAMP_FILES: [&str; 7] = [
+    "index",
+    "HEAD",
+    "logs/HEAD",
+    "logs/refs/stash",
+    "FETCH_HEAD",
+    "MERGE_HEAD",
+    "rebase-merge",
+];
+
+/// Modification times of [`STAMP_FILES`]
+fn stamp(gitdir: &Path) -> Vec<Option<SystemTime>> {
+    STAMP_FILES
+        .iter()
+        .map(|f| fs::metadata(gitdir.join(f)).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+/// Status of a whole repository at one point in time
+#[derive(Debug)]
+pub struct RepoSnapshot {
+    dirs: DirStatuses,
+    pub info: Option<RepoInfo>,
+    gitdir: PathBuf,
+    stamp: Vec<Option<SystemTime>>,
+    /// When the scan started; files changed since may be missing
+    scanned_at: SystemTime,
+}
+
+impl RepoSnapshot {
+    /// Scan the repository whose worktree is `workdir`
+    fn scan(workdir: &Path) -> Self {
+        let scanned_at = SystemTime::now();
+        let repo = Repository::open(workdir).ok();
+        let gitdir = repo
+            .as_ref()
+            .map(|r| r.path().to_path_buf())
+            .unwrap_or_else(|| workdir.join(".git"));
+        // Stamp first, so changes made during the scan trigger another
+        let stamp = stamp(&gitdir);
+        Self {
+            dirs: repo.as_ref().map(collect_statuses).unwrap_or_default(),
+            info: get_repo_info(workdir),
+            gitdir,
+            stamp,
+            scanned_at,
+        }
+    }
+
+    /// Statuses of the direct children of `rel`, a directory relative to
+    /// the worktree
+    pub fn children(&self, rel: &Path) -> HashMap<String, GitFileStatus> {
+        self.dirs.get(rel).cloned().unwrap_or_default()
+    }
+
+    /// Whether the repository changed since the scan: its index or refs
+    /// did, or a file was modified at `newest`
+    fn outdated(&self, newest: Option<SystemTime>) -> bool {
+        newest.is_some_and(|t| t > self.scanned_at) || stamp(&self.gitdir) != self.stamp
+    }
+
+    fn age(&self) -> Duration {
+        self.scanned_at.elapsed().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Default)]
+struct RepoState {
+    snapshot: Option<Arc<RepoSnapshot>>,
+    scanning: bool,
+    /// Changed while scanning, so the scan's result is already outdated
+    rescan: bool,
+    /// Set by `watcher` when something in the worktree changes
+    dirty: Arc<AtomicBool>,
+    watcher: Option<RecommendedWatcher>,
+    /// The worktree could not be watched, so a worktree with changes is
+    /// rescanned every [`UNWATCHED_RESCAN`] instead
+    unwatched: bool,
+}
+
+/// How often a repository that can't be watched is rescanned while its
+/// worktree has changes
+const UNWATCHED_RESCAN: Duration = Duration::from_secs(5);
+
+/// Watch the worktree at `workdir`, setting `dirty` when a file in it
+/// changes. The git directory is left to [`stamp`], and files ignored by
+/// the top-level `.gitignore`, such as build output, to nobody.
+fn watch_worktree(workdir: &Path, dirty: Arc<AtomicBool>) -> Option<RecommendedWatcher> {
+    let gitdir = workdir.join(".git");
+    let root = workdir.to_path_buf();
+    let ignored = load_ignore_file(workdir, &workdir.join(".gitignore"));
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let Ok(event) = event else {
+            return;
         };
-        // We only care about the first component (the direct child)
-        let first = match rel.components().next() {
-            Some(c) => c.as_os_str().to_string_lossy().to_string(),
-            None => continue,
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        let relevant = event.paths.iter().any(|path| {
+            path.starts_with(&root)
+                && !path.starts_with(&gitdir)
+                && !ignored
+                    .as_ref()
+                    .is_some_and(|rules| rules.matched_path_or_any_parents(path, false).is_ignore())
+        });
+        if relevant {
+            dirty.store(true, Ordering::Release);
+        }
+    })
+    .ok()?;
+    watcher.watch(workdir, RecursiveMode::Recursive).ok()?;
+    Some(watcher)
+}
+
+/// Git status of every repository in use, scanned on background threads
+/// and shared by all tabs. Lookups never wait for a scan: they return the
+/// last snapshot, if any, and [`Self::generation`] moves on when a scan
+/// delivers a new one.
+#[derive(Debug, Default)]
+pub struct GitStatusCache {
+    repos: Mutex<HashMap<PathBuf, RepoState>>,
+    generation: AtomicU64,
+}
+
+impl GitStatusCache {
+    /// Last snapshot of the repository at `workdir`. Starts a scan if
+    /// there is none yet or it looks outdated, see [`RepoSnapshot::outdated`].
+    pub fn get(
+        self: &Arc<Self>,
+        workdir: &Path,
+        newest: Option<SystemTime>,
+    ) -> Option<Arc<RepoSnapshot>> {
+        let snapshot = {
+            let repos = self.repos.lock().ok()?;
+            repos.get(workdir).and_then(|r| r.snapshot.clone())
         };
+        if snapshot.as_ref().is_none_or(|s| s.outdated(newest)) {
+            self.invalidate(workdir);
+        }
+        snapshot
+    }
 
-        let file_status = if status.is_conflicted() {
-            GitFileStatus::Conflict
-        } else if status.is_index_new()
-            || status.is_index_modified()
-            || status.is_index_deleted()
-            || status.is_index_renamed()
-        {
-            GitFileStatus::Staged
-        } else if status.is_wt_modified() || status.is_wt_renamed() {
-            GitFileStatus::Modified
-        } else if status.is_wt_deleted() {
-            GitFileStatus::Deleted
-        } else if status.is_wt_new() {
-            GitFileStatus::Untracked
+    /// Scan `workdir` again, after the current scan if one is running
+    pub fn invalidate(self: &Arc<Self>, workdir: &Path) {
+        let Ok(mut repos) = self.repos.lock() else {
+            return;
+        };
+        let state = repos.entry(workdir.to_path_buf()).or_default();
+        if state.scanning {
+            state.rescan = true;
+            return;
+        }
+        state.scanning = true;
+        // Changes from here on are for the next scan
+        state.dirty.store(false, Ordering::Release);
+        let dirty = (state.watcher.is_none() && !state.unwatched).then(|| state.dirty.clone());
+        let cache = Arc::downgrade(self);
+        let workdir = workdir.to_path_buf();
+        thread::spawn(move || {
+            // Watching a big tree takes a while too; start before the
+            // scan so nothing changed during it is missed
+            let watcher = dirty.map(|dirty| watch_worktree(&workdir, dirty));
+            let snapshot = RepoSnapshot::scan(&workdir);
+            if let Some(cache) = Weak::upgrade(&cache) {
+                cache.deliver(workdir, snapshot, watcher);
+            }
+        });
+    }
+
+    /// Store a finished scan, and the watcher it started if it was the
+    /// first: `None` when there was none to start, `Some(None)` when the
+    /// worktree can't be watched
+    fn deliver(
+        self: &Arc<Self>,
+        workdir: PathBuf,
+        snapshot: RepoSnapshot,
+        watcher: Option<Option<RecommendedWatcher>>,
+    ) {
+        let rescan = {
+            let Ok(mut repos) = self.repos.lock() else {
+                return;
+            };
+            let state = repos.entry(workdir.clone()).or_default();
+            state.snapshot = Some(Arc::new(snapshot));
+            state.scanning = false;
+            match watcher {
+                Some(Some(watcher)) => state.watcher = Some(watcher),
+                Some(None) => state.unwatched = true,
+                None => {}
+            }
+            std::mem::take(&mut state.rescan)
+        };
+        self.generation.fetch_add(1, Ordering::Release);
+        if rescan {
+            self.invalidate(&workdir);
+        }
+    }
+
+    /// Rescan every known repository that changed: a file in its worktree
+    /// did, or its index or refs, such as after a commit made outside velo
+    pub fn revalidate(self: &Arc<Self>) {
+        let outdated: Vec<PathBuf> = match self.repos.lock() {
+            Ok(repos) => repos
+                .iter()
+                .filter(|(_, r)| {
+                    let Some(snapshot) = &r.snapshot else {
+                        return false;
+                    };
+                    r.dirty.load(Ordering::Acquire)
+                        || snapshot.outdated(None)
+                        || (r.unwatched
+                            && !snapshot.dirs.is_empty()
+                            && snapshot.age() > UNWATCHED_RESCAN)
+                })
+                .map(|(w, _)| w.clone())
+                .collect(),
+            Err(_) => return,
+        };
+        for workdir in outdated {
+            self.invalidate(&workdir);
+        }
+    }
+
+    /// Whether a scan of `workdir` is running
+    pub fn is_scanning(&self, workdir: &Path) -> bool {
+        self.repos
+            .lock()
+            .is_ok_and(|r| r.get(workdir).is_some_and(|s| s.scanning))
+    }
+
+    /// Counts delivered snapshots; a change means there is news to show
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+}
+
+/// Where HEAD points
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Head {
+    Branch(String),
+    /// Detached at this abbreviated commit id
+    Detached(String),
+    /// A branch with no commits yet
+    Unborn(String),
+}
+
+/// Repository-wide state for the status bar
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoInfo {
+    pub head: Head,
+    /// Commits ahead of and behind the upstream branch, if there is one
+    pub ahead_behind: Option<(usize, usize)>,
+    pub stashes: usize,
+    /// Operation in progress, such as `MERGING`
+    pub state: Option<&'static str>,
+}
+
+impl RepoInfo {
+    /// Compact form such as `⎇ main ↑2 ↓1 │ stash 3 │ MERGING`
+    pub fn summary(&self) -> String {
+        let mut text = match &self.head {
+            Head::Branch(name) => format!("⎇ {name}"),
+            Head::Detached(id) => format!("⎇ detached@{id}"),
+            Head::Unborn(name) => format!("⎇ {name} (no commits)"),
+        };
+        match self.ahead_behind {
+            Some((0, 0)) => text.push_str(" ≡"),
+            Some((ahead, behind)) => {
+                if ahead > 0 {
+                    text.push_str(&format!(" ↑{ahead}"));
+                }
+                if behind > 0 {
+                    text.push_str(&format!(" ↓{behind}"));
+                }
+            }
+            None => {}
+        }
+        if self.stashes > 0 {
+            text.push_str(&format!(" │ stash {}", self.stashes));
+        }
+        if let Some(state) = self.state {
+            text.push_str(&format!(" │ {state}"));
+        }
+        text
+    }
+}
+
+/// Branch, upstream distance, stashes and in-progress operation of the
+/// repository containing `dir`. Local only: nothing is fetched.
+pub fn get_repo_info(dir: &Path) -> Option<RepoInfo> {
+    let repo = Repository::discover(dir).ok()?;
+    let (head, ahead_behind) = match repo.head() {
+        Ok(head_ref) if repo.head_detached().unwrap_or(false) => {
+            let id = head_ref
+                .peel_to_commit()
+                .ok()?
+                .as_object()
+                .short_id()
+                .ok()?;
+            (
+                Head::Detached(id.as_str().unwrap_or_default().to_string()),
+                None,
+            )
+        }
+        Ok(head_ref) => {
+            let name = head_ref.shorthand().unwrap_or("HEAD").to_string();
+            let ahead_behind = repo
+                .find_branch(&name, BranchType::Local)
+                .and_then(|b| b.upstream())
+                .ok()
+                .and_then(|upstream| {
+                    let local = head_ref.target()?;
+                    let remote = upstream.get().target()?;
+                    repo.graph_ahead_behind(local, remote).ok()
+                });
+            (Head::Branch(name), ahead_behind)
+        }
+        // HEAD names a branch that doesn't exist yet
+        Err(_) => {
+            let target = repo.find_reference("HEAD").ok()?;
+            let branch = target
+                .symbolic_target()
+                .and_then(|t| t.strip_prefix("refs/heads/"))
+                .unwrap_or("HEAD")
+                .to_string();
+            (Head::Unborn(branch), None)
+        }
+    };
+    // Reading a missing reflog would create it
+    let stashes = match repo.find_reference("refs/stash") {
+        Ok(_) => repo.reflog("refs/stash").map(|r| r.len()).unwrap_or(0),
+        Err(_) => 0,
+    };
+    let state = match repo.state() {
+        RepositoryState::Clean => None,
+        RepositoryState::Merge => Some("MERGING"),
+        RepositoryState::Revert | RepositoryState::RevertSequence => Some("REVERTING"),
+        RepositoryState::CherryPick | RepositoryState::CherryPickSequence => Some("CHERRY-PICKING"),
+        RepositoryState::Bisect => Some("BISECTING"),
+        RepositoryState::Rebase
+        | RepositoryState::RebaseInteractive
+        | RepositoryState::RebaseMerge
+        | RepositoryState::ApplyMailboxOrRebase => Some("REBASING"),
+        RepositoryState::ApplyMailbox => Some("APPLYING PATCHES"),
+    };
+    Some(RepoInfo {
+        head,
+        ahead_behind,
+        stashes,
+        state,
+    })
+}
+
+/// The repository holding `paths`, and each path relative to its
+/// worktree, empty for the worktree itself. A directory covers everything
+/// below it, see [`covered`].
+fn open_for(paths: &[PathBuf]) -> Result<(Repository, Vec<String>), String> {
+    let first = paths.first().ok_or("Nothing selected")?;
+    // A directory may be the worktree root itself
+    let start = if first.is_dir() {
+        first
+    } else {
+        first.parent().unwrap_or(first)
+    };
+    let repo =
+        Repository::discover(start).map_err(|_| "Not inside a git repository".to_string())?;
+    let workdir = repo
+        .workdir()
+        .ok_or("Bare repository")?
+        .canonicalize()
+        .map_err(|e| e.to_string())?;
+    let mut specs = Vec::with_capacity(paths.len());
+    for path in paths {
+        // The file itself may be deleted; its directory is still there
+        let parent = path
+            .parent()
+            .and_then(|p| p.canonicalize().ok())
+            .ok_or_else(|| format!("Cannot resolve {}", path.display()))?;
+        let full = match path.file_name() {
+            Some(name) => parent.join(name),
+            None => parent,
+        };
+        let rel = full
+            .strip_prefix(&workdir)
+            .map_err(|_| format!("{} is outside the repository", path.display()))?;
+        specs.push(rel.to_string_lossy().to_string());
+    }
+    Ok((repo, specs))
+}
+
+/// Whether `path`, relative to the worktree, is one of `specs` or inside
+/// one. libgit2 matches index pathspecs as globs whatever the flags say,
+/// so `[ab].txt` would also pick `a.txt`; paths are matched here instead.
+fn covered(specs: &[String], path: &str) -> bool {
+    specs.iter().any(|spec| {
+        spec.is_empty()
+            || path
+                .strip_prefix(spec.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
+
+/// Offers every path to the [`covered`] callback. Not an empty list: git2
+/// hands the callback the matching pathspec, and there would be none.
+const EVERY_PATH: [&str; 1] = ["*"];
+
+/// `git add` the paths: new and changed files are staged, deleted ones
+/// removed from the index
+pub fn stage_paths(paths: &[PathBuf]) -> Result<(), String> {
+    let (repo, specs) = open_for(paths)?;
+    let mut matched = |path: &Path, _: &[u8]| i32::from(!covered(&specs, &path.to_string_lossy()));
+    let mut index = repo.index().map_err(|e| e.to_string())?;
+    index
+        .add_all(EVERY_PATH, IndexAddOption::DEFAULT, Some(&mut matched))
+        .map_err(|e| e.to_string())?;
+    index
+        .update_all(EVERY_PATH, Some(&mut matched))
+        .map_err(|e| e.to_string())?;
+    index.write().map_err(|e| e.to_string())
+}
+
+/// `git reset` the paths: the index goes back to HEAD, the worktree is
+/// left alone
+pub fn unstage_paths(paths: &[PathBuf]) -> Result<(), String> {
+    let (repo, specs) = open_for(paths)?;
+    let mut index = repo.index().map_err(|e| e.to_string())?;
+    let head = match repo.head().and_then(|h| h.peel_to_tree()) {
+        Ok(head) => head,
+        // Nothing committed yet: unstaging means dropping from the index
+        Err(_) => {
+            let mut matched =
+                |path: &Path, _: &[u8]| i32::from(!covered(&specs, &path.to_string_lossy()));
+            index
+                .remove_all(EVERY_PATH, Some(&mut matched))
+                .map_err(|e| e.to_string())?;
+            return index.write().map_err(|e| e.to_string());
+        }
+    };
+    // What `git_reset_default` does, minus its glob pathspecs
+    let staged = repo
+        .diff_tree_to_index(Some(&head), Some(&index), None)
+        .map_err(|e| e.to_string())?;
+    let changed: Vec<String> = staged
+        .deltas()
+        .filter_map(|delta| {
+            delta
+                .new_file()
+                .path_bytes()
+                .or(delta.old_file().path_bytes())
+        })
+        .map(|path| String::from_utf8_lossy(path).into_owned())
+        .filter(|path| covered(&specs, path))
+        .collect();
+    for path in changed {
+        let path = Path::new(&path);
+        for stage in 1..=3 {
+            let _ = index.remove(path, stage);
+        }
+        match head.get_path(path) {
+            Ok(entry) => index
+                .add(&IndexEntry {
+                    ctime: IndexTime::new(0, 0),
+                    mtime: IndexTime::new(0, 0),
+                    dev: 0,
+                    ino: 0,
+                    mode: entry.filemode() as u32,
+                    uid: 0,
+                    gid: 0,
+                    file_size: 0,
+                    id: entry.id(),
+                    flags: 0,
+                    flags_extended: 0,
+                    path: path.to_string_lossy().as_bytes().to_vec(),
+                })
+                .map_err(|e| e.to_string())?,
+            Err(_) => index.remove_path(path).map_err(|e| e.to_string())?,
+        }
+    }
+    index.write().map_err(|e| e.to_string())
+}
+
+/// `git checkout -- paths`: throw away worktree changes to tracked files,
+/// restoring what is in the index. Untracked files are not touched.
+pub fn discard_paths(paths: &[PathBuf]) -> Result<(), String> {
+    let (repo, specs) = open_for(paths)?;
+    let index = repo.index().map_err(|e| e.to_string())?;
+    let mut tracked: Vec<String> = index
+        .iter()
+        .map(|entry| String::from_utf8_lossy(&entry.path).into_owned())
+        .filter(|path| covered(&specs, path))
+        .collect();
+    tracked.dedup();
+    // No paths at all would check out the whole worktree
+    if tracked.is_empty() {
+        return Ok(());
+    }
+    let mut checkout = CheckoutBuilder::new();
+    checkout.force();
+    for path in &tracked {
+        checkout.path(literal_pathspec(path));
+    }
+    repo.checkout_index(None, Some(&mut checkout))
+        .map_err(|e| e.to_string())
+}
+
+/// `path` as a pathspec matching only itself. git2 has no way to turn off
+/// glob matching for checkouts, so wildcards, and the `!` and `#` that
+/// would make a negation or a comment, are escaped.
+fn literal_pathspec(path: &str) -> String {
+    let mut spec = String::with_capacity(path.len());
+    for c in path.chars() {
+        if matches!(c, '\\' | '*' | '?' | '[' | ']' | '!' | '#' | ' ') {
+            spec.push('\\');
+        }
+        spec.push(c);
+    }
+    spec
+}
+
+/// What a line of [`file_diff`] output is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffLineKind {
+    /// Title of the staged or unstaged part
+    Section,
+    /// `@@ -1,3 +1,4 @@`
+    Hunk,
+    Added,
+    Removed,
+    Context,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+    pub text: String,
+}
+
+/// Unified diff of one file: staged changes (HEAD to index), then
+/// unstaged ones (index to worktree). Empty if the file is unchanged.
+pub fn file_diff(path: &Path) -> Result<Vec<DiffLine>, String> {
+    let (repo, specs) = open_for(&[path.to_path_buf()])?;
+    let mut opts = DiffOptions::new();
+    opts.pathspec(&specs[0])
+        .disable_pathspec_match(true)
+        .include_untracked(true)
+        .show_untracked_content(true);
+    let head = repo.head().and_then(|h| h.peel_to_tree()).ok();
+    let index = repo.index().map_err(|e| e.to_string())?;
+    let staged = repo
+        .diff_tree_to_index(head.as_ref(), Some(&index), Some(&mut opts))
+        .map_err(|e| e.to_string())?;
+    let unstaged = repo
+        .diff_index_to_workdir(Some(&index), Some(&mut opts))
+        .map_err(|e| e.to_string())?;
+    let mut lines = Vec::new();
+    append_diff(&mut lines, "Staged changes", &staged)?;
+    append_diff(&mut lines, "Unstaged changes", &unstaged)?;
+    Ok(lines)
+}
+
+fn append_diff(lines: &mut Vec<DiffLine>, title: &str, diff: &Diff) -> Result<(), String> {
+    let mut body = Vec::new();
+    diff.print(DiffFormat::Patch, |_, _, line| {
+        let text = String::from_utf8_lossy(line.content());
+        let text = text.trim_end_matches(['\n', '\r']);
+        let (kind, text) = match line.origin() {
+            '+' => (DiffLineKind::Added, format!("+{text}")),
+            '-' => (DiffLineKind::Removed, format!("-{text}")),
+            ' ' => (DiffLineKind::Context, format!(" {text}")),
+            'H' => (DiffLineKind::Hunk, text.to_string()),
+            // "\ No newline at end of file" and the like
+            '=' | '>' | '<' => (DiffLineKind::Context, text.trim_start().to_string()),
+            'B' => (DiffLineKind::Context, text.to_string()),
+            // File headers repeat what the preview title already says
+            _ => return true,
+        };
+        body.push(DiffLine { kind, text });
+        true
+    })
+    .map_err(|e| e.to_string())?;
+    if !body.is_empty() {
+        lines.push(DiffLine {
+            kind: DiffLineKind::Section,
+            text: title.to_string(),
+        });
+        lines.append(&mut body);
+    }
+    Ok(())
+}
+
+/// A commit in the history of one path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitInfo {
+    pub id: String,
+    pub short: String,
+    pub author: String,
+    /// Commit time, seconds since the epoch
+    pub time: i64,
+    pub subject: String,
+}
+
+/// Up to `limit` commits reachable from HEAD that changed `path`, newest
+/// first. Like plain `git log -- path`, renames are not followed.
+pub fn file_log(path: &Path, limit: usize) -> Result<Vec<CommitInfo>, String> {
+    let (repo, specs) = open_for(&[path.to_path_buf()])?;
+    let spec = Path::new(&specs[0]);
+    let entry_id = |commit: &Commit| -> Option<Oid> {
+        if specs[0].is_empty() {
+            return Some(commit.tree_id());
+        }
+        commit.tree().ok()?.get_path(spec).ok().map(|e| e.id())
+    };
+    let mut walk = repo.revwalk().map_err(|e| e.to_string())?;
+    walk.set_sorting(Sort::TIME).map_err(|e| e.to_string())?;
+    if walk.push_head().is_err() {
+        // No commits yet
+        return Ok(Vec::new());
+    }
+    let mut log = Vec::new();
+    for id in walk {
+        let commit = repo
+            .find_commit(id.map_err(|e| e.to_string())?)
+            .map_err(|e| e.to_string())?;
+        let ours = entry_id(&commit);
+        // A merge counts only if it differs from every parent
+        let touched = if commit.parent_count() == 0 {
+            ours.is_some()
         } else {
-            continue;
+            commit.parents().all(|parent| entry_id(&parent) != ours)
         };
+        if !touched {
+            continue;
+        }
+        let short = commit.as_object().short_id().map_err(|e| e.to_string())?;
+        log.push(CommitInfo {
+            id: commit.id().to_string(),
+            short: short.as_str().unwrap_or_default().to_string(),
+            author: commit.author().name().unwrap_or("unknown").to_string(),
+            time: commit.time().seconds(),
+            subject: commit.summary().unwrap_or_default().to_string(),
+        });
+        if log.len() >= limit {
+            break;
+        }
+    }
+    Ok(log)
+}
+
+/// Directory of velo's cache holding old revisions of files
+pub const REVISIONS_DIR: &str = "revisions";
 
-        // Don't override a higher-priority status
-        map.entry(first).or_insert(file_status);
+/// Write the file at `path` as of commit `id` to a scratch copy under
+/// velo's `cache_dir` that keeps its name, so the preview highlights it
+/// like the original
+pub fn materialize_revision(path: &Path, id: &str, cache_dir: &Path) -> Result<PathBuf, String> {
+    let (repo, specs) = open_for(&[path.to_path_buf()])?;
+    let target = cache_dir.join(REVISIONS_DIR).join(id).join(&specs[0]);
+    if target.exists() {
+        return Ok(target);
     }
+    let content = blob_at(&repo, &specs[0], id)?;
+    let parent = target.parent().ok_or("Invalid path")?;
+    std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    std::fs::write(&target, content).map_err(|e| e.to_string())?;
+    Ok(target)
+}
+
+fn blob_at(repo: &Repository, spec: &str, id: &str) -> Result<Vec<u8>, String> {
+    let tree = find_commit(repo, id)?.tree().map_err(|e| e.to_string())?;
+    let entry = tree
+        .get_path(Path::new(spec))
+        .map_err(|_| format!("Not in commit {}", &id[..id.len().min(7)]))?;
+    let blob = repo
+        .find_blob(entry.id())
+        .map_err(|_| "Not a file".to_string())?;
+    Ok(blob.content().to_vec())
+}
+
+fn find_commit<'r>(repo: &'r Repository, id: &str) -> Result<Commit<'r>, String> {
+    Oid::from_str(id)
+        .and_then(|oid| repo.find_commit(oid))
+        .map_err(|e| e.to_string())
+}
 
-    map
+/// One line of [`file_blame`] output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlameLine {
+    /// Abbreviated id of the commit that last changed the line, `None`
+    /// for changes not committed yet
+    pub commit: Option<String>,
+    pub author: String,
+    pub text: String,
+}
+
+/// Who last changed each line of `path`: as of commit `at`, or in the
+/// worktree copy when `at` is `None`
+pub fn file_blame(path: &Path, at: Option<&str>) -> Result<Vec<BlameLine>, String> {
+    let (repo, specs) = open_for(&[path.to_path_buf()])?;
+    let mut opts = BlameOptions::new();
+    if let Some(id) = at {
+        opts.newest_commit(find_commit(&repo, id)?.id());
+    }
+    let committed = repo
+        .blame_file(Path::new(&specs[0]), Some(&mut opts))
+        .map_err(|e| e.to_string())?;
+    let (blame, content) = match at {
+        Some(id) => (committed, blob_at(&repo, &specs[0], id)?),
+        None => {
+            let content = std::fs::read(path).map_err(|e| e.to_string())?;
+            let blame = committed
+                .blame_buffer(&content)
+                .map_err(|e| e.to_string())?;
+            (blame, content)
+        }
+    };
+    let text = String::from_utf8_lossy(&content);
+    let mut lines = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let hunk = blame.get_line(i + 1);
+        // Lines only in the buffer have no commit, nor a signature to read
+        let (commit, author) = match hunk.filter(|h| !h.final_commit_id().is_zero()) {
+            Some(hunk) => {
+                let id = hunk.final_commit_id().to_string();
+                let author = hunk
+                    .final_signature()
+                    .name()
+                    .unwrap_or("unknown")
+                    .to_string();
+                (Some(id[..7].to_string()), author)
+            }
+            None => (None, "Not committed".to_string()),
+        };
+        lines.push(BlameLine {
+            commit,
+            author,
+            text: line.to_string(),
+        });
+    }
+    Ok(lines)
 }
 
 #[cfg(test)]
@@ -107,12 +976,57 @@ mod tests {
         assert_eq!(GitFileStatus::Deleted.icon(), "D");
         assert_eq!(GitFileStatus::Renamed.icon(), "R");
         assert_eq!(GitFileStatus::Ignored.icon(), "I");
+        assert_eq!(GitFileStatus::StagedModified.icon(), "SM");
+    }
+
+    #[test]
+    fn test_combine_priority() {
+        use GitFileStatus::*;
+        // Order of arrival doesn't matter
+        for (a, b, expected) in [
+            (Untracked, Conflict, Conflict),
+            (Modified, Deleted, Modified),
+            (Deleted, Untracked, Deleted),
+            (Ignored, Untracked, Untracked),
+            (Staged, Modified, StagedModified),
+            (Renamed, Modified, StagedModified),
+            (Renamed, Staged, Staged),
+            (StagedModified, Conflict, Conflict),
+            (Staged, Staged, Staged),
+        ] {
+            assert_eq!(a.combine(b), expected, "{a:?} + {b:?}");
+            assert_eq!(b.combine(a), expected, "{b:?} + {a:?}");
+        }
+    }
+
+    /// Commit everything in the index on top of HEAD, if any
+    fn commit_all(repo: &Repository) {
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = git2::Signature::now("Test", "test@example.com").unwrap();
+        let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let parents: Vec<_> = parent.iter().collect();
+        repo.commit(Some("HEAD"), &sig, &sig, "commit", &tree, &parents)
+            .unwrap();
+    }
+
+    /// Statuses of the direct children of `dir`, from a fresh scan of its
+    /// repository
+    fn scan_children(dir: &Path) -> HashMap<String, GitFileStatus> {
+        let Some(workdir) = workdir_of(dir) else {
+            return HashMap::new();
+        };
+        RepoSnapshot::scan(&workdir).children(dir.strip_prefix(&workdir).unwrap())
     }
 
     #[test]
     fn test_non_git_dir_returns_empty() {
         let tmp = TempDir::new().unwrap();
-        let statuses = get_git_statuses(tmp.path());
+        let statuses = scan_children(tmp.path());
         assert!(statuses.is_empty());
     }
 
@@ -123,7 +1037,7 @@ mod tests {
         let dir = tmp.path().canonicalize().unwrap();
         Repository::init(&dir).unwrap();
         std::fs::write(dir.join("new.txt"), "hello").unwrap();
-        let statuses = get_git_statuses(&dir);
+        let statuses = scan_children(&dir);
         assert_eq!(statuses.get("new.txt"), Some(&GitFileStatus::Untracked));
     }
 
@@ -136,7 +1050,449 @@ mod tests {
         let mut index = repo.index().unwrap();
         index.add_path(Path::new("staged.txt")).unwrap();
         index.write().unwrap();
-        let statuses = get_git_statuses(&dir);
+        let statuses = scan_children(&dir);
         assert_eq!(statuses.get("staged.txt"), Some(&GitFileStatus::Staged));
     }
+
+    #[test]
+    fn test_staged_and_modified_file() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        let repo = Repository::init(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), "one").unwrap();
+        commit_all(&repo);
+        std::fs::write(dir.join("a.txt"), "two").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.write().unwrap();
+        std::fs::write(dir.join("a.txt"), "three").unwrap();
+        let statuses = scan_children(&dir);
+        assert_eq!(statuses.get("a.txt"), Some(&GitFileStatus::StagedModified));
+    }
+
+    #[test]
+    fn test_directory_takes_most_severe_status() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        let repo = Repository::init(&dir).unwrap();
+        std::fs::create_dir(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src/gone.rs"), "x").unwrap();
+        std::fs::write(dir.join("src/kept.rs"), "x").unwrap();
+        commit_all(&repo);
+        // A deletion beside many untracked files still shows as deleted
+        std::fs::remove_file(dir.join("src/gone.rs")).unwrap();
+        for i in 0..5 {
+            std::fs::write(dir.join(format!("src/new{i}.rs")), "x").unwrap();
+        }
+        assert_eq!(
+            scan_children(&dir).get("src"),
+            Some(&GitFileStatus::Deleted)
+        );
+        std::fs::write(dir.join("src/kept.rs"), "changed").unwrap();
+        assert_eq!(
+            scan_children(&dir).get("src"),
+            Some(&GitFileStatus::Modified)
+        );
+    }
+
+    #[test]
+    fn test_renamed_and_ignored() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        let repo = Repository::init(&dir).unwrap();
+        std::fs::write(dir.join("old.txt"), "some content to track\n").unwrap();
+        std::fs::write(dir.join(".gitignore"), "target/\n*.log\n").unwrap();
+        commit_all(&repo);
+
+        std::fs::rename(dir.join("old.txt"), dir.join("new.txt")).unwrap();
+        let mut index = repo.index().unwrap();
+        index.remove_path(Path::new("old.txt")).unwrap();
+        index.add_path(Path::new("new.txt")).unwrap();
+        index.write().unwrap();
+        std::fs::create_dir_all(dir.join("target/debug")).unwrap();
+        std::fs::write(dir.join("target/debug/app"), "bin").unwrap();
+        std::fs::create_dir(dir.join("logs")).unwrap();
+        std::fs::write(dir.join("logs/run.log"), "log").unwrap();
+
+        let statuses = scan_children(&dir);
+        assert_eq!(statuses.get("new.txt"), Some(&GitFileStatus::Renamed));
+        assert_eq!(statuses.get("old.txt"), None);
+        assert_eq!(statuses.get("target"), Some(&GitFileStatus::Ignored));
+        // Only ignored content inside: the directory itself isn't ignored
+        assert_eq!(statuses.get("logs"), None);
+        assert_eq!(
+            scan_children(&dir.join("logs")).get("run.log"),
+            Some(&GitFileStatus::Ignored)
+        );
+    }
+
+    #[test]
+    fn test_repo_info() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        assert_eq!(get_repo_info(&dir), None);
+        let mut repo = Repository::init(&dir).unwrap();
+        repo.set_head("refs/heads/main").unwrap();
+        let info = get_repo_info(&dir).unwrap();
+        assert_eq!(info.head, Head::Unborn("main".to_string()));
+
+        std::fs::write(dir.join("a.txt"), "1").unwrap();
+        commit_all(&repo);
+        let base = repo.head().unwrap().peel_to_commit().unwrap().id();
+        repo.branch("base", &repo.find_commit(base).unwrap(), false)
+            .unwrap();
+        std::fs::write(dir.join("a.txt"), "2").unwrap();
+        commit_all(&repo);
+        std::fs::write(dir.join("a.txt"), "3").unwrap();
+        commit_all(&repo);
+        repo.find_branch("main", BranchType::Local)
+            .unwrap()
+            .set_upstream(Some("base"))
+            .unwrap();
+        let info = get_repo_info(&dir.join(".git")).unwrap();
+        assert_eq!(info.head, Head::Branch("main".to_string()));
+        assert_eq!(info.ahead_behind, Some((2, 0)));
+        assert_eq!(info.summary(), "⎇ main ↑2");
+
+        std::fs::write(dir.join("a.txt"), "dirty").unwrap();
+        let sig = git2::Signature::now("Test", "test@example.com").unwrap();
+        repo.stash_save(&sig, "wip", None).unwrap();
+        std::fs::write(dir.join(".git/MERGE_HEAD"), format!("{base}\n")).unwrap();
+        let info = get_repo_info(&dir).unwrap();
+        assert_eq!(info.stashes, 1);
+        assert_eq!(info.state, Some("MERGING"));
+        assert_eq!(info.summary(), "⎇ main ↑2 │ stash 1 │ MERGING");
+        std::fs::remove_file(dir.join(".git/MERGE_HEAD")).unwrap();
+
+        repo.set_head_detached(base).unwrap();
+        let info = get_repo_info(&dir).unwrap();
+        assert!(
+            matches!(&info.head, Head::Detached(id) if base.to_string().starts_with(id.as_str()))
+        );
+        assert_eq!(info.ahead_behind, None);
+    }
+
+    #[test]
+    fn test_stage_unstage_discard() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        let repo = Repository::init(&dir).unwrap();
+        std::fs::create_dir(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src/a.rs"), "a").unwrap();
+        std::fs::write(dir.join("gone.txt"), "g").unwrap();
+        commit_all(&repo);
+        std::fs::write(dir.join("src/a.rs"), "changed").unwrap();
+        std::fs::write(dir.join("src/new.rs"), "n").unwrap();
+        std::fs::remove_file(dir.join("gone.txt")).unwrap();
+
+        // A directory stages everything below it
+        stage_paths(&[dir.join("src"), dir.join("gone.txt")]).unwrap();
+        let statuses = scan_children(&dir.join("src"));
+        assert_eq!(statuses.get("a.rs"), Some(&GitFileStatus::Staged));
+        assert_eq!(statuses.get("new.rs"), Some(&GitFileStatus::Staged));
+        assert_eq!(
+            scan_children(&dir).get("gone.txt"),
+            Some(&GitFileStatus::Staged)
+        );
+
+        unstage_paths(&[dir.join("src/a.rs"), dir.join("gone.txt")]).unwrap();
+        let statuses = scan_children(&dir.join("src"));
+        assert_eq!(statuses.get("a.rs"), Some(&GitFileStatus::Modified));
+        assert_eq!(statuses.get("new.rs"), Some(&GitFileStatus::Staged));
+        assert_eq!(
+            scan_children(&dir).get("gone.txt"),
+            Some(&GitFileStatus::Deleted)
+        );
+
+        std::fs::write(dir.join("untracked.txt"), "u").unwrap();
+        discard_paths(&[dir.join("src/a.rs"), dir.join("gone.txt")]).unwrap();
+        discard_paths(&[dir.join("untracked.txt")]).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("src/a.rs")).unwrap(), "a");
+        assert_eq!(std::fs::read_to_string(dir.join("gone.txt")).unwrap(), "g");
+        assert!(dir.join("untracked.txt").exists());
+        // Staged work survives a discard
+        assert_eq!(
+            std::fs::read_to_string(dir.join("src/new.rs")).unwrap(),
+            "n"
+        );
+
+        let outside = TempDir::new().unwrap();
+        assert!(stage_paths(&[outside.path().join("x")]).is_err());
+    }
+
+    #[test]
+    fn test_glob_characters_are_literal() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        let repo = Repository::init(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), "a").unwrap();
+        std::fs::write(dir.join("[ab].txt"), "ab").unwrap();
+        std::fs::write(dir.join("#x"), "x").unwrap();
+        commit_all(&repo);
+        std::fs::write(dir.join("a.txt"), "a edited").unwrap();
+        std::fs::write(dir.join("[ab].txt"), "ab edited").unwrap();
+        std::fs::write(dir.join("#x"), "x edited").unwrap();
+
+        stage_paths(&[dir.join("[ab].txt")]).unwrap();
+        let statuses = scan_children(&dir);
+        assert_eq!(statuses.get("[ab].txt"), Some(&GitFileStatus::Staged));
+        assert_eq!(statuses.get("a.txt"), Some(&GitFileStatus::Modified));
+
+        stage_paths(&[dir.join("a.txt")]).unwrap();
+        unstage_paths(&[dir.join("[ab].txt")]).unwrap();
+        let statuses = scan_children(&dir);
+        assert_eq!(statuses.get("[ab].txt"), Some(&GitFileStatus::Modified));
+        assert_eq!(statuses.get("a.txt"), Some(&GitFileStatus::Staged));
+        unstage_paths(&[dir.join("a.txt")]).unwrap();
+
+        // Neither the sibling the glob would match, nor everything
+        discard_paths(&[dir.join("[ab].txt")]).unwrap();
+        discard_paths(&[dir.join("#x")]).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("[ab].txt")).unwrap(), "ab");
+        assert_eq!(std::fs::read_to_string(dir.join("#x")).unwrap(), "x");
+        assert_eq!(
+            std::fs::read_to_string(dir.join("a.txt")).unwrap(),
+            "a edited"
+        );
+
+        // The worktree root covers everything
+        stage_paths(std::slice::from_ref(&dir)).unwrap();
+        assert_eq!(
+            scan_children(&dir).get("a.txt"),
+            Some(&GitFileStatus::Staged)
+        );
+        unstage_paths(std::slice::from_ref(&dir)).unwrap();
+        discard_paths(std::slice::from_ref(&dir)).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("a.txt")).unwrap(), "a");
+    }
+
+    #[test]
+    fn test_unstage_before_first_commit() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        Repository::init(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), "a").unwrap();
+        stage_paths(&[dir.join("a.txt")]).unwrap();
+        assert_eq!(
+            scan_children(&dir).get("a.txt"),
+            Some(&GitFileStatus::Staged)
+        );
+        unstage_paths(&[dir.join("a.txt")]).unwrap();
+        assert_eq!(
+            scan_children(&dir).get("a.txt"),
+            Some(&GitFileStatus::Untracked)
+        );
+    }
+
+    #[test]
+    fn test_file_diff_sections() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        let repo = Repository::init(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), "one\ntwo\nthree\n").unwrap();
+        commit_all(&repo);
+        assert!(file_diff(&dir.join("a.txt")).unwrap().is_empty());
+
+        std::fs::write(dir.join("a.txt"), "one\n2\nthree\n").unwrap();
+        stage_paths(&[dir.join("a.txt")]).unwrap();
+        std::fs::write(dir.join("a.txt"), "one\n2\nthree\nfour\n").unwrap();
+        let diff = file_diff(&dir.join("a.txt")).unwrap();
+        let kinds: Vec<_> = diff.iter().map(|l| l.kind).collect();
+        let text: Vec<_> = diff.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(text[0], "Staged changes");
+        assert!(text[1].starts_with("@@ -1,3 +1,3 @@"));
+        assert_eq!(&text[2..6], [" one", "-two", "+2", " three"]);
+        assert_eq!(
+            &kinds[2..6],
+            [
+                DiffLineKind::Context,
+                DiffLineKind::Removed,
+                DiffLineKind::Added,
+                DiffLineKind::Context
+            ]
+        );
+        let unstaged = text.iter().position(|t| *t == "Unstaged changes").unwrap();
+        assert_eq!(text.last(), Some(&"+four"));
+        assert_eq!(kinds[unstaged], DiffLineKind::Section);
+    }
+
+    #[test]
+    fn test_file_log_and_revision() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        let repo = Repository::init(&dir).unwrap();
+        assert!(file_log(&dir, 10).unwrap().is_empty());
+        std::fs::write(dir.join("a.txt"), "first\n").unwrap();
+        commit_all(&repo);
+        std::fs::write(dir.join("b.txt"), "other\n").unwrap();
+        commit_all(&repo);
+        std::fs::write(dir.join("a.txt"), "second\n").unwrap();
+        commit_all(&repo);
+
+        let log = file_log(&dir.join("a.txt"), 10).unwrap();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[0].author, "Test");
+        assert_eq!(log[0].subject, "commit");
+        assert_eq!(log[0].short, &log[0].id[..log[0].short.len()]);
+        assert_eq!(file_log(&dir.join("b.txt"), 10).unwrap().len(), 1);
+        assert_eq!(file_log(&dir, 10).unwrap().len(), 3);
+        assert_eq!(file_log(&dir, 1).unwrap().len(), 1);
+
+        let cache = TempDir::new().unwrap();
+        let old = materialize_revision(&dir.join("a.txt"), &log[1].id, cache.path()).unwrap();
+        assert!(old.starts_with(cache.path().join(REVISIONS_DIR)));
+        assert!(old.ends_with("a.txt"));
+        assert_eq!(std::fs::read_to_string(&old).unwrap(), "first\n");
+        assert!(materialize_revision(&dir.join("b.txt"), &log[1].id, cache.path()).is_err());
+    }
+
+    #[test]
+    fn test_file_blame() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        let repo = Repository::init(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), "one\ntwo\n").unwrap();
+        commit_all(&repo);
+        let first = repo.head().unwrap().target().unwrap().to_string();
+        std::fs::write(dir.join("a.txt"), "one\n2\n").unwrap();
+        commit_all(&repo);
+        std::fs::write(dir.join("a.txt"), "one\n2\nthree\n").unwrap();
+
+        let blame = file_blame(&dir.join("a.txt"), None).unwrap();
+        let texts: Vec<_> = blame.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(texts, ["one", "2", "three"]);
+        assert_eq!(blame[0].commit.as_deref(), Some(&first[..7]));
+        assert_ne!(blame[1].commit, blame[0].commit);
+        assert_eq!(blame[1].author, "Test");
+        assert_eq!(blame[2].commit, None);
+
+        let then = file_blame(&dir.join("a.txt"), Some(&first)).unwrap();
+        let texts: Vec<_> = then.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(texts, ["one", "two"]);
+        assert!(then
+            .iter()
+            .all(|l| l.commit.as_deref() == Some(&first[..7])));
+    }
+
+    #[test]
+    fn test_status_cache_scans_in_background() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        let repo = Repository::init(&dir).unwrap();
+        std::fs::create_dir(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src/lib.rs"), "fn main() {}").unwrap();
+        let cache = Arc::new(GitStatusCache::default());
+        let wait = |cache: &GitStatusCache| {
+            for _ in 0..1000 {
+                if !cache.is_scanning(&dir) {
+                    break;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(2));
+            }
+        };
+
+        assert_eq!(workdir_of(&dir.join("src")), Some(dir.clone()));
+        assert!(cache.get(&dir, None).is_none());
+        assert!(cache.is_scanning(&dir));
+        wait(&cache);
+        assert_eq!(cache.generation(), 1);
+        let snapshot = cache.get(&dir, None).unwrap();
+        assert!(!cache.is_scanning(&dir));
+        assert_eq!(
+            snapshot.children(Path::new("")).get("src"),
+            Some(&GitFileStatus::Untracked)
+        );
+        assert_eq!(
+            snapshot.children(Path::new("src")).get("lib.rs"),
+            Some(&GitFileStatus::Untracked)
+        );
+
+        // Staging writes the index, which the next check notices
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("src/lib.rs")).unwrap();
+        index.write().unwrap();
+        cache.revalidate();
+        wait(&cache);
+        assert_eq!(cache.generation(), 2);
+        let snapshot = cache.get(&dir, None).unwrap();
+        assert_eq!(
+            snapshot.children(Path::new("")).get("src"),
+            Some(&GitFileStatus::Staged)
+        );
+
+        // So does a listed file modified after the scan
+        let later = SystemTime::now() + std::time::Duration::from_secs(1);
+        cache.get(&dir, Some(later));
+        assert!(cache.is_scanning(&dir));
+        wait(&cache);
+        assert_eq!(cache.generation(), 3);
+    }
+
+    #[test]
+    fn test_status_cache_watches_worktree() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        let repo = Repository::init(&dir).unwrap();
+        std::fs::create_dir_all(dir.join("src/deep")).unwrap();
+        std::fs::create_dir(dir.join("target")).unwrap();
+        std::fs::write(dir.join(".gitignore"), "target/\n").unwrap();
+        std::fs::write(dir.join("src/deep/lib.rs"), "fn main() {}").unwrap();
+        commit_all(&repo);
+        let cache = Arc::new(GitStatusCache::default());
+        // Revalidate like the UI does, until a scan delivers
+        let next_generation = |cache: &Arc<GitStatusCache>, timeout_ms: u64| {
+            let start = cache.generation();
+            for _ in 0..timeout_ms / 10 {
+                cache.revalidate();
+                if cache.generation() > start && !cache.is_scanning(&dir) {
+                    return true;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            false
+        };
+        cache.get(&dir, None);
+        assert!(next_generation(&cache, 5000));
+        let snapshot = cache.get(&dir, None).unwrap();
+        assert_eq!(snapshot.children(Path::new("")).get("src"), None);
+
+        // Build output changing is of no interest
+        std::fs::write(dir.join("target/out.o"), "obj").unwrap();
+        assert!(!next_generation(&cache, 300));
+
+        // A file changing deep below, without any listing of it newer
+        std::fs::write(dir.join("src/deep/lib.rs"), "fn main() { edited }").unwrap();
+        assert!(next_generation(&cache, 5000));
+        let snapshot = cache.get(&dir, None).unwrap();
+        assert_eq!(
+            snapshot.children(Path::new("")).get("src"),
+            Some(&GitFileStatus::Modified)
+        );
+    }
+
+    #[test]
+    fn test_ignore_rules() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().canonicalize().unwrap();
+        // Outside a repository only `.ignore` counts
+        std::fs::write(dir.join(".gitignore"), "*.tmp\n").unwrap();
+        std::fs::write(dir.join(".ignore"), "notes/\n").unwrap();
+        let rules = IgnoreRules::for_dir(&dir);
+        assert!(rules.is_ignored(&dir.join("notes"), true));
+        assert!(!rules.is_ignored(&dir.join("notes"), false));
+        assert!(!rules.is_ignored(&dir.join("a.tmp"), false));
+
+        Repository::init(&dir).unwrap();
+        std::fs::write(dir.join(".gitignore"), "*.log\ntarget/\n").unwrap();
+        std::fs::create_dir(dir.join("src")).unwrap();
+        // Deeper rules win, and `.ignore` beats `.gitignore`
+        std::fs::write(dir.join("src/.gitignore"), "!keep.log\ngen.rs\n").unwrap();
+        std::fs::write(dir.join("src/.ignore"), "!gen.rs\n").unwrap();
+        let rules = IgnoreRules::for_dir(&dir.join("src"));
+        assert!(rules.is_ignored(&dir.join("src/debug.log"), false));
+        assert!(!rules.is_ignored(&dir.join("src/keep.log"), false));
+        assert!(!rules.is_ignored(&dir.join("src/gen.rs"), false));
+        assert!(rules.is_ignored(&dir.join("src/notes"), true));
+        assert!(IgnoreRules::for_dir(&dir).is_ignored(&dir.join("target"), true));
+    }
 }
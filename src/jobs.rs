@@ -0,0 +1,404 @@
+use crate::file_ops::SearchResult;
+use crate::undo::UndoAction;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Shared progress counters, written by a worker and read by the UI.
+/// A default `Progress` is a no-op sink for callers that don't report.
+#[derive(Debug, Default)]
+pub struct Progress {
+    bytes_done: AtomicU64,
+    bytes_total: AtomicU64,
+    files_done: AtomicU64,
+    files_total: AtomicU64,
+    cancelled: AtomicBool,
+}
+
+/// A point-in-time copy of [`Progress`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProgressSnapshot {
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    pub files_done: u64,
+    pub files_total: u64,
+}
+
+impl ProgressSnapshot {
+    /// Completed fraction in 0.0..=1.0, by bytes when known, else by files
+    pub fn fraction(&self) -> Option<f64> {
+        if self.bytes_total > 0 {
+            Some((self.bytes_done as f64 / self.bytes_total as f64).min(1.0))
+        } else if self.files_total > 0 {
+            Some((self.files_done as f64 / self.files_total as f64).min(1.0))
+        } else {
+            None
+        }
+    }
+}
+
+impl Progress {
+    pub fn set_totals(&self, files: u64, bytes: u64) {
+        self.files_total.store(files, Ordering::Relaxed);
+        self.bytes_total.store(bytes, Ordering::Relaxed);
+    }
+
+    pub fn add_bytes(&self, n: u64) {
+        self.bytes_done.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn add_files(&self, n: u64) {
+        self.files_done.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Overwrite the done counters, e.g. after an item finished by a
+    /// rename that reported nothing while it ran
+    pub fn set_done(&self, files: u64, bytes: u64) {
+        self.files_done.store(files, Ordering::Relaxed);
+        self.bytes_done.store(bytes, Ordering::Relaxed);
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// `Err("Cancelled")` once the job has been cancelled, for use with `?`
+    pub fn check(&self) -> Result<(), String> {
+        if self.is_cancelled() {
+            Err("Cancelled".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn snapshot(&self) -> ProgressSnapshot {
+        ProgressSnapshot {
+            bytes_done: self.bytes_done.load(Ordering::Relaxed),
+            bytes_total: self.bytes_total.load(Ordering::Relaxed),
+            files_done: self.files_done.load(Ordering::Relaxed),
+            files_total: self.files_total.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Count the files and bytes under `path` (symlinks are not followed)
+pub fn measure(path: &Path) -> (u64, u64) {
+    let meta = match std::fs::symlink_metadata(path) {
+        Ok(m) => m,
+        Err(_) => return (0, 0),
+    };
+    if !meta.is_dir() {
+        return (1, meta.len());
+    }
+    let mut total = (0, 0);
+    if let Ok(entries) = std::fs::read_dir(path) {
+        for entry in entries.flatten() {
+            let (files, bytes) = measure(&entry.path());
+            total.0 += files;
+            total.1 += bytes;
+        }
+    }
+    total
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobKind {
+    Copy,
+    Move,
+    Delete,
+    Compress,
+    Extract,
+    Search,
+    Stage,
+    Unstage,
+    Discard,
+}
+
+impl JobKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Copy => "Copying",
+            Self::Move => "Moving",
+            Self::Delete => "Deleting",
+            Self::Compress => "Compressing",
+            Self::Extract => "Extracting",
+            Self::Search => "Searching",
+            Self::Stage => "Staging",
+            Self::Unstage => "Unstaging",
+            Self::Discard => "Discarding",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Done,
+    Failed(String),
+    Cancelled,
+}
+
+/// What a finished job hands back to the UI thread
+#[derive(Debug, Default)]
+pub struct JobOutcome {
+    /// Undo records for every part of the job that completed
+    pub undo: Vec<UndoAction>,
+    /// Status line to show when the job ends
+    pub message: String,
+    /// Set when the job stopped early because of an error
+    pub error: Option<String>,
+    /// Set by content searches: the matches found, empty if cancelled
+    pub search: Option<Vec<SearchResult>>,
+}
+
+#[derive(Debug)]
+pub struct Job {
+    pub id: u64,
+    pub kind: JobKind,
+    pub description: String,
+    pub progress: Arc<Progress>,
+    pub state: JobState,
+    pub started: Instant,
+    pub finished: Option<Instant>,
+}
+
+impl Job {
+    pub fn is_running(&self) -> bool {
+        self.state == JobState::Running
+    }
+
+    /// Throughput in bytes per second since the job started
+    pub fn rate(&self) -> f64 {
+        let elapsed = self
+            .finished
+            .unwrap_or_else(Instant::now)
+            .duration_since(self.started)
+            .as_secs_f64();
+        if elapsed <= 0.0 {
+            return 0.0;
+        }
+        self.progress.snapshot().bytes_done as f64 / elapsed
+    }
+
+    /// Estimated time left, extrapolated from progress so far
+    pub fn eta(&self) -> Option<Duration> {
+        let fraction = self.progress.snapshot().fraction()?;
+        if fraction <= 0.0 || !self.is_running() {
+            return None;
+        }
+        let elapsed = self.started.elapsed().as_secs_f64();
+        Some(Duration::from_secs_f64(
+            elapsed / fraction * (1.0 - fraction),
+        ))
+    }
+}
+
+/// Keep at most this many finished jobs in the list
+const MAX_FINISHED_JOBS: usize = 50;
+
+/// Runs file operations on worker threads and collects their outcomes
+#[derive(Debug)]
+pub struct JobManager {
+    pub jobs: Vec<Job>,
+    next_id: u64,
+    tx: Sender<(u64, JobOutcome)>,
+    rx: Receiver<(u64, JobOutcome)>,
+}
+
+impl Default for JobManager {
+    fn default() -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self {
+            jobs: Vec::new(),
+            next_id: 1,
+            tx,
+            rx,
+        }
+    }
+}
+
+impl JobManager {
+    /// Start `work` on a new thread. The closure reports through the
+    /// `Progress` it is given and should stop early once it is cancelled.
+    pub fn spawn<F>(&mut self, kind: JobKind, description: String, work: F) -> u64
+    where
+        F: FnOnce(&Progress) -> JobOutcome + Send + 'static,
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+        let progress = Arc::new(Progress::default());
+        let worker_progress = Arc::clone(&progress);
+        let tx = self.tx.clone();
+        thread::spawn(move || {
+            let outcome = work(&worker_progress);
+            let _ = tx.send((id, outcome));
+        });
+        self.jobs.push(Job {
+            id,
+            kind,
+            description,
+            progress,
+            state: JobState::Running,
+            started: Instant::now(),
+            finished: None,
+        });
+        id
+    }
+
+    /// Collect outcomes of jobs that finished since the last poll, with
+    /// the ids [`Self::spawn`] returned for them
+    pub fn poll(&mut self) -> Vec<(u64, JobOutcome)> {
+        let mut finished = Vec::new();
+        while let Ok((id, outcome)) = self.rx.try_recv() {
+            if let Some(job) = self.jobs.iter_mut().find(|j| j.id == id) {
+                job.state = match &outcome.error {
+                    Some(_) if job.progress.is_cancelled() => JobState::Cancelled,
+                    Some(e) => JobState::Failed(e.clone()),
+                    None => JobState::Done,
+                };
+                job.finished = Some(Instant::now());
+            }
+            finished.push((id, outcome));
+        }
+        if !finished.is_empty() {
+            let done = self.jobs.iter().filter(|j| !j.is_running()).count();
+            if done > MAX_FINISHED_JOBS {
+                let mut excess = done - MAX_FINISHED_JOBS;
+                self.jobs.retain(|j| {
+                    if excess > 0 && !j.is_running() {
+                        excess -= 1;
+                        false
+                    } else {
+                        true
+                    }
+                });
+            }
+        }
+        finished
+    }
+
+    pub fn running(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter().filter(|j| j.is_running())
+    }
+
+    pub fn running_count(&self) -> usize {
+        self.running().count()
+    }
+
+    /// Request cancellation; the worker stops at its next check
+    pub fn cancel(&mut self, id: u64) -> bool {
+        match self.jobs.iter().find(|j| j.id == id && j.is_running()) {
+            Some(job) => {
+                job.progress.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drop finished jobs from the list
+    pub fn clear_finished(&mut self) {
+        self.jobs.retain(|j| j.is_running());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn wait(manager: &mut JobManager) -> Vec<JobOutcome> {
+        let mut outcomes = Vec::new();
+        while manager.running_count() > 0 {
+            outcomes.extend(manager.poll().into_iter().map(|(_, outcome)| outcome));
+            thread::sleep(Duration::from_millis(5));
+        }
+        outcomes
+    }
+
+    #[test]
+    fn test_progress_fraction() {
+        let p = Progress::default();
+        assert_eq!(p.snapshot().fraction(), None);
+        p.set_totals(4, 0);
+        p.add_files(1);
+        assert_eq!(p.snapshot().fraction(), Some(0.25));
+        p.set_totals(4, 200);
+        p.add_bytes(150);
+        assert_eq!(p.snapshot().fraction(), Some(0.75));
+    }
+
+    #[test]
+    fn test_progress_cancel() {
+        let p = Progress::default();
+        assert!(p.check().is_ok());
+        p.cancel();
+        assert!(p.check().is_err());
+    }
+
+    #[test]
+    fn test_measure() {
+        let tmp = TempDir::new().unwrap();
+        std::fs::create_dir(tmp.path().join("sub")).unwrap();
+        std::fs::write(tmp.path().join("a"), "123").unwrap();
+        std::fs::write(tmp.path().join("sub").join("b"), "45").unwrap();
+        assert_eq!(measure(tmp.path()), (2, 5));
+        assert_eq!(measure(&tmp.path().join("missing")), (0, 0));
+    }
+
+    #[test]
+    fn test_job_runs_and_reports() {
+        let mut manager = JobManager::default();
+        manager.spawn(JobKind::Copy, "test".to_string(), |progress| {
+            progress.set_totals(1, 10);
+            progress.add_bytes(10);
+            JobOutcome {
+                message: "done".to_string(),
+                ..Default::default()
+            }
+        });
+        let outcomes = wait(&mut manager);
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0].message, "done");
+        assert_eq!(manager.jobs[0].state, JobState::Done);
+        assert_eq!(manager.jobs[0].progress.snapshot().bytes_done, 10);
+    }
+
+    #[test]
+    fn test_job_cancel() {
+        let mut manager = JobManager::default();
+        let id = manager.spawn(JobKind::Delete, "slow".to_string(), |progress| {
+            while !progress.is_cancelled() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            JobOutcome {
+                error: Some("Cancelled".to_string()),
+                ..Default::default()
+            }
+        });
+        assert!(manager.cancel(id));
+        wait(&mut manager);
+        assert_eq!(manager.jobs[0].state, JobState::Cancelled);
+        assert!(!manager.cancel(id));
+        manager.clear_finished();
+        assert!(manager.jobs.is_empty());
+    }
+
+    #[test]
+    fn test_job_failure() {
+        let mut manager = JobManager::default();
+        manager.spawn(JobKind::Extract, "bad".to_string(), |_| JobOutcome {
+            error: Some("boom".to_string()),
+            ..Default::default()
+        });
+        wait(&mut manager);
+        assert_eq!(manager.jobs[0].state, JobState::Failed("boom".to_string()));
+    }
+}
@@ -0,0 +1,541 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+
+/// A named action that a key sequence can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    MoveDown,
+    MoveUp,
+    Open,
+    Parent,
+    JumpTop,
+    JumpBottom,
+    Filter,
+    ToggleSelect,
+    Delete,
+    Yank,
+    Paste,
+    Cut,
+    PasteToOtherPane,
+    Rename,
+    NewFile,
+    NewDir,
+    SetBookmark,
+    JumpBookmark,
+    Chmod,
+    Search,
+    CycleSort,
+    ToggleHidden,
+    ToggleTree,
+    CopyPath,
+    CopyContent,
+    CycleTheme,
+    Undo,
+    Redo,
+    ToggleDualPane,
+    SwitchPane,
+    ExtractArchive,
+    CompressArchive,
+    ShowJobs,
+    GitStage,
+    GitUnstage,
+    GitDiscard,
+    ToggleGitDiff,
+    ToggleGitBlame,
+    GitLog,
+    ToggleIgnored,
+    NewTab,
+    CloseTab,
+    NextTab,
+    PrevTab,
+    /// Switch to tab N (1-based)
+    GotoTab(u8),
+}
+
+/// Config names for every action, in the order they appear in the docs
+const ACTION_NAMES: &[(&str, Action)] = &[
+    ("quit", Action::Quit),
+    ("move_down", Action::MoveDown),
+    ("move_up", Action::MoveUp),
+    ("open", Action::Open),
+    ("parent", Action::Parent),
+    ("jump_top", Action::JumpTop),
+    ("jump_bottom", Action::JumpBottom),
+    ("filter", Action::Filter),
+    ("toggle_select", Action::ToggleSelect),
+    ("delete", Action::Delete),
+    ("yank", Action::Yank),
+    ("paste", Action::Paste),
+    ("cut", Action::Cut),
+    ("paste_other_pane", Action::PasteToOtherPane),
+    ("rename", Action::Rename),
+    ("new_file", Action::NewFile),
+    ("new_dir", Action::NewDir),
+    ("set_bookmark", Action::SetBookmark),
+    ("jump_bookmark", Action::JumpBookmark),
+    ("chmod", Action::Chmod),
+    ("search", Action::Search),
+    ("cycle_sort", Action::CycleSort),
+    ("toggle_hidden", Action::ToggleHidden),
+    ("toggle_tree", Action::ToggleTree),
+    ("copy_path", Action::CopyPath),
+    ("copy_content", Action::CopyContent),
+    ("cycle_theme", Action::CycleTheme),
+    ("undo", Action::Undo),
+    ("redo", Action::Redo),
+    ("toggle_dual_pane", Action::ToggleDualPane),
+    ("switch_pane", Action::SwitchPane),
+    ("extract_archive", Action::ExtractArchive),
+    ("compress_archive", Action::CompressArchive),
+    ("show_jobs", Action::ShowJobs),
+    ("git_stage", Action::GitStage),
+    ("git_unstage", Action::GitUnstage),
+    ("git_discard", Action::GitDiscard),
+    ("toggle_git_diff", Action::ToggleGitDiff),
+    ("toggle_git_blame", Action::ToggleGitBlame),
+    ("git_log", Action::GitLog),
+    ("toggle_ignored", Action::ToggleIgnored),
+    ("new_tab", Action::NewTab),
+    ("close_tab", Action::CloseTab),
+    ("next_tab", Action::NextTab),
+    ("prev_tab", Action::PrevTab),
+    ("goto_tab_1", Action::GotoTab(1)),
+    ("goto_tab_2", Action::GotoTab(2)),
+    ("goto_tab_3", Action::GotoTab(3)),
+    ("goto_tab_4", Action::GotoTab(4)),
+    ("goto_tab_5", Action::GotoTab(5)),
+    ("goto_tab_6", Action::GotoTab(6)),
+    ("goto_tab_7", Action::GotoTab(7)),
+    ("goto_tab_8", Action::GotoTab(8)),
+    ("goto_tab_9", Action::GotoTab(9)),
+];
+
+/// Built-in bindings: action name → space-separated key sequences
+const DEFAULT_BINDINGS: &[(&str, &str)] = &[
+    ("quit", "q"),
+    ("move_down", "j <Down>"),
+    ("move_up", "k <Up>"),
+    ("open", "l <Right> <Enter>"),
+    ("parent", "h <Left>"),
+    ("jump_top", "gg"),
+    ("jump_bottom", "G"),
+    ("filter", "/"),
+    ("toggle_select", "<Space>"),
+    ("delete", "dd"),
+    ("yank", "yy"),
+    ("paste", "pp"),
+    ("cut", "x dx"),
+    ("paste_other_pane", "po"),
+    ("rename", "r"),
+    ("new_file", "n"),
+    ("new_dir", "N"),
+    ("set_bookmark", "m"),
+    ("jump_bookmark", "'"),
+    ("chmod", "c"),
+    ("search", "F"),
+    ("cycle_sort", "s"),
+    ("toggle_hidden", "."),
+    ("toggle_tree", "t"),
+    ("copy_path", "Y"),
+    ("copy_content", "<C-y>"),
+    ("cycle_theme", "T"),
+    ("undo", "u"),
+    ("redo", "U"),
+    ("toggle_dual_pane", "D"),
+    ("switch_pane", "<Tab>"),
+    ("extract_archive", "X"),
+    ("compress_archive", "Z"),
+    ("show_jobs", "J"),
+    ("git_stage", "ga"),
+    ("git_unstage", "gu"),
+    ("git_discard", "gr"),
+    ("toggle_git_diff", "gd"),
+    ("toggle_git_blame", "gb"),
+    ("git_log", "gl"),
+    ("toggle_ignored", "gi"),
+    ("new_tab", "<C-t>"),
+    ("close_tab", "<C-w>"),
+    ("next_tab", "<C-Right>"),
+    ("prev_tab", "<C-Left>"),
+    ("goto_tab_1", "<A-1>"),
+    ("goto_tab_2", "<A-2>"),
+    ("goto_tab_3", "<A-3>"),
+    ("goto_tab_4", "<A-4>"),
+    ("goto_tab_5", "<A-5>"),
+    ("goto_tab_6", "<A-6>"),
+    ("goto_tab_7", "<A-7>"),
+    ("goto_tab_8", "<A-8>"),
+    ("goto_tab_9", "<A-9>"),
+];
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Self> {
+        ACTION_NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|&(_, action)| action)
+    }
+
+    /// The name `[keybinds]` knows the action by
+    pub fn name(self) -> &'static str {
+        ACTION_NAMES
+            .iter()
+            .find(|(_, a)| *a == self)
+            .map(|&(n, _)| n)
+            .unwrap_or("unknown")
+    }
+}
+
+/// A single key press, normalised so that bindings compare reliably
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyPress {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyPress {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        // Shift is already encoded in the character itself ('G' vs 'g')
+        if matches!(code, KeyCode::Char(_) | KeyCode::BackTab) {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Self { code, modifiers }
+    }
+}
+
+impl From<KeyEvent> for KeyPress {
+    fn from(key: KeyEvent) -> Self {
+        Self::new(key.code, key.modifiers)
+    }
+}
+
+/// Result of looking up a (possibly partial) key sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyLookup {
+    /// The sequence is bound to an action
+    Matched(Action),
+    /// The sequence is a prefix of at least one binding — wait for more keys
+    Pending,
+    /// Nothing starts with this sequence
+    Unbound,
+}
+
+/// Maps key sequences to actions
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: HashMap<Vec<KeyPress>, Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Self {
+            bindings: HashMap::new(),
+        };
+        for (name, spec) in DEFAULT_BINDINGS {
+            let action = Action::from_name(name).expect("default binding names are valid");
+            for seq in parse_bindings(spec).expect("default key specs are valid") {
+                keymap.bindings.insert(seq, action);
+            }
+        }
+        keymap
+    }
+}
+
+impl Keymap {
+    /// Build the default keymap and apply `[keybinds]` overrides on top.
+    /// Each override replaces all default keys of that action; an empty
+    /// string unbinds it. Invalid entries are skipped and reported.
+    pub fn with_overrides(overrides: &HashMap<String, String>) -> (Self, Vec<String>) {
+        let mut keymap = Self::default();
+        let mut errors = Vec::new();
+        // Sort so that conflicting overrides resolve the same way every run
+        let mut names: Vec<_> = overrides.keys().collect();
+        names.sort();
+        for name in names {
+            let spec = &overrides[name];
+            let Some(action) = Action::from_name(name) else {
+                errors.push(format!("Unknown action '{name}'"));
+                continue;
+            };
+            match parse_bindings(spec) {
+                Ok(seqs) => keymap.bind(action, seqs),
+                Err(e) => errors.push(format!("{name}: {e}")),
+            }
+        }
+        errors.extend(keymap.prefix_conflicts());
+        (keymap, errors)
+    }
+
+    /// Actions whose keys start another action's longer sequence, which
+    /// can then never be typed since the shorter one matches first
+    fn prefix_conflicts(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        for &(name, action) in ACTION_NAMES {
+            let mut hidden: Vec<Action> = Vec::new();
+            for (seq, _) in self.bindings.iter().filter(|(_, a)| **a == action) {
+                for (longer, other) in &self.bindings {
+                    if longer.len() > seq.len()
+                        && longer.starts_with(seq)
+                        && !hidden.contains(other)
+                    {
+                        hidden.push(*other);
+                    }
+                }
+            }
+            if hidden.is_empty() {
+                continue;
+            }
+            let mut hidden: Vec<&str> = hidden.iter().map(|a| a.name()).collect();
+            hidden.sort();
+            errors.push(format!(
+                "{name} starts the keys of {}, which can't be reached",
+                hidden.join(", ")
+            ));
+        }
+        errors
+    }
+
+    /// Replace every binding of `action` with `seqs`, stealing keys that
+    /// were previously bound to other actions
+    pub fn bind(&mut self, action: Action, seqs: Vec<Vec<KeyPress>>) {
+        self.bindings.retain(|_, a| *a != action);
+        for seq in seqs {
+            self.bindings.insert(seq, action);
+        }
+    }
+
+    pub fn lookup(&self, keys: &[KeyPress]) -> KeyLookup {
+        if let Some(&action) = self.bindings.get(keys) {
+            return KeyLookup::Matched(action);
+        }
+        if self
+            .bindings
+            .keys()
+            .any(|seq| seq.len() > keys.len() && seq.starts_with(keys))
+        {
+            KeyLookup::Pending
+        } else {
+            KeyLookup::Unbound
+        }
+    }
+}
+
+/// Parse a whitespace-separated list of key sequences, e.g. `"j <Down>"`
+pub fn parse_bindings(spec: &str) -> Result<Vec<Vec<KeyPress>>, String> {
+    spec.split_whitespace().map(parse_sequence).collect()
+}
+
+/// Parse a single key sequence in vim notation, e.g. `gg`, `<C-t>`, `d<Space>`
+pub fn parse_sequence(s: &str) -> Result<Vec<KeyPress>, String> {
+    let mut keys = Vec::new();
+    let mut rest = s;
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(end) = rest.find('>') {
+                // "<>" or a lone "<" followed later by ">" of another token
+                if end > 1 {
+                    keys.push(parse_special(&rest[1..end])?);
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+            }
+        }
+        keys.push(KeyPress::new(KeyCode::Char(c), KeyModifiers::NONE));
+        rest = &rest[c.len_utf8()..];
+    }
+    if keys.is_empty() {
+        return Err("empty key sequence".to_string());
+    }
+    Ok(keys)
+}
+
+fn parse_special(token: &str) -> Result<KeyPress, String> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut name = token;
+    while name.len() > 2 && name.as_bytes()[1] == b'-' {
+        match name.as_bytes()[0].to_ascii_uppercase() {
+            b'C' => modifiers |= KeyModifiers::CONTROL,
+            b'A' | b'M' => modifiers |= KeyModifiers::ALT,
+            b'S' => modifiers |= KeyModifiers::SHIFT,
+            _ => return Err(format!("unknown modifier in <{token}>")),
+        }
+        name = &name[2..];
+    }
+    let code = match name.to_lowercase().as_str() {
+        "enter" | "cr" | "return" => KeyCode::Enter,
+        "esc" => KeyCode::Esc,
+        "tab" if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::BackTab,
+        "tab" => KeyCode::Tab,
+        "backtab" => KeyCode::BackTab,
+        "space" => KeyCode::Char(' '),
+        "bs" | "backspace" => KeyCode::Backspace,
+        "del" | "delete" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "lt" => KeyCode::Char('<'),
+        "gt" => KeyCode::Char('>'),
+        f if f.len() > 1 && f.starts_with('f') => match f[1..].parse::<u8>() {
+            Ok(n @ 1..=12) => KeyCode::F(n),
+            _ => return Err(format!("unknown key <{token}>")),
+        },
+        _ => {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => KeyCode::Char(c),
+                _ => return Err(format!("unknown key <{token}>")),
+            }
+        }
+    };
+    Ok(KeyPress::new(code, modifiers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(c: char) -> KeyPress {
+        KeyPress::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_action_names_round_trip() {
+        for &(name, action) in ACTION_NAMES {
+            assert_eq!(Action::from_name(name), Some(action));
+            assert_eq!(action.name(), name);
+        }
+        assert_eq!(Action::from_name("nope"), None);
+    }
+
+    #[test]
+    fn test_every_action_has_default_binding() {
+        let keymap = Keymap::default();
+        for &(name, action) in ACTION_NAMES {
+            let (_, spec) = DEFAULT_BINDINGS
+                .iter()
+                .find(|(n, _)| *n == name)
+                .unwrap_or_else(|| panic!("{name} is unbound"));
+            for seq in parse_bindings(spec).unwrap() {
+                assert_eq!(keymap.lookup(&seq), KeyLookup::Matched(action), "{name}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_sequence() {
+        assert_eq!(parse_sequence("gg").unwrap(), vec![key('g'), key('g')]);
+        assert_eq!(
+            parse_sequence("<C-t>").unwrap(),
+            vec![KeyPress::new(KeyCode::Char('t'), KeyModifiers::CONTROL)]
+        );
+        assert_eq!(
+            parse_sequence("<Down>").unwrap(),
+            vec![KeyPress::new(KeyCode::Down, KeyModifiers::NONE)]
+        );
+        assert_eq!(
+            parse_sequence("d<Space>").unwrap(),
+            vec![key('d'), key(' ')]
+        );
+        assert_eq!(parse_sequence("<lt>").unwrap(), vec![key('<')]);
+        assert_eq!(parse_sequence("<").unwrap(), vec![key('<')]);
+        assert!(parse_sequence("<Nope>").is_err());
+        assert!(parse_sequence("<F13>").is_err());
+    }
+
+    #[test]
+    fn test_shift_is_folded_into_char() {
+        let shifted = KeyPress::new(KeyCode::Char('G'), KeyModifiers::SHIFT);
+        assert_eq!(shifted, key('G'));
+    }
+
+    #[test]
+    fn test_lookup_sequences() {
+        let keymap = Keymap::default();
+        assert_eq!(
+            keymap.lookup(&[key('j')]),
+            KeyLookup::Matched(Action::MoveDown)
+        );
+        assert_eq!(keymap.lookup(&[key('g')]), KeyLookup::Pending);
+        assert_eq!(
+            keymap.lookup(&[key('g'), key('g')]),
+            KeyLookup::Matched(Action::JumpTop)
+        );
+        assert_eq!(keymap.lookup(&[key('d'), key('q')]), KeyLookup::Unbound);
+    }
+
+    #[test]
+    fn test_overrides_replace_defaults() {
+        let mut overrides = HashMap::new();
+        overrides.insert("move_down".to_string(), "J".to_string());
+        let (keymap, errors) = Keymap::with_overrides(&overrides);
+        assert!(errors.is_empty());
+        assert_eq!(
+            keymap.lookup(&[key('J')]),
+            KeyLookup::Matched(Action::MoveDown)
+        );
+        assert_eq!(keymap.lookup(&[key('j')]), KeyLookup::Unbound);
+    }
+
+    #[test]
+    fn test_override_steals_key_from_other_action() {
+        let mut overrides = HashMap::new();
+        overrides.insert("toggle_hidden".to_string(), "q".to_string());
+        let (keymap, _) = Keymap::with_overrides(&overrides);
+        assert_eq!(
+            keymap.lookup(&[key('q')]),
+            KeyLookup::Matched(Action::ToggleHidden)
+        );
+    }
+
+    #[test]
+    fn test_empty_override_unbinds() {
+        let mut overrides = HashMap::new();
+        overrides.insert("delete".to_string(), String::new());
+        let (keymap, errors) = Keymap::with_overrides(&overrides);
+        assert!(errors.is_empty());
+        assert_eq!(keymap.lookup(&[key('d'), key('d')]), KeyLookup::Unbound);
+    }
+
+    #[test]
+    fn test_invalid_overrides_reported() {
+        let mut overrides = HashMap::new();
+        overrides.insert("fly".to_string(), "f".to_string());
+        overrides.insert("yank".to_string(), "<Bogus>".to_string());
+        let (keymap, errors) = Keymap::with_overrides(&overrides);
+        assert_eq!(errors.len(), 2);
+        // Failed override leaves the default in place
+        assert_eq!(
+            keymap.lookup(&[key('y'), key('y')]),
+            KeyLookup::Matched(Action::Yank)
+        );
+    }
+
+    #[test]
+    fn test_prefix_override_reported() {
+        assert!(Keymap::default().prefix_conflicts().is_empty());
+        let mut overrides = HashMap::new();
+        overrides.insert("git_log".to_string(), "g".to_string());
+        let (_, errors) = Keymap::with_overrides(&overrides);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("git_log starts the keys of "));
+        assert!(errors[0].contains("jump_top") && errors[0].contains("git_stage"));
+
+        // A longer sequence behind an existing key is just as unreachable
+        overrides.clear();
+        overrides.insert("toggle_hidden".to_string(), "jk".to_string());
+        let (_, errors) = Keymap::with_overrides(&overrides);
+        assert_eq!(
+            errors,
+            ["move_down starts the keys of toggle_hidden, which can't be reached"]
+        );
+    }
+}
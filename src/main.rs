@@ -1,7 +1,10 @@
 mod app;
+mod archive;
 mod config;
 mod file_ops;
 mod git_status;
+mod jobs;
+mod keymap;
 mod preview;
 mod theme;
 mod ui;
@@ -16,6 +19,12 @@ use crossterm::{
 };
 use ratatui::{backend::CrosstermBackend, Terminal};
 use std::io;
+use std::time::Duration;
+
+/// How often the UI wakes without input to pick up background progress
+const TICK_RATE: Duration = Duration::from_millis(100);
+/// Faster wake-up while a preview is rendering, so it shows promptly
+const PREVIEW_TICK_RATE: Duration = Duration::from_millis(15);
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let config = Config::load();
@@ -52,18 +61,18 @@ fn run_app(
     loop {
         terminal.draw(|f| ui::draw(f, app))?;
 
-        match event::read()? {
-            Event::Key(key) => {
-                if app.handle_key(key)? {
-                    return Ok(());
-                }
-            }
-            Event::Mouse(mouse) => {
-                if app.handle_mouse(mouse)? {
-                    return Ok(());
-                }
+        let timeout = if app.preview_loading() {
+            PREVIEW_TICK_RATE
+        } else {
+            TICK_RATE
+        };
+        if event::poll(timeout)? {
+            match event::read()? {
+                Event::Key(key) if app.handle_key(key)? => return Ok(()),
+                Event::Mouse(mouse) if app.handle_mouse(mouse)? => return Ok(()),
+                _ => {}
             }
-            _ => {}
         }
+        app.tick()?;
     }
 }
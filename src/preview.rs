@@ -1,17 +1,79 @@
+use crate::archive::{self, ArchiveIndex};
+use crate::config::PreviewConfig;
+use crate::file_ops::{ArchiveKind, Codec};
+use crate::git_status::{self, DiffLineKind};
+use std::collections::{HashMap, VecDeque};
 use std::fs;
-use std::path::Path;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::thread;
+use std::time::SystemTime;
 use syntect::easy::HighlightLines;
-use syntect::highlighting::ThemeSet;
+use syntect::highlighting::{Color, FontStyle, ThemeSet};
 use syntect::parsing::SyntaxSet;
-use syntect::util::LinesWithEndings;
 
-const MAX_PREVIEW_LINES: usize = 100;
-const MAX_FILE_SIZE: u64 = 1024 * 1024; // 1 MB
+/// Files up to this size are read to the end to count the lines not shown
+const LINE_COUNT_LIMIT: u64 = 1024 * 1024; // 1 MB
+/// Longer lines are cut short in the preview
+const MAX_LINE_BYTES: usize = 1024;
+/// Smallest chunk read from the end of a file for tail lines
+const TAIL_WINDOW: u64 = 64 * 1024;
+/// Used when a configured syntax theme isn't one syntect ships
+const FALLBACK_SYNTAX_THEME: &str = "base16-ocean.dark";
+/// Rendered previews kept around for revisiting files
+const PREVIEW_CACHE_SIZE: usize = 64;
+/// How much of a binary file the hex dump shows
+const HEX_PREVIEW_BYTES: usize = 4 * 1024;
+const HEX_BYTES_PER_ROW: usize = 16;
 
 #[derive(Debug, Clone)]
 pub struct PreviewLine {
     pub text: String,
     pub style: PreviewStyle,
+    /// Highlighted pieces of `text`; empty means draw `text` with `style`
+    pub spans: Vec<PreviewSpan>,
+}
+
+impl PreviewLine {
+    pub fn new(text: impl Into<String>, style: PreviewStyle) -> Self {
+        Self {
+            text: text.into(),
+            style,
+            spans: Vec::new(),
+        }
+    }
+}
+
+pub type Rgb = (u8, u8, u8);
+
+/// A run of text with its own colors, as produced by syntect
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreviewSpan {
+    pub text: String,
+    /// Fallback color role when `fg` is not set
+    pub role: PreviewStyle,
+    pub fg: Option<Rgb>,
+    pub bg: Option<Rgb>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl PreviewSpan {
+    fn plain(text: impl Into<String>, role: PreviewStyle) -> Self {
+        Self {
+            text: text.into(),
+            role,
+            fg: None,
+            bg: None,
+            bold: false,
+            italic: false,
+            underline: false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -21,26 +83,502 @@ pub enum PreviewStyle {
     Directory,
     Header,
     LineNumber,
+    /// Diff lines added or removed
+    Added,
+    Removed,
 }
 
-pub fn preview_path(path: &Path) -> Vec<PreviewLine> {
-    if path.is_dir() {
-        preview_directory(path)
-    } else if is_image(path) {
-        preview_image_meta(path)
-    } else {
-        preview_text_file(path)
+/// Renders previews, loading syntect's syntax and theme sets once on first
+/// use and remembering recent results. Shared by every tab.
+#[derive(Debug)]
+pub struct PreviewEngine {
+    syntaxes: OnceLock<SyntaxSet>,
+    themes: OnceLock<ThemeSet>,
+    cache: Mutex<PreviewCache>,
+    /// Queue of the background render thread, started on first request
+    worker: Mutex<Option<Sender<PreviewRequest>>>,
+    limits: PreviewConfig,
+}
+
+impl Default for PreviewEngine {
+    fn default() -> Self {
+        Self::new(PreviewConfig::default())
+    }
+}
+
+impl PreviewEngine {
+    pub fn new(limits: PreviewConfig) -> Self {
+        Self::with_capacity(PREVIEW_CACHE_SIZE, limits)
+    }
+
+    pub fn with_capacity(capacity: usize, limits: PreviewConfig) -> Self {
+        Self {
+            limits,
+            syntaxes: OnceLock::new(),
+            themes: OnceLock::new(),
+            cache: Mutex::new(PreviewCache::new(capacity)),
+            worker: Mutex::new(None),
+        }
+    }
+
+    /// Lines shown from the top of a file or listing
+    pub fn max_lines(&self) -> usize {
+        self.limits.max_lines
+    }
+
+    /// The cached preview of `path`, if it is still fresh. Cheap enough to
+    /// call on the UI thread: one `stat` and a map lookup.
+    pub fn cached(&self, path: &Path, syntax_theme: &str) -> Option<Vec<PreviewLine>> {
+        let key = CacheKey::of(path, syntax_theme)?;
+        self.cache.lock().ok()?.get(&key)
+    }
+
+    /// Render `path` on the background thread and deliver it to `slot`.
+    /// Requests the slot has moved past by then are dropped unrendered.
+    pub fn request(self: &Arc<Self>, slot: &Arc<PreviewSlot>, path: PathBuf, syntax_theme: String) {
+        self.enqueue(PreviewRequest {
+            generation: slot.next_generation(),
+            slot: Arc::clone(slot),
+            source: PreviewSource::Path(path),
+            syntax_theme,
+        });
+    }
+
+    /// Like [`Self::request`], for a file that `prepare` first has to
+    /// produce on the worker, such as an entry extracted from an archive
+    pub fn request_prepared<F>(
+        self: &Arc<Self>,
+        slot: &Arc<PreviewSlot>,
+        syntax_theme: String,
+        prepare: F,
+    ) where
+        F: FnOnce() -> Result<PathBuf, String> + Send + 'static,
+    {
+        self.enqueue(PreviewRequest {
+            generation: slot.next_generation(),
+            slot: Arc::clone(slot),
+            source: PreviewSource::Prepared(Box::new(prepare)),
+            syntax_theme,
+        });
+    }
+
+    /// Like [`Self::request`], showing the file's git diff instead of
+    /// its contents. Diffs aren't cached, since the index changes them.
+    pub fn request_diff(self: &Arc<Self>, slot: &Arc<PreviewSlot>, path: PathBuf) {
+        self.enqueue(PreviewRequest {
+            generation: slot.next_generation(),
+            slot: Arc::clone(slot),
+            source: PreviewSource::Diff(path),
+            syntax_theme: String::new(),
+        });
+    }
+
+    /// Like [`Self::request_diff`], annotating each line with the commit
+    /// and author that last changed it, as of commit `at` if given
+    pub fn request_blame(
+        self: &Arc<Self>,
+        slot: &Arc<PreviewSlot>,
+        path: PathBuf,
+        at: Option<String>,
+    ) {
+        self.enqueue(PreviewRequest {
+            generation: slot.next_generation(),
+            slot: Arc::clone(slot),
+            source: PreviewSource::Blame(path, at),
+            syntax_theme: String::new(),
+        });
+    }
+
+    fn enqueue(self: &Arc<Self>, request: PreviewRequest) {
+        let Ok(mut worker) = self.worker.lock() else {
+            return;
+        };
+        let sender = worker.get_or_insert_with(|| self.spawn_worker());
+        if let Err(mpsc::SendError(request)) = sender.send(request) {
+            // The thread died; start a fresh one and retry once
+            let sender = worker.insert(self.spawn_worker());
+            let _ = sender.send(request);
+        }
+    }
+
+    fn spawn_worker(self: &Arc<Self>) -> Sender<PreviewRequest> {
+        let (tx, rx) = mpsc::channel::<PreviewRequest>();
+        // A weak handle lets the thread exit once the engine is dropped
+        let engine = Arc::downgrade(self);
+        thread::spawn(move || {
+            while let Ok(request) = rx.recv() {
+                if !request.is_current() {
+                    continue;
+                }
+                let Some(engine) = Weak::upgrade(&engine) else {
+                    return;
+                };
+                let PreviewRequest {
+                    slot,
+                    generation,
+                    source,
+                    syntax_theme,
+                } = request;
+                let path = match source {
+                    PreviewSource::Path(path) => path,
+                    PreviewSource::Diff(path) => {
+                        let lines = preview_git_diff(&path, engine.limits.max_lines);
+                        slot.deliver(generation, lines);
+                        continue;
+                    }
+                    PreviewSource::Blame(path, at) => {
+                        let lines =
+                            preview_git_blame(&path, at.as_deref(), engine.limits.max_lines);
+                        slot.deliver(generation, lines);
+                        continue;
+                    }
+                    PreviewSource::Prepared(prepare) => match prepare() {
+                        Ok(path) => path,
+                        Err(e) => {
+                            let line =
+                                PreviewLine::new(format!("Error: {e}"), PreviewStyle::Normal);
+                            slot.deliver(generation, vec![line]);
+                            continue;
+                        }
+                    },
+                };
+                let stale = || !slot.is_current(generation);
+                if let Some(lines) = engine.preview_unless(&path, &syntax_theme, &stale) {
+                    slot.deliver(generation, lines);
+                }
+            }
+        });
+        tx
+    }
+
+    /// Cached or freshly rendered preview; `None` if `cancelled` fired midway
+    fn preview_unless(
+        &self,
+        path: &Path,
+        syntax_theme: &str,
+        cancelled: &dyn Fn() -> bool,
+    ) -> Option<Vec<PreviewLine>> {
+        let key = CacheKey::of(path, syntax_theme);
+        if let Some(key) = &key {
+            if let Some(lines) = self.cache.lock().ok().and_then(|mut c| c.get(key)) {
+                return Some(lines);
+            }
+        }
+        let lines = self.render(path, syntax_theme, cancelled)?;
+        if let (Some(key), Ok(mut cache)) = (key, self.cache.lock()) {
+            cache.insert(key, lines.clone());
+        }
+        Some(lines)
+    }
+
+    fn render(
+        &self,
+        path: &Path,
+        syntax_theme: &str,
+        cancelled: &dyn Fn() -> bool,
+    ) -> Option<Vec<PreviewLine>> {
+        if path.is_dir() {
+            preview_directory(path, self.limits.max_lines, cancelled)
+        } else if is_image(path) {
+            Some(preview_image_meta(path))
+        } else if archive::is_browsable(path) {
+            preview_archive(path, self.limits.max_lines, cancelled)
+        } else {
+            self.preview_text_file(path, syntax_theme, cancelled)
+        }
+    }
+
+    fn syntaxes(&self) -> &SyntaxSet {
+        self.syntaxes.get_or_init(SyntaxSet::load_defaults_newlines)
+    }
+
+    fn themes(&self) -> &ThemeSet {
+        self.themes.get_or_init(ThemeSet::load_defaults)
+    }
+
+    fn preview_text_file(
+        &self,
+        path: &Path,
+        syntax_theme: &str,
+        cancelled: &dyn Fn() -> bool,
+    ) -> Option<Vec<PreviewLine>> {
+        let error = |e: std::io::Error| {
+            Some(vec![PreviewLine::new(
+                format!("Error: {e}"),
+                PreviewStyle::Normal,
+            )])
+        };
+        let meta = match fs::metadata(path) {
+            Ok(m) => m,
+            Err(e) => return error(e),
+        };
+        let head = match read_head(path, HEX_PREVIEW_BYTES) {
+            Ok(h) => h,
+            Err(e) => return error(e),
+        };
+        if looks_binary(&head) {
+            return Some(preview_hex(&head, meta.len()));
+        }
+
+        // Stream only what is shown, so file size doesn't matter
+        let mut reader = match fs::File::open(path) {
+            Ok(f) => BufReader::new(f),
+            Err(e) => return error(e),
+        };
+        let mut head_lines = Vec::new();
+        let mut consumed = 0;
+        while head_lines.len() < self.limits.max_lines {
+            if cancelled() {
+                return None;
+            }
+            match read_line_capped(&mut reader, MAX_LINE_BYTES) {
+                Ok(Some((line, n))) => {
+                    head_lines.push(line);
+                    consumed += n;
+                }
+                Ok(None) => break,
+                Err(e) => return error(e),
+            }
+        }
+        let remaining = meta.len().saturating_sub(consumed);
+        let more_lines = if remaining > 0 && meta.len() <= LINE_COUNT_LIMIT {
+            count_lines(&mut reader).ok()
+        } else {
+            None
+        };
+        let tail = if remaining > 0 && self.limits.tail_lines > 0 {
+            read_tail(path, consumed, self.limits.tail_lines).unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        // A file growing between the count and the tail read leaves more
+        // tail than counted lines; numbering would be a guess then
+        let more_lines = more_lines.filter(|&n| n >= tail.len());
+
+        let mut highlighter = self.highlighter(path, syntax_theme);
+        let mut lines = Vec::new();
+        for (i, line) in head_lines.iter().enumerate() {
+            if cancelled() {
+                return None;
+            }
+            lines.push(highlighter.line(Some(i + 1), line));
+        }
+        if remaining > 0 {
+            let marker = match more_lines {
+                Some(n) => format!("... ({} more lines)", n - tail.len()),
+                None => format!("... ({remaining} more bytes)"),
+            };
+            lines.push(PreviewLine::new(marker, PreviewStyle::Header));
+        }
+        if !tail.is_empty() {
+            // Highlighting state from the head doesn't carry over the gap
+            let mut highlighter = self.highlighter(path, syntax_theme);
+            let first = more_lines.map(|n| head_lines.len() + n - tail.len() + 1);
+            for (i, line) in tail.iter().enumerate() {
+                lines.push(highlighter.line(first.map(|f| f + i), line));
+            }
+        }
+        Some(lines)
+    }
+
+    fn highlighter(&self, path: &Path, syntax_theme: &str) -> LineHighlighter<'_> {
+        let ss = self.syntaxes();
+        let ts = self.themes();
+        let syntax = ss
+            .find_syntax_for_file(path)
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| ss.find_syntax_plain_text());
+        let theme = ts
+            .themes
+            .get(syntax_theme)
+            .unwrap_or(&ts.themes[FALLBACK_SYNTAX_THEME]);
+        LineHighlighter {
+            highlight: HighlightLines::new(syntax, theme),
+            syntaxes: ss,
+            base_bg: theme.settings.background,
+        }
+    }
+}
+
+/// Syntax highlighting for consecutive lines of one file
+struct LineHighlighter<'a> {
+    highlight: HighlightLines<'a>,
+    syntaxes: &'a SyntaxSet,
+    /// The theme's own background, left to the UI theme
+    base_bg: Option<Color>,
+}
+
+impl LineHighlighter<'_> {
+    /// Highlight `line` (without its newline) behind a line-number gutter
+    fn line(&mut self, number: Option<usize>, line: &str) -> PreviewLine {
+        let gutter = match number {
+            Some(n) => format!("{n:>4} │ "),
+            None => "     │ ".to_string(),
+        };
+        let mut spans = vec![PreviewSpan::plain(&gutter, PreviewStyle::LineNumber)];
+        match self
+            .highlight
+            .highlight_line(&format!("{line}\n"), self.syntaxes)
+        {
+            Ok(ranges) => spans.extend(ranges.into_iter().filter_map(|(style, text)| {
+                let text = text.trim_end_matches(['\n', '\r']);
+                if text.is_empty() {
+                    return None;
+                }
+                Some(PreviewSpan {
+                    text: text.to_string(),
+                    role: PreviewStyle::Normal,
+                    fg: Some(rgb(style.foreground)),
+                    bg: (Some(style.background) != self.base_bg).then(|| rgb(style.background)),
+                    bold: style.font_style.contains(FontStyle::BOLD),
+                    italic: style.font_style.contains(FontStyle::ITALIC),
+                    underline: style.font_style.contains(FontStyle::UNDERLINE),
+                })
+            })),
+            Err(_) => spans.push(PreviewSpan::plain(line.trim_end(), PreviewStyle::Normal)),
+        }
+        PreviewLine {
+            text: format!("{gutter}{}", line.trim_end()),
+            style: PreviewStyle::Normal,
+            spans,
+        }
     }
 }
 
-fn preview_directory(path: &Path) -> Vec<PreviewLine> {
-    let mut lines = vec![PreviewLine {
-        text: format!("📁 Directory: {}", path.display()),
-        style: PreviewStyle::Header,
-    }];
+/// Receives previews rendered in the background for one tab
+#[derive(Debug, Default)]
+pub struct PreviewSlot {
+    /// Bumped for every request; anything older is stale
+    generation: AtomicU64,
+    ready: Mutex<Option<(u64, Vec<PreviewLine>)>>,
+}
+
+impl PreviewSlot {
+    /// Invalidate outstanding requests and return the new generation
+    pub fn next_generation(&self) -> u64 {
+        self.generation.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn is_current(&self, generation: u64) -> bool {
+        self.generation.load(Ordering::Relaxed) == generation
+    }
+
+    fn deliver(&self, generation: u64, lines: Vec<PreviewLine>) {
+        if let Ok(mut ready) = self.ready.lock() {
+            *ready = Some((generation, lines));
+        }
+    }
+
+    /// The delivered preview, if it answers the latest request
+    pub fn take(&self) -> Option<Vec<PreviewLine>> {
+        let (generation, lines) = self.ready.lock().ok()?.take()?;
+        self.is_current(generation).then_some(lines)
+    }
+}
+
+struct PreviewRequest {
+    slot: Arc<PreviewSlot>,
+    generation: u64,
+    source: PreviewSource,
+    syntax_theme: String,
+}
+
+/// What a request previews: a file on disk, or one produced on demand
+enum PreviewSource {
+    Path(PathBuf),
+    Prepared(Box<dyn FnOnce() -> Result<PathBuf, String> + Send>),
+    Diff(PathBuf),
+    Blame(PathBuf, Option<String>),
+}
+
+impl PreviewRequest {
+    fn is_current(&self) -> bool {
+        self.slot.is_current(self.generation)
+    }
+}
+
+/// Identifies one rendering of a file; any change to the file or theme
+/// makes a new key, so stale entries simply age out
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    size: u64,
+    syntax_theme: String,
+}
+
+impl CacheKey {
+    fn of(path: &Path, syntax_theme: &str) -> Option<Self> {
+        let meta = fs::metadata(path).ok()?;
+        Some(Self {
+            path: path.to_path_buf(),
+            modified: meta.modified().ok(),
+            size: meta.len(),
+            syntax_theme: syntax_theme.to_string(),
+        })
+    }
+}
+
+/// Least-recently-used map of rendered previews
+#[derive(Debug)]
+struct PreviewCache {
+    entries: HashMap<CacheKey, Vec<PreviewLine>>,
+    /// Keys from least to most recently used
+    order: VecDeque<CacheKey>,
+    capacity: usize,
+}
+
+impl PreviewCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    fn get(&mut self, key: &CacheKey) -> Option<Vec<PreviewLine>> {
+        let lines = self.entries.get(key)?.clone();
+        self.touch(key);
+        Some(lines)
+    }
+
+    fn insert(&mut self, key: CacheKey, lines: Vec<PreviewLine>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.insert(key.clone(), lines).is_some() {
+            self.touch(&key);
+            return;
+        }
+        self.order.push_back(key);
+        while self.order.len() > self.capacity {
+            if let Some(old) = self.order.pop_front() {
+                self.entries.remove(&old);
+            }
+        }
+    }
+
+    fn touch(&mut self, key: &CacheKey) {
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            if let Some(k) = self.order.remove(pos) {
+                self.order.push_back(k);
+            }
+        }
+    }
+}
+
+fn preview_directory(
+    path: &Path,
+    max_lines: usize,
+    cancelled: &dyn Fn() -> bool,
+) -> Option<Vec<PreviewLine>> {
+    let title = format!("📁 Directory: {}", path.display());
     match fs::read_dir(path) {
         Ok(entries) => {
-            let mut names: Vec<_> = entries
+            let names: Vec<_> = entries
+                .take_while(|_| !cancelled())
                 .filter_map(|e| e.ok())
                 .map(|e| {
                     let name = e.file_name().to_string_lossy().to_string();
@@ -48,81 +586,423 @@ fn preview_directory(path: &Path) -> Vec<PreviewLine> {
                     (name, is_dir)
                 })
                 .collect();
-            names.sort_by(|a, b| a.0.to_lowercase().cmp(&b.0.to_lowercase()));
-            for (name, is_dir) in names.into_iter().take(MAX_PREVIEW_LINES) {
-                let prefix = if is_dir { "📁 " } else { "📄 " };
-                lines.push(PreviewLine {
-                    text: format!("{prefix}{name}"),
-                    style: if is_dir {
-                        PreviewStyle::Directory
-                    } else {
-                        PreviewStyle::Normal
-                    },
-                });
+            if cancelled() {
+                return None;
             }
+            Some(preview_listing(title, names, max_lines))
         }
-        Err(e) => lines.push(PreviewLine {
-            text: format!("Error: {e}"),
-            style: PreviewStyle::Normal,
-        }),
+        Err(e) => Some(vec![
+            PreviewLine::new(title, PreviewStyle::Header),
+            PreviewLine::new(format!("Error: {e}"), PreviewStyle::Normal),
+        ]),
+    }
+}
+
+/// A titled list of `(name, is_dir)` entries, sorted by name
+pub fn preview_listing(
+    title: String,
+    mut names: Vec<(String, bool)>,
+    max_lines: usize,
+) -> Vec<PreviewLine> {
+    let mut lines = vec![PreviewLine::new(title, PreviewStyle::Header)];
+    names.sort_by_key(|a| a.0.to_lowercase());
+    for (name, is_dir) in names.into_iter().take(max_lines) {
+        let prefix = if is_dir { "📁 " } else { "📄 " };
+        lines.push(PreviewLine::new(
+            format!("{prefix}{name}"),
+            if is_dir {
+                PreviewStyle::Directory
+            } else {
+                PreviewStyle::Normal
+            },
+        ));
     }
     lines
 }
 
-fn preview_text_file(path: &Path) -> Vec<PreviewLine> {
-    let meta = match fs::metadata(path) {
-        Ok(m) => m,
+/// Entries of a zip or tar archive with their sizes, and how well the
+/// archive compressed them
+fn preview_archive(
+    path: &Path,
+    max_lines: usize,
+    cancelled: &dyn Fn() -> bool,
+) -> Option<Vec<PreviewLine>> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let index = match ArchiveIndex::open(path) {
+        Ok(index) => index,
         Err(e) => {
-            return vec![PreviewLine {
-                text: format!("Error: {e}"),
-                style: PreviewStyle::Normal,
-            }];
+            return Some(vec![
+                PreviewLine::new(format!("📦 Archive: {name}"), PreviewStyle::Header),
+                PreviewLine::new(format!("Error: {e}"), PreviewStyle::Normal),
+            ])
         }
     };
-    if meta.len() > MAX_FILE_SIZE {
-        return vec![PreviewLine {
-            text: format!("File too large ({} bytes)", meta.len()),
-            style: PreviewStyle::Header,
-        }];
-    }
-    let content = match fs::read_to_string(path) {
-        Ok(c) => c,
-        Err(_) => {
-            return vec![PreviewLine {
-                text: "Binary file".to_string(),
-                style: PreviewStyle::Header,
-            }];
+    if cancelled() {
+        return None;
+    }
+    let kind = match index.kind {
+        ArchiveKind::Tar(Codec::None) => "Tar archive".to_string(),
+        ArchiveKind::Tar(codec) => format!("Tar archive ({})", codec.name()),
+        _ => "Zip archive".to_string(),
+    };
+    let entries = index.entries();
+    let dirs = entries.iter().filter(|e| e.is_dir).count();
+    let files = entries.len() - dirs;
+    let unpacked: u64 = entries.iter().map(|e| e.size).sum();
+    let mut lines = vec![
+        PreviewLine::new(format!("📦 {kind}: {name}"), PreviewStyle::Header),
+        PreviewLine::new(
+            format!("{files} files, {dirs} directories"),
+            PreviewStyle::Normal,
+        ),
+        PreviewLine::new(
+            format!("Uncompressed: {unpacked} bytes"),
+            PreviewStyle::Normal,
+        ),
+        PreviewLine::new(
+            format!(
+                "Compressed: {} bytes{}",
+                index.size,
+                ratio(index.size, unpacked)
+            ),
+            PreviewStyle::Normal,
+        ),
+        PreviewLine::new(String::new(), PreviewStyle::Normal),
+    ];
+    for entry in entries.iter().take(max_lines) {
+        let shown = entry.path.display();
+        let line = if entry.is_dir {
+            PreviewLine::new(format!("📁 {shown}/"), PreviewStyle::Directory)
+        } else if entry.is_symlink {
+            let target = entry.symlink_target.as_deref().unwrap_or("?");
+            PreviewLine::new(format!("🔗 {shown} → {target}"), PreviewStyle::Normal)
+        } else {
+            let packed = entry
+                .compressed_size
+                .map(|c| ratio(c, entry.size))
+                .unwrap_or_default();
+            PreviewLine::new(
+                format!("📄 {shown}  {} bytes{packed}", entry.size),
+                PreviewStyle::Normal,
+            )
+        };
+        lines.push(line);
+    }
+    if entries.len() > max_lines {
+        lines.push(PreviewLine::new(
+            format!("... ({} more entries)", entries.len() - max_lines),
+            PreviewStyle::Header,
+        ));
+    }
+    Some(lines)
+}
+
+/// ` (42%)`: compressed size as a share of the original, or nothing for
+/// empty content
+fn ratio(compressed: u64, original: u64) -> String {
+    if original == 0 {
+        return String::new();
+    }
+    format!(" ({:.0}%)", compressed as f64 * 100.0 / original as f64)
+}
+
+/// Staged and unstaged changes to `path` as a colored unified diff
+pub fn preview_git_diff(path: &Path, max_lines: usize) -> Vec<PreviewLine> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let mut lines = vec![PreviewLine::new(
+        format!("± Diff: {name}"),
+        PreviewStyle::Header,
+    )];
+    let diff = match git_status::file_diff(path) {
+        Ok(diff) => diff,
+        Err(e) => {
+            lines.push(PreviewLine::new(
+                format!("Error: {e}"),
+                PreviewStyle::Normal,
+            ));
+            return lines;
         }
     };
+    if diff.is_empty() {
+        lines.push(PreviewLine::new("No changes", PreviewStyle::Normal));
+    }
+    let total = diff.len();
+    for line in diff.into_iter().take(max_lines) {
+        let style = match line.kind {
+            DiffLineKind::Section | DiffLineKind::Hunk => PreviewStyle::Header,
+            DiffLineKind::Added => PreviewStyle::Added,
+            DiffLineKind::Removed => PreviewStyle::Removed,
+            DiffLineKind::Context => PreviewStyle::Normal,
+        };
+        lines.push(PreviewLine::new(line.text, style));
+    }
+    if total > max_lines {
+        lines.push(PreviewLine::new(
+            format!("... ({} more lines)", total - max_lines),
+            PreviewStyle::Header,
+        ));
+    }
+    lines
+}
 
-    let ss = SyntaxSet::load_defaults_newlines();
-    let ts = ThemeSet::load_defaults();
-    let syntax = ss
-        .find_syntax_for_file(path)
-        .ok()
-        .flatten()
-        .unwrap_or_else(|| ss.find_syntax_plain_text());
-    let mut h = HighlightLines::new(syntax, &ts.themes["base16-ocean.dark"]);
-
-    let mut lines = Vec::new();
-    for (i, line) in LinesWithEndings::from(&content).enumerate() {
-        if i >= MAX_PREVIEW_LINES {
-            lines.push(PreviewLine {
-                text: format!("... ({} more lines)", content.lines().count() - i),
-                style: PreviewStyle::Header,
-            });
+/// `path` with each line prefixed by the commit and author that last
+/// changed it, as of commit `at` or in the worktree
+pub fn preview_git_blame(path: &Path, at: Option<&str>, max_lines: usize) -> Vec<PreviewLine> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let title = match at {
+        Some(id) => format!("± Blame: {name} @ {}", &id[..id.len().min(7)]),
+        None => format!("± Blame: {name}"),
+    };
+    let mut lines = vec![PreviewLine::new(title, PreviewStyle::Header)];
+    let blame = match git_status::file_blame(path, at) {
+        Ok(blame) => blame,
+        Err(e) => {
+            lines.push(PreviewLine::new(
+                format!("Error: {e}"),
+                PreviewStyle::Normal,
+            ));
+            return lines;
+        }
+    };
+    let total = blame.len();
+    for line in blame.into_iter().take(max_lines) {
+        let author: String = line.author.chars().take(12).collect();
+        let annotation = format!(
+            "{:<7} {author:<12} │ ",
+            line.commit.as_deref().unwrap_or("·······")
+        );
+        let role = if line.commit.is_some() {
+            PreviewStyle::LineNumber
+        } else {
+            PreviewStyle::Added
+        };
+        lines.push(PreviewLine {
+            text: format!("{annotation}{}", line.text),
+            style: PreviewStyle::Normal,
+            spans: vec![
+                PreviewSpan::plain(annotation, role),
+                PreviewSpan::plain(line.text, PreviewStyle::Normal),
+            ],
+        });
+    }
+    if total > max_lines {
+        lines.push(PreviewLine::new(
+            format!("... ({} more lines)", total - max_lines),
+            PreviewStyle::Header,
+        ));
+    }
+    lines
+}
+
+/// Read up to `limit` bytes from the start of `path`
+fn read_head(path: &Path, limit: usize) -> io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(limit);
+    fs::File::open(path)?
+        .take(limit as u64)
+        .read_to_end(&mut head)?;
+    Ok(head)
+}
+
+/// Read one line, keeping at most `cap` bytes of it (cut lines end in
+/// `…`). Returns the text without its newline and the bytes consumed, or
+/// `None` at end of file.
+fn read_line_capped(reader: &mut impl BufRead, cap: usize) -> io::Result<Option<(String, u64)>> {
+    let mut kept = Vec::new();
+    let mut consumed = 0;
+    let mut truncated = false;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
             break;
         }
-        // We just use the text; terminal coloring would need styled spans
-        let _ = h.highlight_line(line, &ss);
+        let newline = buf.iter().position(|&b| b == b'\n');
+        let chunk = &buf[..newline.map_or(buf.len(), |i| i + 1)];
+        let room = cap.saturating_sub(kept.len());
+        truncated |= newline.unwrap_or(buf.len()) > room;
+        kept.extend_from_slice(&chunk[..chunk.len().min(room)]);
+        let n = chunk.len();
+        reader.consume(n);
+        consumed += n as u64;
+        if newline.is_some() {
+            break;
+        }
+    }
+    if consumed == 0 {
+        return Ok(None);
+    }
+    let mut line = String::from_utf8_lossy(&kept).into_owned();
+    line.truncate(line.trim_end_matches(['\n', '\r']).len());
+    if truncated {
+        line.push('…');
+    }
+    Ok(Some((line, consumed)))
+}
+
+/// Count the remaining lines in `reader`, including an unterminated last one
+fn count_lines(reader: &mut impl BufRead) -> io::Result<usize> {
+    let mut count = 0;
+    let mut last = b'\n';
+    loop {
+        let buf = reader.fill_buf()?;
+        let Some(&end) = buf.last() else {
+            break;
+        };
+        count += buf.iter().filter(|&&b| b == b'\n').count();
+        last = end;
+        let n = buf.len();
+        reader.consume(n);
+    }
+    Ok(count + usize::from(last != b'\n'))
+}
+
+/// The last `n` lines of `path`, never reaching back before byte `floor`
+/// (where the head preview stopped)
+fn read_tail(path: &Path, floor: u64, n: usize) -> io::Result<Vec<String>> {
+    let mut file = fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    let window = (n as u64 * MAX_LINE_BYTES as u64)
+        .max(TAIL_WINDOW)
+        .min(size.saturating_sub(floor));
+    let start = size - window;
+    // Read one byte early to tell whether the window starts mid-line
+    let read_from = if start > floor { start - 1 } else { start };
+    file.seek(SeekFrom::Start(read_from))?;
+    let mut buf = Vec::new();
+    file.take(size - read_from).read_to_end(&mut buf)?;
+    let text = if start > floor {
+        let skip = buf
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(buf.len(), |i| i + 1);
+        &buf[skip..]
+    } else {
+        &buf[..]
+    };
+    let text = String::from_utf8_lossy(text);
+    let lines: Vec<&str> = text.lines().collect();
+    let first = lines.len().saturating_sub(n);
+    Ok(lines[first..]
+        .iter()
+        .map(|line| {
+            if line.len() > MAX_LINE_BYTES {
+                let mut end = MAX_LINE_BYTES;
+                while !line.is_char_boundary(end) {
+                    end -= 1;
+                }
+                format!("{}…", &line[..end])
+            } else {
+                line.to_string()
+            }
+        })
+        .collect())
+}
+
+/// NUL bytes or invalid UTF-8 mean binary. A multi-byte character cut
+/// off at the end of `head` doesn't count.
+fn looks_binary(head: &[u8]) -> bool {
+    head.contains(&0) || std::str::from_utf8(head).is_err_and(|e| e.error_len().is_some())
+}
+
+/// Name the format of a file from its first bytes
+pub fn detect_magic(head: &[u8]) -> Option<&'static str> {
+    const MAGICS: &[(&[u8], &str)] = &[
+        (b"\x7fELF", "ELF executable"),
+        (b"\x89PNG\r\n\x1a\n", "PNG image"),
+        (b"PK\x03\x04", "ZIP archive"),
+        (b"PK\x05\x06", "ZIP archive (empty)"),
+        (b"SQLite format 3\0", "SQLite database"),
+        (b"%PDF-", "PDF document"),
+        (b"\x1f\x8b", "gzip compressed data"),
+        (b"\xfd7zXZ\0", "xz compressed data"),
+        (b"BZh", "bzip2 compressed data"),
+        (b"\x28\xb5\x2f\xfd", "zstd compressed data"),
+        (b"\xfe\xed\xfa\xce", "Mach-O executable (32-bit)"),
+        (b"\xce\xfa\xed\xfe", "Mach-O executable (32-bit)"),
+        (b"\xfe\xed\xfa\xcf", "Mach-O executable (64-bit)"),
+        (b"\xcf\xfa\xed\xfe", "Mach-O executable (64-bit)"),
+    ];
+    if let Some((_, name)) = MAGICS.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return Some(name);
+    }
+    if head.starts_with(b"\xca\xfe\xba\xbe") {
+        // Shared by universal Mach-O and Java classes; a fat header's
+        // architecture count is tiny where a class file's version isn't
+        let count = head
+            .get(4..8)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]));
+        return Some(match count {
+            Some(n) if n < 45 => "Mach-O universal binary",
+            _ => "Java class file",
+        });
+    }
+    if head.starts_with(b"MZ") {
+        // The DOS header points at the PE signature
+        let pe = head
+            .get(0x3c..0x40)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .and_then(|offset| head.get(offset..offset + 4));
+        return Some(if pe == Some(b"PE\0\0") {
+            "PE executable"
+        } else {
+            "DOS executable"
+        });
+    }
+    None
+}
+
+/// `hexdump -C`-style rows of `head`, under a header naming the format
+fn preview_hex(head: &[u8], file_size: u64) -> Vec<PreviewLine> {
+    let kind = detect_magic(head).unwrap_or("Binary file");
+    let mut lines = vec![PreviewLine::new(
+        format!("🔢 {kind} ({file_size} bytes)"),
+        PreviewStyle::Header,
+    )];
+    for (row, chunk) in head.chunks(HEX_BYTES_PER_ROW).enumerate() {
+        let offset = format!("{:08x}  ", row * HEX_BYTES_PER_ROW);
+        let mut hex = String::with_capacity(HEX_BYTES_PER_ROW * 3 + 1);
+        for i in 0..HEX_BYTES_PER_ROW {
+            match chunk.get(i) {
+                Some(b) => hex.push_str(&format!("{b:02x} ")),
+                None => hex.push_str("   "),
+            }
+            if i == HEX_BYTES_PER_ROW / 2 - 1 {
+                hex.push(' ');
+            }
+        }
+        let ascii: String = chunk
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        let ascii = format!(" │{ascii}│");
         lines.push(PreviewLine {
-            text: format!("{:>4} │ {}", i + 1, line.trim_end()),
+            text: format!("{offset}{hex}{ascii}"),
             style: PreviewStyle::Normal,
+            spans: vec![
+                PreviewSpan::plain(offset, PreviewStyle::LineNumber),
+                PreviewSpan::plain(hex, PreviewStyle::Normal),
+                PreviewSpan::plain(ascii, PreviewStyle::Directory),
+            ],
         });
     }
+    if file_size > head.len() as u64 {
+        lines.push(PreviewLine::new(
+            format!("... ({} more bytes)", file_size - head.len() as u64),
+            PreviewStyle::Header,
+        ));
+    }
     lines
 }
 
+fn rgb(c: Color) -> Rgb {
+    (c.r, c.g, c.b)
+}
+
 fn is_image(path: &Path) -> bool {
     matches!(
         path.extension()
@@ -134,24 +1014,24 @@ fn is_image(path: &Path) -> bool {
 }
 
 fn preview_image_meta(path: &Path) -> Vec<PreviewLine> {
-    let mut lines = vec![PreviewLine {
-        text: format!(
+    let mut lines = vec![PreviewLine::new(
+        format!(
             "🖼️  Image: {}",
             path.file_name().unwrap_or_default().to_string_lossy()
         ),
-        style: PreviewStyle::Header,
-    }];
+        PreviewStyle::Header,
+    )];
     if let Ok(meta) = fs::metadata(path) {
-        lines.push(PreviewLine {
-            text: format!("Size: {} bytes", meta.len()),
-            style: PreviewStyle::Normal,
-        });
+        lines.push(PreviewLine::new(
+            format!("Size: {} bytes", meta.len()),
+            PreviewStyle::Normal,
+        ));
     }
     if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-        lines.push(PreviewLine {
-            text: format!("Format: {}", ext.to_uppercase()),
-            style: PreviewStyle::Normal,
-        });
+        lines.push(PreviewLine::new(
+            format!("Format: {}", ext.to_uppercase()),
+            PreviewStyle::Normal,
+        ));
     }
     lines
 }
@@ -166,7 +1046,11 @@ mod tests {
         let tmp = TempDir::new().unwrap();
         fs::write(tmp.path().join("a.txt"), "hello").unwrap();
         fs::create_dir(tmp.path().join("subdir")).unwrap();
-        let lines = preview_path(tmp.path());
+        let lines = preview(
+            &Arc::new(PreviewEngine::default()),
+            tmp.path(),
+            FALLBACK_SYNTAX_THEME,
+        );
         assert!(!lines.is_empty());
         assert!(lines[0].text.contains("Directory"));
     }
@@ -176,7 +1060,11 @@ mod tests {
         let tmp = TempDir::new().unwrap();
         let f = tmp.path().join("test.rs");
         fs::write(&f, "fn main() {}").unwrap();
-        let lines = preview_path(&f);
+        let lines = preview(
+            &Arc::new(PreviewEngine::default()),
+            &f,
+            FALLBACK_SYNTAX_THEME,
+        );
         assert!(!lines.is_empty());
         assert!(lines[0].text.contains("main"));
     }
@@ -185,8 +1073,12 @@ mod tests {
     fn test_preview_binary_file() {
         let tmp = TempDir::new().unwrap();
         let f = tmp.path().join("binary.bin");
-        fs::write(&f, &[0u8, 1, 2, 255, 254]).unwrap();
-        let lines = preview_path(&f);
+        fs::write(&f, [0u8, 1, 2, 255, 254]).unwrap();
+        let lines = preview(
+            &Arc::new(PreviewEngine::default()),
+            &f,
+            FALLBACK_SYNTAX_THEME,
+        );
         assert!(!lines.is_empty());
     }
 
@@ -195,7 +1087,11 @@ mod tests {
         let tmp = TempDir::new().unwrap();
         let f = tmp.path().join("photo.png");
         fs::write(&f, "fake png").unwrap();
-        let lines = preview_path(&f);
+        let lines = preview(
+            &Arc::new(PreviewEngine::default()),
+            &f,
+            FALLBACK_SYNTAX_THEME,
+        );
         assert!(lines[0].text.contains("Image"));
     }
 
@@ -209,10 +1105,348 @@ mod tests {
 
     #[test]
     fn test_preview_nonexistent() {
-        let lines = preview_path(Path::new("/nonexistent_file_xyz"));
+        let lines = preview(
+            &Arc::new(PreviewEngine::default()),
+            Path::new("/nonexistent_file_xyz"),
+            FALLBACK_SYNTAX_THEME,
+        );
         assert!(!lines.is_empty());
     }
 
+    #[test]
+    fn test_preview_text_file_is_highlighted() {
+        let tmp = TempDir::new().unwrap();
+        let f = tmp.path().join("test.rs");
+        fs::write(&f, "fn main() {}\n").unwrap();
+        let lines = preview(
+            &Arc::new(PreviewEngine::default()),
+            &f,
+            "base16-eighties.dark",
+        );
+        let spans = &lines[0].spans;
+        assert_eq!(spans[0].role, PreviewStyle::LineNumber);
+        let code: String = spans[1..].iter().map(|s| s.text.as_str()).collect();
+        assert_eq!(code, "fn main() {}");
+        // `fn` and `main` are colored differently
+        assert!(spans[1..].iter().all(|s| s.fg.is_some()));
+        let colors: std::collections::HashSet<_> = spans[1..].iter().map(|s| s.fg).collect();
+        assert!(colors.len() > 1);
+    }
+
+    #[test]
+    fn test_unknown_syntax_theme_falls_back() {
+        let tmp = TempDir::new().unwrap();
+        let f = tmp.path().join("test.rs");
+        fs::write(&f, "fn main() {}").unwrap();
+        let lines = preview(&Arc::new(PreviewEngine::default()), &f, "no-such-theme");
+        assert!(lines[0].spans.len() > 1);
+    }
+
+    #[test]
+    fn test_preview_cache_hit_and_invalidation() {
+        let tmp = TempDir::new().unwrap();
+        let f = tmp.path().join("a.txt");
+        fs::write(&f, "one").unwrap();
+        let engine = Arc::new(PreviewEngine::default());
+        assert!(preview(&engine, &f, FALLBACK_SYNTAX_THEME)[0]
+            .text
+            .contains("one"));
+        let key = CacheKey::of(&f, FALLBACK_SYNTAX_THEME).unwrap();
+        assert!(engine.cache.lock().unwrap().entries.contains_key(&key));
+        // A different size is a different key, so the new content shows
+        fs::write(&f, "second").unwrap();
+        assert!(preview(&engine, &f, FALLBACK_SYNTAX_THEME)[0]
+            .text
+            .contains("second"));
+    }
+
+    #[test]
+    fn test_preview_cache_evicts_least_recent() {
+        let tmp = TempDir::new().unwrap();
+        let engine = Arc::new(PreviewEngine::with_capacity(2, PreviewConfig::default()));
+        let paths: Vec<_> = ["a", "b", "c"]
+            .iter()
+            .map(|n| {
+                let p = tmp.path().join(n);
+                fs::write(&p, n).unwrap();
+                p
+            })
+            .collect();
+        preview(&engine, &paths[0], FALLBACK_SYNTAX_THEME);
+        preview(&engine, &paths[1], FALLBACK_SYNTAX_THEME);
+        // Touch `a` so `b` becomes the oldest
+        preview(&engine, &paths[0], FALLBACK_SYNTAX_THEME);
+        preview(&engine, &paths[2], FALLBACK_SYNTAX_THEME);
+        let cache = engine.cache.lock().unwrap();
+        let cached = |p: &Path| {
+            cache
+                .entries
+                .contains_key(&CacheKey::of(p, FALLBACK_SYNTAX_THEME).unwrap())
+        };
+        assert!(cached(&paths[0]));
+        assert!(!cached(&paths[1]));
+        assert!(cached(&paths[2]));
+    }
+
+    /// Preview `path` the way a tab does: from the cache while fresh,
+    /// otherwise rendered by the worker and delivered to a slot
+    fn preview(engine: &Arc<PreviewEngine>, path: &Path, syntax_theme: &str) -> Vec<PreviewLine> {
+        if let Some(lines) = engine.cached(path, syntax_theme) {
+            return lines;
+        }
+        let slot = Arc::new(PreviewSlot::default());
+        engine.request(&slot, path.to_path_buf(), syntax_theme.to_string());
+        wait_for(&slot)
+    }
+
+    /// Wait for the background worker to answer the slot's latest request
+    fn wait_for(slot: &PreviewSlot) -> Vec<PreviewLine> {
+        for _ in 0..1000 {
+            if let Some(lines) = slot.take() {
+                return lines;
+            }
+            thread::sleep(std::time::Duration::from_millis(2));
+        }
+        panic!("preview never arrived");
+    }
+
+    #[test]
+    fn test_background_preview_is_delivered_and_cached() {
+        let tmp = TempDir::new().unwrap();
+        let f = tmp.path().join("a.txt");
+        fs::write(&f, "hello").unwrap();
+        let engine = Arc::new(PreviewEngine::default());
+        let slot = Arc::new(PreviewSlot::default());
+        assert!(engine.cached(&f, FALLBACK_SYNTAX_THEME).is_none());
+        engine.request(&slot, f.clone(), FALLBACK_SYNTAX_THEME.to_string());
+        assert!(wait_for(&slot)[0].text.contains("hello"));
+        assert!(engine.cached(&f, FALLBACK_SYNTAX_THEME).is_some());
+    }
+
+    #[test]
+    fn test_stale_preview_is_dropped() {
+        let tmp = TempDir::new().unwrap();
+        let a = tmp.path().join("a.txt");
+        let b = tmp.path().join("b.txt");
+        fs::write(&a, "first").unwrap();
+        fs::write(&b, "second").unwrap();
+        let engine = Arc::new(PreviewEngine::default());
+        let slot = Arc::new(PreviewSlot::default());
+        engine.request(&slot, a, FALLBACK_SYNTAX_THEME.to_string());
+        engine.request(&slot, b, FALLBACK_SYNTAX_THEME.to_string());
+        assert!(wait_for(&slot)[0].text.contains("second"));
+        // A result for an old generation is never handed out
+        slot.deliver(1, vec![PreviewLine::new("stale", PreviewStyle::Normal)]);
+        assert!(slot.take().is_none());
+    }
+
+    #[test]
+    fn test_preview_binary_hex_dump() {
+        let tmp = TempDir::new().unwrap();
+        let f = tmp.path().join("prog");
+        let mut bytes = b"\x7fELF\x02\x01\x01".to_vec();
+        bytes.resize(20, 0);
+        bytes.extend_from_slice(b"hi");
+        fs::write(&f, &bytes).unwrap();
+        let lines = preview(
+            &Arc::new(PreviewEngine::default()),
+            &f,
+            FALLBACK_SYNTAX_THEME,
+        );
+        assert_eq!(lines[0].text, "🔢 ELF executable (22 bytes)");
+        assert_eq!(
+            lines[1].text,
+            "00000000  7f 45 4c 46 02 01 01 00  00 00 00 00 00 00 00 00  │.ELF............│"
+        );
+        assert!(lines[2].text.starts_with("00000010  00 00 00 00 68 69"));
+        assert!(lines[2].text.ends_with("│....hi│"));
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[test]
+    fn test_preview_zip_listing() {
+        use std::io::Write;
+        let tmp = TempDir::new().unwrap();
+        let f = tmp.path().join("bundle.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&f).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("docs/notes.txt", options).unwrap();
+        zip.write_all(&[b'a'; 1000]).unwrap();
+        zip.finish().unwrap();
+        let lines = preview(
+            &Arc::new(PreviewEngine::default()),
+            &f,
+            FALLBACK_SYNTAX_THEME,
+        );
+        let text: Vec<_> = lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(text[0], "📦 Zip archive: bundle.zip");
+        assert_eq!(text[1], "1 files, 1 directories");
+        assert_eq!(text[2], "Uncompressed: 1000 bytes");
+        assert!(text[3].starts_with("Compressed: "));
+        assert!(text.contains(&"📁 docs/"));
+        let entry = text.iter().find(|t| t.contains("notes.txt")).unwrap();
+        // Deflate squeezes a run of one byte to a few percent
+        assert!(entry.starts_with("📄 docs/notes.txt  1000 bytes ("));
+        assert!(entry.ends_with("%)"));
+    }
+
+    #[test]
+    fn test_preview_tar_gz_listing() {
+        let tmp = TempDir::new().unwrap();
+        let src = tmp.path().join("src");
+        fs::create_dir(&src).unwrap();
+        fs::write(src.join("a.txt"), "abc").unwrap();
+        fs::write(src.join("b.txt"), "defg").unwrap();
+        let f = tmp.path().join("src.tgz");
+        crate::file_ops::compress_tar(&[src], &f, Codec::Gzip, &crate::jobs::Progress::default())
+            .unwrap();
+        let engine = Arc::new(PreviewEngine::new(PreviewConfig {
+            max_lines: 2,
+            ..PreviewConfig::default()
+        }));
+        let lines = preview(&engine, &f, FALLBACK_SYNTAX_THEME);
+        let text: Vec<_> = lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(text[0], "📦 Tar archive (gzip): src.tgz");
+        assert_eq!(text[1], "2 files, 1 directories");
+        assert_eq!(text[2], "Uncompressed: 7 bytes");
+        assert_eq!(
+            &text[5..],
+            ["📁 src/", "📄 src/a.txt  3 bytes", "... (1 more entries)"]
+        );
+    }
+
+    #[test]
+    fn test_ratio() {
+        assert_eq!(ratio(25, 100), " (25%)");
+        assert_eq!(ratio(10, 0), "");
+    }
+
+    #[test]
+    fn test_looks_binary() {
+        assert!(!looks_binary(b"plain text\n"));
+        assert!(looks_binary(b"nul\0inside"));
+        assert!(looks_binary(b"\xff\xfe bad utf-8"));
+        // A UTF-8 character split by the head limit is still text
+        assert!(!looks_binary(&"é".as_bytes()[..1]));
+    }
+
+    #[test]
+    fn test_detect_magic() {
+        let mut pe = b"MZ".to_vec();
+        pe.resize(0x40, 0);
+        pe[0x3c] = 0x40;
+        pe.extend_from_slice(b"PE\0\0");
+        let cases: &[(&[u8], Option<&str>)] = &[
+            (b"\x7fELF\x02", Some("ELF executable")),
+            (&pe, Some("PE executable")),
+            (b"MZ\x90\x00", Some("DOS executable")),
+            (b"\xcf\xfa\xed\xfe\x07", Some("Mach-O executable (64-bit)")),
+            (
+                b"\xca\xfe\xba\xbe\0\0\0\x02",
+                Some("Mach-O universal binary"),
+            ),
+            (b"\xca\xfe\xba\xbe\0\0\0\x34", Some("Java class file")),
+            (b"\x89PNG\r\n\x1a\n", Some("PNG image")),
+            (b"PK\x03\x04", Some("ZIP archive")),
+            (b"SQLite format 3\0", Some("SQLite database")),
+            (b"%PDF-1.7", Some("PDF document")),
+            (b"\xfd7zXZ\0\0", Some("xz compressed data")),
+            (b"\x28\xb5\x2f\xfd\x04", Some("zstd compressed data")),
+            (b"\x00\x01\x02", None),
+        ];
+        for (head, expected) in cases {
+            assert_eq!(detect_magic(head), *expected, "{head:?}");
+        }
+    }
+
+    fn limits(max_lines: usize, tail_lines: usize) -> PreviewConfig {
+        PreviewConfig {
+            max_lines,
+            tail_lines,
+        }
+    }
+
+    #[test]
+    fn test_preview_head_of_long_file() {
+        let tmp = TempDir::new().unwrap();
+        let f = tmp.path().join("log.txt");
+        let content: String = (1..=10).map(|i| format!("line {i}\n")).collect();
+        fs::write(&f, content).unwrap();
+        let engine = Arc::new(PreviewEngine::new(limits(3, 0)));
+        let lines = preview(&engine, &f, FALLBACK_SYNTAX_THEME);
+        let text: Vec<_> = lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(
+            text,
+            [
+                "   1 │ line 1",
+                "   2 │ line 2",
+                "   3 │ line 3",
+                "... (7 more lines)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_preview_head_and_tail() {
+        let tmp = TempDir::new().unwrap();
+        let f = tmp.path().join("log.txt");
+        let content: String = (1..=10).map(|i| format!("line {i}\n")).collect();
+        fs::write(&f, content).unwrap();
+        let engine = Arc::new(PreviewEngine::new(limits(2, 3)));
+        let lines = preview(&engine, &f, FALLBACK_SYNTAX_THEME);
+        let text: Vec<_> = lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(
+            text,
+            [
+                "   1 │ line 1",
+                "   2 │ line 2",
+                "... (5 more lines)",
+                "   8 │ line 8",
+                "   9 │ line 9",
+                "  10 │ line 10",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_preview_huge_file_streams_head() {
+        let tmp = TempDir::new().unwrap();
+        let f = tmp.path().join("big.log");
+        let line = "x".repeat(99) + "\n";
+        fs::write(&f, line.repeat(20_000)).unwrap(); // 2 MB
+        let engine = Arc::new(PreviewEngine::new(limits(5, 2)));
+        let lines = preview(&engine, &f, FALLBACK_SYNTAX_THEME);
+        assert_eq!(lines.len(), 5 + 1 + 2);
+        assert_eq!(lines[5].text, "... (1999500 more bytes)");
+        // Line numbers are unknown without counting the whole file
+        assert!(lines[6].text.starts_with("     │ xxx"));
+    }
+
+    #[test]
+    fn test_read_line_capped() {
+        let mut reader = BufReader::with_capacity(4, "abcdefgh\nxy\r\nz".as_bytes());
+        assert_eq!(
+            read_line_capped(&mut reader, 5).unwrap(),
+            Some(("abcde…".to_string(), 9))
+        );
+        assert_eq!(
+            read_line_capped(&mut reader, 5).unwrap(),
+            Some(("xy".to_string(), 4))
+        );
+        assert_eq!(
+            read_line_capped(&mut reader, 1).unwrap(),
+            Some(("z".to_string(), 1))
+        );
+        assert_eq!(read_line_capped(&mut reader, 5).unwrap(), None);
+    }
+
+    #[test]
+    fn test_count_lines() {
+        assert_eq!(count_lines(&mut "a\nb\n".as_bytes()).unwrap(), 2);
+        assert_eq!(count_lines(&mut "a\nb".as_bytes()).unwrap(), 2);
+        assert_eq!(count_lines(&mut "".as_bytes()).unwrap(), 0);
+    }
+
     #[test]
     fn test_preview_style_eq() {
         assert_eq!(PreviewStyle::Normal, PreviewStyle::Normal);
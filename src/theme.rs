@@ -31,6 +31,16 @@ impl ThemeName {
             Self::Gruvbox => "Gruvbox",
         }
     }
+
+    /// Bundled syntect theme used for code previews under this palette
+    pub fn syntax_theme(self) -> &'static str {
+        match self {
+            Self::Default | Self::Nord => "base16-ocean.dark",
+            Self::Dracula => "base16-eighties.dark",
+            Self::Catppuccin => "base16-mocha.dark",
+            Self::Gruvbox => "Solarized (dark)",
+        }
+    }
 }
 
 /// Resolved color palette for rendering
@@ -1,4 +1,8 @@
-use crate::app::{App, FileEntry, InputMode, MouseAreas};
+use crate::app::{App, CompressFormat, FileEntry, FileStat, InputMode, MouseAreas, PasteConflict};
+use crate::file_ops::SearchResult;
+use crate::git_status::GitFileStatus;
+use crate::jobs::{Job, JobState};
+use crate::preview::{PreviewSpan, PreviewStyle};
 use chrono::{DateTime, Local};
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
@@ -7,6 +11,10 @@ use ratatui::{
     widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
     Frame,
 };
+use std::time::{Duration, SystemTime};
+
+/// Entries waiting in the cut register are drawn faded until pasted
+const CUT_MODIFIER: Modifier = Modifier::DIM.union(Modifier::ITALIC);
 
 pub fn draw(f: &mut Frame, app: &mut App) {
     let has_tabs = app.tabs.len() > 1;
@@ -90,6 +98,14 @@ fn draw_panes(f: &mut Frame, app: &mut App, area: Rect) {
         draw_search_results(f, app, area);
         return;
     }
+    if app.input_mode == InputMode::Jobs {
+        draw_job_list(f, app, area);
+        return;
+    }
+    if app.input_mode == InputMode::GitLog {
+        draw_git_log(f, app, area);
+        return;
+    }
 
     if app.dual_pane {
         draw_dual_panes(f, app, area);
@@ -163,10 +179,17 @@ fn draw_dual_file_pane(f: &mut Frame, app: &mut App, area: Rect, is_right: bool)
             if selected {
                 style = style.add_modifier(Modifier::BOLD).fg(theme.selected);
             }
+            let cut = app.is_cut(&entry.path);
+            if cut {
+                style = style.add_modifier(CUT_MODIFIER);
+            }
             let mut name = entry_display_name(entry);
             if let Some(gs) = &entry.git_status {
                 name = format!("[{}] {}", gs.icon(), name);
             }
+            if cut {
+                name = format!("✂ {name}");
+            }
             if selected && !is_cursor {
                 name = format!("* {name}");
             }
@@ -245,11 +268,18 @@ fn draw_current_pane(f: &mut Frame, app: &mut App, area: Rect) {
             if selected {
                 style = style.add_modifier(Modifier::BOLD).fg(theme.selected);
             }
+            let cut = app.is_cut(&entry.path);
+            if cut {
+                style = style.add_modifier(CUT_MODIFIER);
+            }
 
             let mut name = entry_display_name(entry);
             if let Some(gs) = &entry.git_status {
                 name = format!("[{}] {}", gs.icon(), name);
             }
+            if cut {
+                name = format!("✂ {name}");
+            }
             if selected && !is_cursor {
                 name = format!("* {name}");
             }
@@ -291,6 +321,10 @@ fn draw_tree_pane(f: &mut Frame, app: &App, area: Rect) {
             if selected {
                 style = style.add_modifier(Modifier::BOLD).fg(theme.selected);
             }
+            let cut = app.is_cut(&node.entry.path);
+            if cut {
+                style = style.add_modifier(CUT_MODIFIER);
+            }
 
             let indent = "  ".repeat(node.depth);
             let icon = if node.entry.is_dir {
@@ -305,6 +339,9 @@ fn draw_tree_pane(f: &mut Frame, app: &App, area: Rect) {
                 "  "
             };
             let mut name = entry_display_name(&node.entry);
+            if cut {
+                name = format!("✂ {name}");
+            }
             if selected && !is_cursor {
                 name = format!("* {name}");
             }
@@ -337,20 +374,21 @@ fn draw_search_results(f: &mut Frame, app: &App, area: Rect) {
         .take(visible_height)
         .map(|(i, result)| {
             let is_cursor = i == app.search_cursor;
-            let text = format!(
-                "{}:{} {}",
-                result.path.display(),
-                result.line_number,
-                result.line_text.trim()
-            );
             let style = if is_cursor {
-                Style::default()
-                    .fg(theme.cursor_fg)
-                    .bg(theme.search_highlight)
+                Style::default().fg(theme.cursor_fg).bg(theme.cursor_bg)
             } else {
                 Style::default().fg(theme.fg)
             };
-            ListItem::new(text).style(style)
+            let highlight = Style::default()
+                .fg(theme.cursor_fg)
+                .bg(theme.search_highlight);
+            let mut spans = vec![Span::raw(format!(
+                "{}:{} ",
+                result.path.display(),
+                result.line_number
+            ))];
+            spans.extend(match_spans(result, highlight));
+            ListItem::new(Line::from(spans)).style(style)
         })
         .collect();
 
@@ -365,21 +403,148 @@ fn draw_search_results(f: &mut Frame, app: &App, area: Rect) {
     f.render_widget(list, area);
 }
 
-fn draw_preview_pane(f: &mut Frame, app: &App, area: Rect) {
+/// Commits that touched a path, with the path as of the selected one
+fn draw_git_log(f: &mut Frame, app: &App, area: Rect) {
     let theme = &app.theme;
-    let lines: Vec<Line> = app
-        .preview_lines()
+    let panes = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area);
+    let visible_height = panes[0].height.saturating_sub(2) as usize; // borders
+    let scroll = (app.git_log_cursor + 1).saturating_sub(visible_height);
+
+    let items: Vec<ListItem> = app
+        .git_log
         .iter()
-        .map(|pl| {
-            let color = match pl.style {
-                crate::preview::PreviewStyle::Header => theme.preview_header,
-                crate::preview::PreviewStyle::Directory => theme.directory,
-                crate::preview::PreviewStyle::LineNumber => theme.preview_line_no,
-                crate::preview::PreviewStyle::Normal => theme.fg,
+        .enumerate()
+        .skip(scroll)
+        .take(visible_height)
+        .map(|(i, commit)| {
+            let date = DateTime::from_timestamp(commit.time, 0)
+                .map(|t| t.with_timezone(&Local).format("%Y-%m-%d").to_string())
+                .unwrap_or_default();
+            let author: String = commit.author.chars().take(16).collect();
+            let text = format!("{} {date} {author:<16} {}", commit.short, commit.subject);
+            let style = if i == app.git_log_cursor {
+                Style::default()
+                    .fg(theme.cursor_fg)
+                    .bg(theme.search_highlight)
+            } else {
+                Style::default().fg(theme.fg)
             };
-            Line::from(Span::styled(pl.text.clone(), Style::default().fg(color)))
+            ListItem::new(text).style(style)
+        })
+        .collect();
+
+    let name = app
+        .git_log_path
+        .as_ref()
+        .and_then(|p| p.file_name())
+        .unwrap_or_default()
+        .to_string_lossy();
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("± Log: {name} ({} commits)", app.git_log.len()))
+        .border_style(Style::default().fg(theme.border));
+    f.render_widget(List::new(items).block(block), panes[0]);
+    draw_preview_pane(f, app, panes[1]);
+}
+
+/// The trimmed line of a search result, its matches in `highlight`
+fn match_spans(result: &SearchResult, highlight: Style) -> Vec<Span<'_>> {
+    let line = &result.line_text;
+    let start = line.len() - line.trim_start().len();
+    let end = line.trim_end().len().max(start);
+    let mut spans = Vec::new();
+    let mut pos = start;
+    for range in &result.matches {
+        let (from, to) = (range.start.clamp(pos, end), range.end.clamp(pos, end));
+        if from >= to {
+            continue;
+        }
+        if from > pos {
+            spans.push(Span::raw(&line[pos..from]));
+        }
+        spans.push(Span::styled(&line[from..to], highlight));
+        pos = to;
+    }
+    if pos < end {
+        spans.push(Span::raw(&line[pos..end]));
+    }
+    spans
+}
+
+fn draw_job_list(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.theme;
+    let visible_height = area.height.saturating_sub(2) as usize; // borders
+    let scroll = (app.job_cursor + 1).saturating_sub(visible_height);
+
+    let items: Vec<ListItem> = app
+        .jobs
+        .jobs
+        .iter()
+        .enumerate()
+        .skip(scroll)
+        .take(visible_height)
+        .map(|(i, job)| {
+            let state = match &job.state {
+                JobState::Running => job_progress(job),
+                JobState::Done => "done".to_string(),
+                JobState::Failed(e) => format!("failed: {e}"),
+                JobState::Cancelled => "cancelled".to_string(),
+            };
+            let text = format!(
+                "#{} {} {} — {state}",
+                job.id,
+                job.kind.label(),
+                job.description
+            );
+            let style = if i == app.job_cursor {
+                Style::default().fg(theme.cursor_fg).bg(theme.cursor_bg)
+            } else if job.is_running() {
+                Style::default().fg(theme.fg)
+            } else {
+                Style::default().fg(theme.fg).add_modifier(Modifier::DIM)
+            };
+            ListItem::new(text).style(style)
         })
         .collect();
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("⏳ Jobs ({} running)", app.jobs.running_count()))
+        .border_style(Style::default().fg(theme.border));
+    let list = List::new(items).block(block);
+    f.render_widget(list, area);
+}
+
+fn draw_preview_pane(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.theme;
+    let lines: Vec<Line> = if app.preview_loading() {
+        vec![Line::from(Span::styled(
+            "loading…",
+            Style::default()
+                .fg(theme.preview_line_no)
+                .add_modifier(Modifier::ITALIC),
+        ))]
+    } else {
+        app.preview_lines()
+            .iter()
+            .map(|pl| {
+                if pl.spans.is_empty() {
+                    let color = preview_role_color(pl.style, theme);
+                    Line::from(Span::styled(pl.text.clone(), Style::default().fg(color)))
+                } else {
+                    Line::from(
+                        pl.spans
+                            .iter()
+                            .map(|span| preview_span(span, theme))
+                            .collect::<Vec<_>>(),
+                    )
+                }
+            })
+            .collect()
+    };
     let block = Block::default()
         .borders(Borders::LEFT)
         .title("Preview")
@@ -390,21 +555,55 @@ fn draw_preview_pane(f: &mut Frame, app: &App, area: Rect) {
     f.render_widget(paragraph, area);
 }
 
+fn preview_role_color(role: PreviewStyle, theme: &crate::theme::Theme) -> Color {
+    match role {
+        PreviewStyle::Header => theme.preview_header,
+        PreviewStyle::Directory => theme.directory,
+        PreviewStyle::LineNumber => theme.preview_line_no,
+        PreviewStyle::Normal => theme.fg,
+        PreviewStyle::Added => theme.git_added,
+        PreviewStyle::Removed => theme.git_modified,
+    }
+}
+
+/// Convert a highlighted preview span into a ratatui span
+fn preview_span<'a>(span: &'a PreviewSpan, theme: &crate::theme::Theme) -> Span<'a> {
+    let fg = span
+        .fg
+        .map(|(r, g, b)| Color::Rgb(r, g, b))
+        .unwrap_or_else(|| preview_role_color(span.role, theme));
+    let mut style = Style::default().fg(fg);
+    if let Some((r, g, b)) = span.bg {
+        style = style.bg(Color::Rgb(r, g, b));
+    }
+    if span.bold {
+        style = style.add_modifier(Modifier::BOLD);
+    }
+    if span.italic {
+        style = style.add_modifier(Modifier::ITALIC);
+    }
+    if span.underline {
+        style = style.add_modifier(Modifier::UNDERLINED);
+    }
+    Span::styled(span.text.as_str(), style)
+}
+
 fn draw_status_bar(f: &mut Frame, app: &App, area: Rect) {
     let rows = Layout::default()
         .direction(Direction::Vertical)
         .constraints([Constraint::Length(1), Constraint::Length(1)])
         .split(area);
 
-    let info = if let Some(entry) = app.selected_entry() {
+    let compare = app
+        .paste_state
+        .as_ref()
+        .filter(|s| s.show_compare && app.input_mode == InputMode::PasteConflict)
+        .and_then(|s| s.conflict.as_ref());
+    let info = if let Some(conflict) = compare {
+        paste_comparison(conflict)
+    } else if let Some(entry) = app.selected_entry() {
         let size = human_size(entry.size);
-        let modified = entry
-            .modified
-            .map(|m| {
-                let dt: DateTime<Local> = m.into();
-                dt.format("%Y-%m-%d %H:%M").to_string()
-            })
-            .unwrap_or_else(|| "—".to_string());
+        let modified = format_time(entry.modified);
         let symlink_info = if entry.is_symlink {
             format!(" → {}", entry.symlink_target.as_deref().unwrap_or("?"))
         } else {
@@ -415,9 +614,21 @@ fn draw_status_bar(f: &mut Frame, app: &App, area: Rect) {
         String::new()
     };
     let theme = &app.theme;
+    let jobs = job_summary(app);
+    let info_row = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(jobs.chars().count() as u16),
+        ])
+        .split(rows[0]);
     f.render_widget(
         Paragraph::new(info).style(Style::default().bg(theme.status_bg).fg(theme.status_fg)),
-        rows[0],
+        info_row[0],
+    );
+    f.render_widget(
+        Paragraph::new(jobs).style(Style::default().bg(theme.status_bg).fg(theme.status_fg)),
+        info_row[1],
     );
 
     let tab_info = if app.tabs.len() > 1 {
@@ -436,7 +647,7 @@ fn draw_status_bar(f: &mut Frame, app: &App, area: Rect) {
             InputMode::Bookmark => "Bookmark key?".to_string(),
             InputMode::JumpBookmark => "Jump to bookmark?".to_string(),
             InputMode::Chmod => format!("chmod (octal): {}", app.input_buffer),
-            InputMode::Search => format!("Search: {}", app.input_buffer),
+            InputMode::Search => app.search_prompt(),
             InputMode::SearchResults => {
                 format!(
                     "Search results: {}/{} — j/k navigate, Enter open, Esc close",
@@ -444,11 +655,51 @@ fn draw_status_bar(f: &mut Frame, app: &App, area: Rect) {
                     app.search_results.len()
                 )
             }
+            InputMode::PasteConflict => paste_conflict_prompt(app),
+            InputMode::GitLog => format!(
+                "Git log: {}/{} — j/k navigate, gb toggle blame, Esc close",
+                app.git_log_cursor + 1,
+                app.git_log.len()
+            ),
+            InputMode::Jobs => {
+                "Jobs — j/k navigate, c cancel, C clear finished, Esc close".to_string()
+            }
+            InputMode::Compress => format!(
+                "Compress as {} (Tab cycles format): {}",
+                app.compress_mode.unwrap_or(CompressFormat::Zip).extension(),
+                app.input_buffer
+            ),
+            InputMode::Extract => format!(
+                "Extract {} to (Tab for other pane): {}",
+                app.extract_prompt
+                    .as_ref()
+                    .and_then(|p| p.archive.file_name())
+                    .unwrap_or_default()
+                    .to_string_lossy(),
+                app.input_buffer
+            ),
+            InputMode::ConfirmDiscard => format!(
+                "Discard worktree changes to {}? (y/N)",
+                match app.pending_discard.as_slice() {
+                    [single] => single
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string(),
+                    many => format!("{} items", many.len()),
+                }
+            ),
             InputMode::Normal | InputMode::Filter => String::new(),
         }
     } else {
+        let git = match app.repo_info() {
+            Some(info) => format!(" │ {}", info.summary()),
+            // First scan of a large repository still running
+            None if app.tab().git_pending => " │ ⎇ …".to_string(),
+            None => String::new(),
+        };
         format!(
-            "{} {} files │ {} selected │ Sort: {:?}",
+            "{} {} files │ {} selected │ Sort: {:?}{git}",
             tab_info,
             app.file_count(),
             app.selection_count(),
@@ -475,6 +726,117 @@ fn draw_status_bar(f: &mut Frame, app: &App, area: Rect) {
     );
 }
 
+/// Right-hand status text for running jobs: the oldest one in detail,
+/// plus a count of the rest
+fn job_summary(app: &App) -> String {
+    let mut running = app.jobs.running();
+    let Some(job) = running.next() else {
+        return String::new();
+    };
+    let more = running.count();
+    let more = if more > 0 {
+        format!(" (+{more} more)")
+    } else {
+        String::new()
+    };
+    format!("{} {}{more} ", job.kind.label(), job_progress(job))
+}
+
+/// Bar, percentage, amount done, rate and ETA of a running job
+fn job_progress(job: &Job) -> String {
+    let snap = job.progress.snapshot();
+    let mut parts = Vec::new();
+    if let Some(fraction) = snap.fraction() {
+        parts.push(format!(
+            "{} {:>3.0}%",
+            progress_bar(fraction, 10),
+            fraction * 100.0
+        ));
+    }
+    if snap.files_total > 0 {
+        parts.push(format!("{}/{} files", snap.files_done, snap.files_total));
+    } else if snap.files_done > 0 {
+        // Open-ended work such as a search
+        parts.push(format!("{} files", snap.files_done));
+    }
+    if snap.bytes_total > 0 {
+        parts.push(format!(
+            "{}/{}",
+            human_size(snap.bytes_done),
+            human_size(snap.bytes_total)
+        ));
+        parts.push(format!("{}/s", human_size(job.rate() as u64)));
+    }
+    if let Some(eta) = job.eta() {
+        parts.push(format!("ETA {}", format_eta(eta)));
+    }
+    if parts.is_empty() {
+        "…".to_string()
+    } else {
+        parts.join(" │ ")
+    }
+}
+
+fn progress_bar(fraction: f64, width: usize) -> String {
+    let filled = ((fraction * width as f64).round() as usize).min(width);
+    format!("[{}{}]", "█".repeat(filled), "░".repeat(width - filled))
+}
+
+fn format_eta(eta: Duration) -> String {
+    let secs = eta.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+fn paste_conflict_prompt(app: &App) -> String {
+    let Some(state) = app.paste_state.as_ref() else {
+        return String::new();
+    };
+    let name = state
+        .conflict
+        .as_ref()
+        .and_then(|c| c.dest.file_name())
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let all = if state.apply_all { "on" } else { "off" };
+    format!(
+        "\"{name}\" exists — [o]verwrite [s]kip [r]ename [a]pply to all:{all} [c]ompare Esc:cancel"
+    )
+}
+
+fn paste_comparison(conflict: &PasteConflict) -> String {
+    let describe = |stat: Option<FileStat>| match stat {
+        Some(s) if s.is_dir => format!("dir, {}", format_time(s.modified)),
+        Some(s) => format!("{}, {}", human_size(s.size), format_time(s.modified)),
+        None => "missing".to_string(),
+    };
+    let newer = match (
+        conflict.src_stat.and_then(|s| s.modified),
+        conflict.dest_stat.and_then(|s| s.modified),
+    ) {
+        (Some(a), Some(b)) if a > b => " │ source is newer",
+        (Some(a), Some(b)) if a < b => " │ existing is newer",
+        (Some(_), Some(_)) => " │ same age",
+        _ => "",
+    };
+    format!(
+        " source: {} │ existing: {}{newer}",
+        describe(conflict.src_stat),
+        describe(conflict.dest_stat)
+    )
+}
+
+fn format_time(time: Option<SystemTime>) -> String {
+    time.map(|m| {
+        let dt: DateTime<Local> = m.into();
+        dt.format("%Y-%m-%d %H:%M").to_string()
+    })
+    .unwrap_or_else(|| "—".to_string())
+}
+
 #[allow(dead_code)]
 fn entry_style(entry: &FileEntry) -> Style {
     // Fallback — callers with theme access should use entry_style_themed
@@ -490,7 +852,7 @@ fn entry_style(entry: &FileEntry) -> Style {
 }
 
 fn entry_style_themed(entry: &FileEntry, theme: &crate::theme::Theme) -> Style {
-    if entry.is_symlink {
+    let style = if entry.is_symlink {
         Style::default().fg(theme.symlink)
     } else if entry.is_dir {
         Style::default()
@@ -498,6 +860,11 @@ fn entry_style_themed(entry: &FileEntry, theme: &crate::theme::Theme) -> Style {
             .add_modifier(Modifier::BOLD)
     } else {
         Style::default().fg(theme.file)
+    };
+    if entry.git_status == Some(GitFileStatus::Ignored) {
+        style.add_modifier(Modifier::DIM)
+    } else {
+        style
     }
 }
 
@@ -600,4 +967,20 @@ mod tests {
         let style = entry_style(&entry);
         assert_eq!(style.fg, Some(Color::Cyan));
     }
+
+    #[test]
+    fn test_match_spans() {
+        let result = SearchResult {
+            path: std::path::PathBuf::from("a.rs"),
+            line_number: 1,
+            line_text: "    let count = count;  ".to_string(),
+            matches: vec![8..13, 16..21],
+        };
+        let highlight = Style::default().bg(Color::Yellow);
+        let spans = match_spans(&result, highlight);
+        let parts: Vec<_> = spans.iter().map(|s| s.content.as_ref()).collect();
+        assert_eq!(parts, ["let ", "count", " = ", "count", ";"]);
+        assert_eq!(spans[1].style, highlight);
+        assert_eq!(spans[2].style, Style::default());
+    }
 }
@@ -1,5 +1,14 @@
+use crate::file_ops;
+use crate::jobs::Progress;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Directory of velo's cache holding overwritten files
+pub const BACKUP_DIR: &str = "overwritten";
+
+/// Backups older than this are taken to be left over from a past session
+const BACKUP_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
 
 /// A completed file operation that can be undone
 #[derive(Debug, Clone)]
@@ -17,6 +26,29 @@ pub enum UndoAction {
     CreateFile { path: PathBuf },
     /// Directory was created at path — undo = remove
     CreateDir { path: PathBuf },
+    /// `action` replaced an existing `dest`, whose original content was
+    /// stashed at `backup` — undo = undo `action`, then restore the backup
+    Overwrite {
+        action: Box<UndoAction>,
+        dest: PathBuf,
+        backup: PathBuf,
+    },
+    /// An undone overwrite — redo = stash `dest` again, then replay `action`
+    Restored {
+        action: Box<UndoAction>,
+        dest: PathBuf,
+        backup: PathBuf,
+    },
+    /// `archive` was extracted into `dest`, creating `created` (parents
+    /// first) — undo = remove those, leaving anything added since. `dest`
+    /// is where the entry paths start, above a lifted single root.
+    Extract {
+        archive: PathBuf,
+        dest: PathBuf,
+        created: Vec<PathBuf>,
+    },
+    /// An undone extraction — redo = extract `archive` into `dest` again
+    Unextracted { archive: PathBuf, dest: PathBuf },
 }
 
 impl UndoAction {
@@ -39,36 +71,118 @@ impl UndoAction {
             }
             Self::CreateFile { path } => format!("Create {}", path.display()),
             Self::CreateDir { path } => format!("Create dir {}", path.display()),
+            Self::Overwrite { action, .. } | Self::Restored { action, .. } => {
+                format!("{} (overwrite)", action.description())
+            }
+            Self::Extract { archive, dest, .. } | Self::Unextracted { archive, dest } => {
+                let name = archive
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                format!("Extract {name} → {}", dest.display())
+            }
         }
     }
 }
 
+/// Move an about-to-be-overwritten `path` into `backup_dir` under a
+/// unique name and return the new location. Pair with [`record_overwrite`].
+pub fn stash(backup_dir: &Path, path: &Path) -> Result<PathBuf, String> {
+    fs::create_dir_all(backup_dir)
+        .map_err(|e| format!("Failed to create {}: {e}", backup_dir.display()))?;
+    let nanos = now_nanos();
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let backup = backup_dir.join(format!("{nanos}-{name}"));
+    file_ops::move_path(path, &backup)?;
+    Ok(backup)
+}
+
+fn now_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default()
+}
+
+/// Remove backups [`stash`]ed more than `max_age` ago, left by sessions
+/// that ended. Newer ones may belong to another running velo.
+pub fn prune_backups(backup_dir: &Path, max_age: Duration) {
+    let Ok(entries) = fs::read_dir(backup_dir) else {
+        return;
+    };
+    let now = now_nanos();
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let stashed = name
+            .split_once('-')
+            .and_then(|(nanos, _)| nanos.parse::<u128>().ok());
+        if stashed.is_some_and(|t| now.saturating_sub(t) > max_age.as_nanos()) {
+            let _ = file_ops::remove_path(&entry.path());
+        }
+    }
+}
+
+/// Delete the backup an action holds, once it can no longer be undone
+fn discard_backup(action: &UndoAction) {
+    if let UndoAction::Overwrite { backup, .. } = action {
+        let _ = file_ops::remove_path(backup);
+    }
+}
+
+/// Put a [`stash`]ed `backup` back at `path`, first clearing whatever a
+/// failed or cancelled operation left there
+pub fn unstash(backup: &Path, path: &Path) -> Result<(), String> {
+    if path.symlink_metadata().is_ok() {
+        file_ops::remove_path(path)
+            .map_err(|e| format!("Failed to remove {}: {e}", path.display()))?;
+    }
+    file_ops::move_path(backup, path)
+}
+
 /// Undo/redo stack
 #[derive(Debug, Default)]
 pub struct UndoStack {
     undo: Vec<UndoAction>,
     redo: Vec<UndoAction>,
     max_size: usize,
+    /// Where overwritten files are kept so an overwrite can be undone
+    backup_dir: PathBuf,
 }
 
 impl UndoStack {
-    pub fn new() -> Self {
+    /// A stack keeping overwritten files in `backup_dir`
+    pub fn new(backup_dir: PathBuf) -> Self {
         Self {
             undo: Vec::new(),
             redo: Vec::new(),
             max_size: 100,
+            backup_dir,
         }
     }
 
+    pub fn backup_dir(&self) -> &Path {
+        &self.backup_dir
+    }
+
     /// Record a completed action (clears redo stack)
     pub fn push(&mut self, action: UndoAction) {
         self.undo.push(action);
-        self.redo.clear();
+        for dropped in self.redo.drain(..) {
+            discard_backup(&dropped);
+        }
         if self.undo.len() > self.max_size {
-            self.undo.remove(0);
+            discard_backup(&self.undo.remove(0));
         }
     }
 
+    /// Remove backups of earlier sessions
+    pub fn prune_backups(&self) {
+        prune_backups(&self.backup_dir, BACKUP_MAX_AGE);
+    }
+
     /// Undo the last action. Returns description on success.
     pub fn undo(&mut self) -> Result<String, String> {
         let action = self.undo.pop().ok_or("Nothing to undo")?;
@@ -126,8 +240,8 @@ fn perform_undo(action: &UndoAction) -> Result<UndoAction, String> {
             Ok(UndoAction::CreateFile { path: dest.clone() })
         }
         UndoAction::Move { src, dest } => {
-            // Undo move = move it back
-            fs::rename(dest, src).map_err(|e| format!("Failed to move back: {e}"))?;
+            // Undo move = move it back (may cross filesystems, like the move itself)
+            file_ops::move_path(dest, src).map_err(|e| format!("Failed to move back: {e}"))?;
             Ok(UndoAction::Move {
                 src: dest.clone(),
                 dest: src.clone(),
@@ -152,6 +266,61 @@ fn perform_undo(action: &UndoAction) -> Result<UndoAction, String> {
             }
             Ok(UndoAction::CreateDir { path: path.clone() })
         }
+        UndoAction::Overwrite {
+            action,
+            dest,
+            backup,
+        } => {
+            let reverse = perform_undo(action)?;
+            file_ops::move_path(backup, dest)
+                .map_err(|e| format!("Failed to restore {}: {e}", dest.display()))?;
+            Ok(UndoAction::Restored {
+                action: Box::new(reverse),
+                dest: dest.clone(),
+                backup: backup.clone(),
+            })
+        }
+        UndoAction::Restored {
+            action,
+            dest,
+            backup,
+        } => {
+            file_ops::move_path(dest, backup)
+                .map_err(|e| format!("Failed to stash {}: {e}", dest.display()))?;
+            let reverse = perform_undo(action)?;
+            Ok(UndoAction::Overwrite {
+                action: Box::new(reverse),
+                dest: dest.clone(),
+                backup: backup.clone(),
+            })
+        }
+        UndoAction::Extract {
+            archive,
+            dest,
+            created,
+        } => {
+            // Children before parents; a directory that gained other
+            // files since is left alone
+            for path in created.iter().rev() {
+                let result = match path.symlink_metadata() {
+                    Ok(meta) if meta.is_dir() => match fs::remove_dir(path) {
+                        Err(e) if e.kind() == std::io::ErrorKind::DirectoryNotEmpty => Ok(()),
+                        other => other,
+                    },
+                    Ok(_) => fs::remove_file(path),
+                    Err(_) => Ok(()),
+                };
+                result.map_err(|e| format!("Failed to remove {}: {e}", path.display()))?;
+            }
+            Ok(UndoAction::Unextracted {
+                archive: archive.clone(),
+                dest: dest.clone(),
+            })
+        }
+        UndoAction::Unextracted { archive, dest } => {
+            let report = file_ops::extract_archive(archive, dest, &Progress::default())?;
+            Ok(record_extract(archive, &report))
+        }
     }
 }
 
@@ -170,6 +339,24 @@ pub fn record_move(src: &Path, dest: &Path) -> UndoAction {
     }
 }
 
+/// Helper: wrap an action that replaced a file stashed by [`UndoStack::stash`]
+pub fn record_overwrite(action: UndoAction, dest: &Path, backup: &Path) -> UndoAction {
+    UndoAction::Overwrite {
+        action: Box::new(action),
+        dest: dest.to_path_buf(),
+        backup: backup.to_path_buf(),
+    }
+}
+
+/// Helper: record an extraction, for removing what it created
+pub fn record_extract(archive: &Path, report: &file_ops::ExtractReport) -> UndoAction {
+    UndoAction::Extract {
+        archive: archive.to_path_buf(),
+        dest: report.into.clone(),
+        created: report.created.clone(),
+    }
+}
+
 /// Helper: record a rename
 pub fn record_rename(old_path: &Path, new_path: &Path) -> UndoAction {
     UndoAction::Rename {
@@ -197,9 +384,14 @@ mod tests {
     use super::*;
     use tempfile::TempDir;
 
+    /// A stack for actions that never back anything up
+    fn stack() -> UndoStack {
+        UndoStack::new(PathBuf::from("unused-backups"))
+    }
+
     #[test]
     fn test_undo_stack_push_and_count() {
-        let mut stack = UndoStack::new();
+        let mut stack = stack();
         assert_eq!(stack.undo_count(), 0);
         assert!(!stack.can_undo());
         stack.push(record_create_file(Path::new("/tmp/x")));
@@ -209,7 +401,7 @@ mod tests {
 
     #[test]
     fn test_undo_clears_redo() {
-        let mut stack = UndoStack::new();
+        let mut stack = stack();
         // We can't easily test full undo/redo without real files,
         // but we can test the redo-clearing behavior
         stack.push(record_create_file(Path::new("/tmp/x")));
@@ -228,7 +420,7 @@ mod tests {
         let new = tmp.path().join("new.txt");
         fs::write(&new, "data").unwrap();
 
-        let mut stack = UndoStack::new();
+        let mut stack = stack();
         stack.push(record_rename(&old, &new));
         let result = stack.undo();
         assert!(result.is_ok());
@@ -243,7 +435,7 @@ mod tests {
         let path = tmp.path().join("created.txt");
         fs::write(&path, "").unwrap();
 
-        let mut stack = UndoStack::new();
+        let mut stack = stack();
         stack.push(record_create_file(&path));
         let result = stack.undo();
         assert!(result.is_ok());
@@ -256,7 +448,7 @@ mod tests {
         let path = tmp.path().join("newdir");
         fs::create_dir(&path).unwrap();
 
-        let mut stack = UndoStack::new();
+        let mut stack = stack();
         stack.push(record_create_dir(&path));
         let result = stack.undo();
         assert!(result.is_ok());
@@ -270,7 +462,7 @@ mod tests {
         let dest = tmp.path().join("dest.txt");
         fs::write(&dest, "moved").unwrap();
 
-        let mut stack = UndoStack::new();
+        let mut stack = stack();
         stack.push(record_move(&src, &dest));
         let result = stack.undo();
         assert!(result.is_ok());
@@ -284,7 +476,7 @@ mod tests {
         let dest = tmp.path().join("copy.txt");
         fs::write(&dest, "copied").unwrap();
 
-        let mut stack = UndoStack::new();
+        let mut stack = stack();
         stack.push(record_copy(&dest));
         let result = stack.undo();
         assert!(result.is_ok());
@@ -298,7 +490,7 @@ mod tests {
         let new = tmp.path().join("new.txt");
         fs::write(&new, "data").unwrap();
 
-        let mut stack = UndoStack::new();
+        let mut stack = stack();
         stack.push(record_rename(&old, &new));
         stack.undo().unwrap();
         assert!(old.exists());
@@ -309,21 +501,99 @@ mod tests {
         assert!(!old.exists());
     }
 
+    #[test]
+    fn test_undo_overwrite_restores_original() {
+        let tmp = TempDir::new().unwrap();
+        let mut stack = UndoStack::new(tmp.path().join("backup"));
+        let src = tmp.path().join("src.txt");
+        let dest_dir = tmp.path().join("dest");
+        fs::create_dir(&dest_dir).unwrap();
+        let dest = dest_dir.join("src.txt");
+        fs::write(&src, "new").unwrap();
+        fs::write(&dest, "old").unwrap();
+
+        // Move src over dest the way paste does it
+        let backup = stash(stack.backup_dir(), &dest).unwrap();
+        assert!(!dest.exists());
+        file_ops::move_path(&src, &dest).unwrap();
+        stack.push(record_overwrite(record_move(&src, &dest), &dest, &backup));
+
+        stack.undo().unwrap();
+        assert_eq!(fs::read_to_string(&src).unwrap(), "new");
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "old");
+        assert!(!backup.exists());
+
+        stack.redo().unwrap();
+        assert!(!src.exists());
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "new");
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "old");
+    }
+
+    #[test]
+    fn test_unstash_replaces_partial_copy() {
+        let tmp = TempDir::new().unwrap();
+        let dest = tmp.path().join("dir");
+        fs::create_dir(&dest).unwrap();
+        fs::write(dest.join("original.txt"), "mine").unwrap();
+        let backup = stash(&tmp.path().join("backup"), &dest).unwrap();
+        // What an interrupted directory copy leaves behind
+        fs::create_dir_all(dest.join("half")).unwrap();
+        fs::write(dest.join("half/copied.txt"), "new").unwrap();
+
+        unstash(&backup, &dest).unwrap();
+        assert_eq!(
+            fs::read_to_string(dest.join("original.txt")).unwrap(),
+            "mine"
+        );
+        assert!(!dest.join("half").exists());
+        assert!(!backup.exists());
+        assert!(unstash(&backup, &dest).is_err());
+    }
+
+    #[test]
+    fn test_backups_removed_with_their_action() {
+        let tmp = TempDir::new().unwrap();
+        let backup_dir = tmp.path().join("backup");
+        let mut stack = UndoStack::new(backup_dir.clone());
+        let dest = tmp.path().join("a.txt");
+        fs::write(&dest, "old").unwrap();
+        let backup = stash(&backup_dir, &dest).unwrap();
+        fs::write(&dest, "new").unwrap();
+        stack.push(record_overwrite(record_copy(&dest), &dest, &backup));
+        for i in 0..stack.max_size {
+            assert!(backup.exists());
+            stack.push(record_create_file(&PathBuf::from(format!("/tmp/{i}"))));
+        }
+        assert!(!backup.exists());
+    }
+
+    #[test]
+    fn test_prune_backups() {
+        let tmp = TempDir::new().unwrap();
+        let stale = tmp.path().join("1000-old.txt");
+        fs::create_dir_all(stale.join("tree")).unwrap();
+        fs::write(tmp.path().join("fresh.txt"), "x").unwrap();
+        let fresh = stash(tmp.path(), &tmp.path().join("fresh.txt")).unwrap();
+        prune_backups(tmp.path(), Duration::from_secs(3600));
+        assert!(!stale.exists());
+        assert!(fresh.exists());
+    }
+
     #[test]
     fn test_nothing_to_undo() {
-        let mut stack = UndoStack::new();
+        let mut stack = stack();
         assert!(stack.undo().is_err());
     }
 
     #[test]
     fn test_nothing_to_redo() {
-        let mut stack = UndoStack::new();
+        let mut stack = stack();
         assert!(stack.redo().is_err());
     }
 
     #[test]
     fn test_max_size() {
-        let mut stack = UndoStack::new();
+        let mut stack = stack();
         for i in 0..150 {
             stack.push(record_create_file(&PathBuf::from(format!("/tmp/{i}"))));
         }
@@ -348,9 +618,71 @@ mod tests {
         assert!(a.description().contains("dir"));
     }
 
+    #[test]
+    fn test_undo_redo_extract() {
+        let tmp = TempDir::new().unwrap();
+        let src = tmp.path().join("pkg");
+        fs::create_dir_all(src.join("lib")).unwrap();
+        fs::write(src.join("lib/a.rs"), "a").unwrap();
+        fs::write(src.join("notes.txt"), "n").unwrap();
+        let archive = tmp.path().join("pkg.tar");
+        file_ops::compress_tar(
+            &[src.join("lib"), src.join("notes.txt")],
+            &archive,
+            file_ops::Codec::None,
+            &Progress::default(),
+        )
+        .unwrap();
+
+        // Extract next to a file that was there before
+        let out = tmp.path().join("out");
+        fs::create_dir(&out).unwrap();
+        fs::write(out.join("keep.txt"), "mine").unwrap();
+        let report = file_ops::extract_archive(&archive, &out, &Progress::default()).unwrap();
+        let mut stack = stack();
+        stack.push(record_extract(&archive, &report));
+        assert!(stack.last_undo_desc().unwrap().contains("Extract pkg.tar"));
+        fs::write(out.join("lib/added.rs"), "later").unwrap();
+
+        stack.undo().unwrap();
+        assert!(!out.join("notes.txt").exists());
+        assert!(!out.join("lib/a.rs").exists());
+        // Files that were not extracted stay, and so does their directory
+        assert_eq!(fs::read_to_string(out.join("keep.txt")).unwrap(), "mine");
+        assert!(out.join("lib/added.rs").exists());
+
+        stack.redo().unwrap();
+        assert_eq!(fs::read_to_string(out.join("lib/a.rs")).unwrap(), "a");
+        stack.undo().unwrap();
+        assert!(!out.join("lib/a.rs").exists());
+        assert!(out.join("lib/added.rs").exists());
+
+        // A single root directory lifted out of its folder comes back in
+        // the same place
+        let single = tmp.path().join("proj.tar");
+        file_ops::compress_tar(
+            std::slice::from_ref(&src),
+            &single,
+            file_ops::Codec::None,
+            &Progress::default(),
+        )
+        .unwrap();
+        let report =
+            file_ops::extract_to_subfolder(&single, &out, "proj", &Progress::default()).unwrap();
+        assert_eq!(report.dest, out.join("pkg"));
+        stack.push(record_extract(&single, &report));
+        stack.undo().unwrap();
+        assert!(!out.join("pkg").exists());
+        stack.redo().unwrap();
+        assert_eq!(fs::read_to_string(out.join("pkg/lib/a.rs")).unwrap(), "a");
+        assert!(!out.join("pkg/pkg").exists());
+        stack.undo().unwrap();
+        assert!(!out.join("pkg").exists());
+    }
+
     #[test]
     fn test_last_undo_desc() {
-        let mut stack = UndoStack::new();
+        let mut stack = stack();
         assert!(stack.last_undo_desc().is_none());
         stack.push(record_create_file(Path::new("/tmp/x")));
         assert!(stack.last_undo_desc().is_some());